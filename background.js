@@ -10,6 +10,9 @@ let orderedGroups = [];
 // Last update timestamp for group state
 let lastGroupUpdate = 0;
 
+// Native messaging protocol version this extension speaks
+const PROTOCOL_VERSION = 1;
+
 // Connect to native messaging host
 function connectNativeHost() {
     console.log('Connecting to native host...');
@@ -37,6 +40,8 @@ function connectNativeHost() {
             console.log('Received from native host:', message);
             if (message.type === 'hover_result') {
                 handleHoverResult(message);
+            } else if (message.type === 'version') {
+                handleVersion(message);
             } else if (message.type === 'error') {
                 console.error('Native host error:', message.message);
             }
@@ -58,6 +63,9 @@ function connectNativeHost() {
             setTimeout(connectNativeHost, 5000);
         });
 
+        // Check the host speaks our protocol before relying on it
+        port.postMessage({ type: 'get_version', data: { protocol: PROTOCOL_VERSION } });
+
         // Initialize tab groups state
         updateTabGroups();
     } catch (error) {
@@ -66,6 +74,15 @@ function connectNativeHost() {
     }
 }
 
+// Warn when the installed host was built for another protocol version
+function handleVersion(message) {
+    const protocol = message.data && message.data.protocol;
+    if (protocol !== PROTOCOL_VERSION) {
+        console.warn(`Native host ${message.data && message.data.version} speaks protocol ${protocol}, ` +
+            `expected ${PROTOCOL_VERSION}; reinstall the native host`);
+    }
+}
+
 // Update ordered list of tab groups
 async function updateTabGroups() {
     try {
@@ -109,6 +126,9 @@ async function updateTabGroups() {
 function handleHoverResult(message) {
     const index = message.data.index;
     console.log('Received hover index:', index);
+    if (message.data.reason === 'NotBrowser') {
+        console.log('Cursor is not over a browser window');
+    }
     
     // Convert 1-based index to 0-based for array access
     const arrayIndex = index - 1;
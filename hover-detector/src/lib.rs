@@ -0,0 +1,6554 @@
+//! Tab group hover detection for Chromium-based browsers: finds the colored
+//! group pills on the tab strip of the browser window under the cursor and
+//! tells which one is hovered. The `hover-detector` executable is a thin CLI
+//! over this; [`detect_hovered_group`] is the entry point for linking it,
+//! and [`scan_image`] runs the same scan over a saved capture.
+
+// `effective_config_json` lists every setting in one `json!` invocation.
+#![recursion_limit = "256"]
+
+use anyhow::Result;
+use chrono::Local;
+use image::{ImageBuffer, ImageFormat, Rgb, RgbImage, RgbaImage};
+use log::{error, Level};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    env,
+    fs::OpenOptions,
+    io::{self, BufRead, Cursor, Write},
+    thread,
+    time::{Duration, Instant},
+};
+use windows::{
+    core::{PCWSTR, PWSTR},
+    Win32::Foundation::{CloseHandle, ERROR_SUCCESS, HANDLE, HWND, POINT, RECT},
+    Win32::Graphics::Dwm::{DwmGetWindowAttribute, DWMWA_CAPTION_COLOR, DWMWA_COLOR_DEFAULT, DWMWA_COLOR_NONE},
+    Win32::Graphics::Gdi::{GetDC, GetPixel, ReleaseDC},
+    Win32::System::DataExchange::{CloseClipboard, EmptyClipboard, OpenClipboard, SetClipboardData},
+    Win32::System::Memory::{GlobalAlloc, GlobalFree, GlobalLock, GlobalUnlock, GMEM_MOVEABLE},
+    Win32::System::Ole::CF_UNICODETEXT,
+    Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD},
+    Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+    },
+    Win32::UI::Accessibility::{HCF_HIGHCONTRASTON, HIGHCONTRASTW},
+    Win32::UI::ColorSystem::GetICMProfileW,
+    Win32::UI::WindowsAndMessaging::{
+        GA_ROOT,
+        GA_ROOTOWNER,
+        GW_OWNER,
+        GetAncestor,
+        GetCursorPos,
+        GetForegroundWindow,
+        GetWindow,
+        GetWindowLongW,
+        GetWindowRect,
+        GetWindowThreadProcessId,
+        GWL_STYLE,
+        WS_CAPTION,
+        WS_CHILD,
+        SM_CXPADDEDBORDER,
+        SM_CYCAPTION,
+        SM_CYFRAME,
+        SPI_GETHIGHCONTRAST,
+        SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS,
+        SystemParametersInfoW,
+        WindowFromPoint,
+    },
+    Win32::UI::HiDpi::{
+        GetDpiForSystem, GetDpiForWindow, GetSystemMetricsForDpi, SetProcessDpiAwareness,
+        PROCESS_PER_MONITOR_DPI_AWARE,
+    },
+};
+use xcap::Window;
+
+const VERTICAL_THRESHOLD: f64 = 60.0; // Maximum pixels from top of window, at 96 DPI
+const BASE_DPI: u32 = 96;
+const LOG_FILE: &str = "hover_detector.log";
+const SCREENSHOT_STATE_FILE: &str = "hover_detector_last_screenshot.txt";
+const TOAST_STATE_FILE: &str = "hover_detector_last_toast.txt";
+const TOAST_MIN_INTERVAL_MS_DEFAULT: u32 = 2000;
+const REFERENCE_FILE_DEFAULT: &str = "titlebar_reference.json";
+const COLORS_FILE: &str = "colors.json";
+const WINDOW_ENUM_ATTEMPTS: u32 = 3;
+const WINDOW_ENUM_RETRY_DELAY: Duration = Duration::from_millis(30);
+/// How close the detected cursor scale must be to a monitor-derived factor to
+/// be snapped to it on mixed-DPI setups.
+const MONITOR_SCALE_TOLERANCE: f64 = 0.05;
+const MOTION_SAMPLE_INTERVAL: Duration = Duration::from_millis(15);
+const MOTION_SETTLE_MS_DEFAULT: u32 = 250;
+const WINDOW_CACHE_MS_DEFAULT: u32 = 250;
+const TARGET_COLORS: &[u32] = &[0x779FF8, 0xE06AB7, 0xC78BD9, 0xB497FE, 0x5987B9, 0x65B1B6, 0xD59367, 0xBCA359, 0x83817E];
+const TARGET_COLORS_ALT: &[u32] = &[0x7BA0FD, 0xDB6ABA, 0xC48BDD, 0xB298FF, 0x5E87BC, 0x6DB1B7, 0xD19262, 0xBAA351, 0x83817E];
+const BACKGROUND_COLOR: u32 = 0x202020;
+// Group colors and title bar of the light browser theme (Chromium's light tab
+// group palette: grey, blue, red, yellow, green, pink, purple, cyan, orange).
+const TARGET_COLORS_LIGHT: &[u32] = &[0x5F6368, 0x1A73E8, 0xD93025, 0xF9AB00, 0x1E8E3E, 0xD01884, 0x9334E6, 0x007B83, 0xFA903E];
+const BACKGROUND_COLOR_LIGHT: u32 = 0xF7F7F7;
+// Chrome's dark-theme group colors (grey, blue, red, yellow, green, pink,
+// purple, cyan, orange); its light theme uses the Chromium light palette.
+const TARGET_COLORS_CHROME: &[u32] = &[0xBDC1C6, 0x8AB4F8, 0xF28B82, 0xFDD663, 0x81C995, 0xFF8BCB, 0xD7AEFB, 0x78D9EC, 0xFCAD70];
+
+/// Built-in group colors of one browser, per theme.
+struct BrowserPalette {
+    /// Lowercase substring of the window's app name that selects this entry.
+    name: &'static str,
+    dark: &'static [&'static [u32]],
+    light: &'static [&'static [u32]],
+}
+
+/// Palettes by browser, first match wins; the first entry (Edge) also covers
+/// any browser without one of its own. Another Chromium browser with its own
+/// colors is one more entry here.
+const BROWSER_PALETTES: &[BrowserPalette] = &[
+    BrowserPalette { name: "edge", dark: &[TARGET_COLORS, TARGET_COLORS_ALT], light: &[TARGET_COLORS_LIGHT] },
+    BrowserPalette { name: "chrome", dark: &[TARGET_COLORS_CHROME], light: &[TARGET_COLORS_LIGHT] },
+];
+
+/// The palette entry for a window's `app_name`, or the one named by
+/// `TABGROUP_HOVER_BROWSER_PALETTE` (for saved captures, which have no app).
+fn browser_palette(app_name: &str) -> &'static BrowserPalette {
+    let forced = env::var("TABGROUP_HOVER_BROWSER_PALETTE").map(|raw| raw.trim().to_lowercase());
+    let key = forced.unwrap_or_else(|_| app_name.to_lowercase());
+    BROWSER_PALETTES
+        .iter()
+        .find(|palette| key.contains(palette.name))
+        .unwrap_or(&BROWSER_PALETTES[0])
+}
+const PROXIMITY_RADIUS: i32 = 2; // Radius in pixels (at 96 DPI) to check around cursor for target colors
+const TARGET_COLOR_TOLERANCE: u8 = 8;
+const BACKGROUND_COLOR_TOLERANCE: u8 = 6;
+const MAX_BACKGROUND_COLORS: usize = 6;
+const MIN_GROUP_WIDTH_DEFAULT: u32 = 24;
+const COLLAPSED_CHIP_MIN_WIDTH_DEFAULT: u32 = 8;
+const MIN_BACKGROUND_GAP_WIDTH_DEFAULT: u32 = 8;
+const MIN_START_RUN_DEFAULT: u32 = 6;
+const UNKNOWN_SEPARATOR_WIDTH_DEFAULT: u32 = 0;
+const SEPARATOR_COLOR_TOLERANCE: u8 = 3;
+const TOP_BORDER_OFFSET_DEFAULT: u32 = 3;
+const UNGROUPED_BLIP_WIDTH_DEFAULT: u32 = 0;
+const UNGROUPED_CONTEXT_WIDTH: u32 = 32;
+const UNGROUPED_NEUTRAL_RATIO: f64 = 0.75;
+const TAB_EDGE_DISTANCE: u32 = 48;
+const TAB_EDGE_CLUSTER_GAP: f64 = 8.0; // At 96 DPI
+const CAPTION_BUTTONS_WIDTH: f64 = 138.0; // Minimize, maximize and close at 96 DPI
+const VERTICAL_PANE_WIDTH_DEFAULT: u32 = 250; // Expanded vertical tab pane, at 96 DPI
+const VERTICAL_SCAN_X_DEFAULT: u32 = 12; // Group color column in the vertical tab pane, at 96 DPI
+const BG_SAMPLE_WIDTH_DEFAULT: u32 = 24; // Empty strip left of the caption buttons, at 96 DPI
+const BG_SAMPLE_MIN_SHARE: f64 = 0.8;
+// Below this share of background pixels on the scan line, something covers the strip
+const MIN_BACKGROUND_SHARE_DEFAULT: f64 = 0.02;
+// At or above this share the scan line is plain background: tabs draw icons and
+// titles on it, so only a frame captured mid-repaint looks like that
+const BLANK_STRIP_SHARE: f64 = 0.99;
+const BLANK_RETRIES_DEFAULT: u32 = 1;
+const BLANK_RETRY_DELAY_MS_DEFAULT: u32 = 30;
+// Group pill heights (at 96 DPI) separating Edge's UI density settings
+const DENSITY_COMPACT_MAX_BAND: f64 = 18.0;
+const DENSITY_DEFAULT_MAX_BAND: f64 = 24.0;
+const SCAN_ROW_SPACING_DEFAULT: u32 = 4;
+const COLLAPSED_MAX_RATIO_DEFAULT: f64 = 3.0;
+const TAB_ROW_MIN_HEIGHT: f64 = 12.0; // At 96 DPI; thinner content bands are borders, not tab rows
+const TAB_ROW_MIN_GAP: f64 = 2.0; // At 96 DPI
+const TAB_ROW_CONTENT_PIXELS: usize = 4;
+
+thread_local! {
+    /// Verbose logging for the library call running on this thread, from
+    /// [`Config::verbose`], on top of `TABGROUP_HOVER_DETECTOR_VERBOSE`.
+    static VERBOSE_CALL: Cell<bool> = const { Cell::new(false) };
+}
+
+fn is_verbose() -> bool {
+    VERBOSE_CALL.with(Cell::get) || env::var("TABGROUP_HOVER_DETECTOR_VERBOSE").is_ok()
+}
+
+/// Runs `f` with verbose logging turned on for this thread when `verbose` is
+/// set, and back to what it was afterwards.
+fn with_verbose<T>(verbose: bool, f: impl FnOnce() -> T) -> T {
+    let previous = VERBOSE_CALL.with(|call| call.replace(verbose || call.get()));
+    let result = f();
+    VERBOSE_CALL.with(|call| call.set(previous));
+    result
+}
+
+fn unix_millis_now() -> u64 {
+    Local::now().timestamp_millis().max(0) as u64
+}
+
+fn log_to_file(msg: &str) -> Result<()> {
+    if !is_verbose() {
+        return Ok(());
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(LOG_FILE)?;
+    writeln!(file, "[{}] {}", Local::now().format("%Y-%m-%d %H:%M:%S"), msg)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Caption height plus the sizing frame at `dpi`, or `None` if the metrics
+/// are unavailable.
+fn caption_height(dpi: u32) -> Option<u32> {
+    let (caption, frame, padding) = unsafe {
+        (
+            GetSystemMetricsForDpi(SM_CYCAPTION, dpi),
+            GetSystemMetricsForDpi(SM_CYFRAME, dpi),
+            GetSystemMetricsForDpi(SM_CXPADDEDBORDER, dpi),
+        )
+    };
+    if caption <= 0 {
+        return None;
+    }
+    Some((caption + frame.max(0) + padding.max(0)) as u32)
+}
+
+fn clamp_threshold(threshold: u32, caption_height: Option<u32>) -> u32 {
+    match caption_height {
+        Some(height) if height > 0 => threshold.min(height),
+        _ => threshold,
+    }
+}
+
+/// Height of the strip scanned for tab groups at `dpi`, from
+/// `TABGROUP_HOVER_VERTICAL_THRESHOLD` (96-DPI pixels) or `VERTICAL_THRESHOLD`.
+/// With `TABGROUP_HOVER_CLAMP_TO_CAPTION` set it never exceeds the system
+/// caption height, so compact windows are not scanned into page content.
+fn vertical_threshold(dpi: u32) -> u32 {
+    let threshold = scale_for_dpi(
+        parse_f64_from_env("TABGROUP_HOVER_VERTICAL_THRESHOLD").unwrap_or(VERTICAL_THRESHOLD),
+        dpi,
+    );
+    if !parse_bool_from_env("TABGROUP_HOVER_CLAMP_TO_CAPTION", false) {
+        return threshold;
+    }
+    clamp_threshold(threshold, caption_height(dpi))
+}
+
+/// Converts a length in 96-DPI pixels to physical pixels at `dpi`.
+fn scale_for_dpi(value: f64, dpi: u32) -> u32 {
+    (value * dpi as f64 / BASE_DPI as f64).round() as u32
+}
+
+/// Effective DPI of the window, from `TABGROUP_HOVER_DPI` if set, otherwise
+/// `GetDpiForWindow`. Falls back to 96 for injected frames without a window.
+fn window_dpi(window: &WindowInfo) -> (u32, &'static str) {
+    if let Some(dpi) = parse_f64_from_env("TABGROUP_HOVER_DPI") {
+        return (dpi.round() as u32, "override");
+    }
+    if window.is_offline() {
+        return (BASE_DPI, "default");
+    }
+    match unsafe { GetDpiForWindow(HWND(window.id as isize)) } {
+        0 => (BASE_DPI, "default"),
+        dpi => (dpi, "window"),
+    }
+}
+
+/// The system DPI (the primary monitor's at sign-in), which the window DPI
+/// is compared with to map the cursor. `None` for injected frames without a
+/// window, or when it cannot be queried.
+fn system_dpi(window: &WindowInfo) -> Option<u32> {
+    if window.is_offline() {
+        return None;
+    }
+    match unsafe { GetDpiForSystem() } {
+        0 => None,
+        dpi => Some(dpi),
+    }
+}
+
+/// System-wide color transform applied by Windows accessibility settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ColorTransform {
+    None,
+    Inverted,
+    Grayscale,
+    GrayscaleInverted,
+    /// One of the color-blindness filters, by `FilterType` value.
+    ColorBlindFilter(u32),
+    HighContrast,
+}
+
+fn read_hkcu_dword(subkey: &str, value: &str) -> Option<u32> {
+    let subkey: Vec<u16> = subkey.encode_utf16().chain(std::iter::once(0)).collect();
+    let value: Vec<u16> = value.encode_utf16().chain(std::iter::once(0)).collect();
+    let mut data = 0u32;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            PCWSTR(subkey.as_ptr()),
+            PCWSTR(value.as_ptr()),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut data as *mut u32 as *mut std::ffi::c_void),
+            Some(&mut size),
+        )
+    };
+    (status == ERROR_SUCCESS).then_some(data)
+}
+
+fn color_transform_from_filter(active: bool, filter_type: u32) -> ColorTransform {
+    match (active, filter_type) {
+        (false, _) => ColorTransform::None,
+        (true, 0) => ColorTransform::Grayscale,
+        (true, 1) => ColorTransform::Inverted,
+        (true, 2) => ColorTransform::GrayscaleInverted,
+        (true, other) => ColorTransform::ColorBlindFilter(other),
+    }
+}
+
+fn active_color_transform() -> ColorTransform {
+    let mut high_contrast = HIGHCONTRASTW {
+        cbSize: std::mem::size_of::<HIGHCONTRASTW>() as u32,
+        ..Default::default()
+    };
+    let queried = unsafe {
+        SystemParametersInfoW(
+            SPI_GETHIGHCONTRAST,
+            high_contrast.cbSize,
+            Some(&mut high_contrast as *mut HIGHCONTRASTW as *mut std::ffi::c_void),
+            SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+        )
+    };
+    if queried.as_bool() && (high_contrast.dwFlags.0 & HCF_HIGHCONTRASTON.0) != 0 {
+        return ColorTransform::HighContrast;
+    }
+
+    let active = read_hkcu_dword("Software\\Microsoft\\ColorFiltering", "Active").unwrap_or(0) != 0;
+    let filter_type = read_hkcu_dword("Software\\Microsoft\\ColorFiltering", "FilterType").unwrap_or(0);
+    color_transform_from_filter(active, filter_type)
+}
+
+fn invert_colors(img: &mut RgbaImage) {
+    for pixel in img.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        pixel.0 = [255 - r, 255 - g, 255 - b, a];
+    }
+}
+
+/// Reverts `transform` on `capture` where possible: an inverted filter is
+/// inverted back when `undo` is set. Returns whether a transform is still in
+/// effect, so a miss can be blamed on it.
+fn undo_color_transform(capture: &mut RgbaImage, transform: ColorTransform, undo: bool) -> Result<bool> {
+    match transform {
+        ColorTransform::None => Ok(false),
+        ColorTransform::Inverted if undo => {
+            log_to_file("Inverted color filter active, inverting capture back before matching")?;
+            invert_colors(capture);
+            Ok(false)
+        }
+        other => {
+            log_to_file(&format!("Accessibility color transform active: {:?}", other))?;
+            Ok(true)
+        }
+    }
+}
+
+const EVENT_SOURCE_NAME: &str = "TabGroup Hover Detector";
+const EVENT_ID_DETECTION: u32 = 1000;
+const EVENT_ID_ERROR: u32 = 1001;
+
+/// Event Log entry type for a log level: error (1), warning (2) or information (4).
+fn event_type_for_level(level: Level) -> u16 {
+    match level {
+        Level::Error => 1,
+        Level::Warn => 2,
+        Level::Info | Level::Debug | Level::Trace => 4,
+    }
+}
+
+struct EventRecord {
+    event_type: u16,
+    event_id: u32,
+    text: String,
+}
+
+fn format_event(level: Level, message: &str) -> EventRecord {
+    EventRecord {
+        event_type: event_type_for_level(level),
+        event_id: if level == Level::Error { EVENT_ID_ERROR } else { EVENT_ID_DETECTION },
+        text: format!("[{}] pid={} {}", level, std::process::id(), message),
+    }
+}
+
+/// Sends a detection event to the Windows Event Log when
+/// `TABGROUP_HOVER_EVENT_LOG` is set. Writing requires the `eventlog` cargo
+/// feature; without it the event only goes to the verbose log file.
+pub fn trace_event(level: Level, message: &str) {
+    if !parse_bool_from_env("TABGROUP_HOVER_EVENT_LOG", false) {
+        return;
+    }
+    let record = format_event(level, message);
+    if let Err(e) = write_event(&record) {
+        let _ = log_to_file(&format!("Failed to write event log entry: {}", e));
+    }
+}
+
+#[cfg(feature = "eventlog")]
+fn write_event(record: &EventRecord) -> Result<()> {
+    use windows::Win32::System::EventLog::{
+        DeregisterEventSource, RegisterEventSourceW, ReportEventW, REPORT_EVENT_TYPE,
+    };
+
+    let source: Vec<u16> = EVENT_SOURCE_NAME.encode_utf16().chain(std::iter::once(0)).collect();
+    let text: Vec<u16> = record.text.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        let handle = RegisterEventSourceW(PCWSTR::null(), PCWSTR(source.as_ptr()))?;
+        let reported = ReportEventW(
+            handle,
+            REPORT_EVENT_TYPE(record.event_type),
+            0,
+            record.event_id,
+            None,
+            0,
+            Some(&[PCWSTR(text.as_ptr())]),
+            None,
+        );
+        DeregisterEventSource(handle);
+        reported.ok()?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "eventlog"))]
+fn write_event(record: &EventRecord) -> Result<()> {
+    log_to_file(&format!(
+        "Event log support not built in (source '{}', type {}, id {}): {}",
+        EVENT_SOURCE_NAME, record.event_type, record.event_id, record.text
+    ))
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Toast XML describing a detection: the hovered group and its color, or why
+/// there is none.
+fn toast_payload(detection: &Detection) -> String {
+    let headline = if detection.index > 0 {
+        match detection.cursor_color {
+            Some(hit) => format!("Group {} (#{:06X})", detection.index, hit.color),
+            None => format!("Group {}", detection.index),
+        }
+    } else {
+        "No group".to_string()
+    };
+    let detail = format!(
+        "{:?}, {} group{} found{}",
+        detection.reason,
+        detection.groups.len(),
+        if detection.groups.len() == 1 { "" } else { "s" },
+        if detection.partial { ", partial" } else { "" }
+    );
+    format!(
+        "<toast><visual><binding template=\"ToastGeneric\"><text>{}</text><text>{}</text></binding></visual><audio silent=\"true\"/></toast>",
+        escape_xml(&headline),
+        escape_xml(&detail)
+    )
+}
+
+/// Whether a toast for `signature` is due at `now_ms`, given the last toast
+/// shown (`previous`, as time and signature): never within `min_interval_ms`
+/// of it, and with `only_on_change` not for an unchanged result either.
+fn toast_due(previous: Option<(u64, &str)>, now_ms: u64, signature: &str, min_interval_ms: u64, only_on_change: bool) -> bool {
+    match previous {
+        None => true,
+        Some((at, last)) => {
+            now_ms.saturating_sub(at) >= min_interval_ms && !(only_on_change && last == signature)
+        }
+    }
+}
+
+/// With `TABGROUP_HOVER_TOAST` set to `1` (every detection) or `change` (only
+/// when the hovered group changes), shows the detection as a Windows toast
+/// for checking a setup without logs. Toasts are at most one per
+/// `TABGROUP_HOVER_TOAST_MIN_INTERVAL_MS`; the last one is kept in a file so
+/// one-shot runs share the throttle. Needs the `toast` cargo feature.
+pub fn notify_toast(detection: &Detection) {
+    let only_on_change = match env::var("TABGROUP_HOVER_TOAST").map(|raw| raw.trim().to_lowercase()).as_deref() {
+        Ok("change") => true,
+        Ok("1" | "true" | "yes" | "on" | "always") => false,
+        _ => return,
+    };
+    let signature = format!(
+        "{} {:?} {:?}",
+        detection.index,
+        detection.reason,
+        detection.cursor_color.map(|hit| hit.color)
+    );
+    let now_ms = unix_millis_now();
+    let state = std::fs::read_to_string(TOAST_STATE_FILE).ok();
+    let previous = state.as_deref().and_then(|raw| {
+        let (at, last) = raw.split_once(' ')?;
+        Some((at.parse().ok()?, last))
+    });
+    let min_interval = parse_u32_from_env("TABGROUP_HOVER_TOAST_MIN_INTERVAL_MS", TOAST_MIN_INTERVAL_MS_DEFAULT);
+    if !toast_due(previous, now_ms, &signature, min_interval as u64, only_on_change) {
+        return;
+    }
+    let shown = show_toast(&toast_payload(detection))
+        .and_then(|_| std::fs::write(TOAST_STATE_FILE, format!("{} {}", now_ms, signature)).map_err(Into::into));
+    if let Err(e) = shown {
+        let _ = log_to_file(&format!("Failed to show toast: {}", e));
+    }
+}
+
+#[cfg(feature = "toast")]
+fn show_toast(payload: &str) -> Result<()> {
+    use windows::{
+        core::HSTRING,
+        Data::Xml::Dom::XmlDocument,
+        UI::Notifications::{ToastNotification, ToastNotificationManager},
+    };
+    // Unpackaged executables cannot show toasts under their own id; borrow PowerShell's.
+    const TOAST_APP_ID_DEFAULT: &str = "{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\\WindowsPowerShell\\v1.0\\powershell.exe";
+
+    let app_id = env::var("TABGROUP_HOVER_TOAST_APP_ID").unwrap_or_else(|_| TOAST_APP_ID_DEFAULT.to_string());
+    let xml = XmlDocument::new()?;
+    xml.LoadXml(&HSTRING::from(payload))?;
+    let toast = ToastNotification::CreateToastNotification(&xml)?;
+    ToastNotificationManager::CreateToastNotifierWithId(&HSTRING::from(app_id))?.Show(&toast)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "toast"))]
+fn show_toast(payload: &str) -> Result<()> {
+    anyhow::bail!("toast support not built in (enable the `toast` feature): {}", payload)
+}
+
+fn get_cursor_pos() -> Result<POINT> {
+    let mut point = POINT::default();
+    unsafe {
+        GetCursorPos(&mut point).ok()?;
+    }
+    Ok(point)
+}
+
+fn color_distance(a: u32, b: u32) -> u32 {
+    let ar = ((a >> 16) & 0xFF) as i32;
+    let ag = ((a >> 8) & 0xFF) as i32;
+    let ab = (a & 0xFF) as i32;
+    let br = ((b >> 16) & 0xFF) as i32;
+    let bg = ((b >> 8) & 0xFF) as i32;
+    let bb = (b & 0xFF) as i32;
+    ((ar - br).abs() + (ag - bg).abs() + (ab - bb).abs()) as u32
+}
+
+/// Whether each channel of `candidate` is within `tolerance` of the same
+/// channel of `target`. Every group, background and separator comparison goes
+/// through this, so anti-aliased edges and slight blending still match.
+fn color_matches(candidate: u32, target: u32, tolerance: u8) -> bool {
+    [16, 8, 0].iter().all(|shift| {
+        let a = ((candidate >> shift) & 0xFF) as u8;
+        let b = ((target >> shift) & 0xFF) as u8;
+        a.abs_diff(b) <= tolerance
+    })
+}
+
+fn parse_hex_color(input: &str) -> Option<u32> {
+    let trimmed = input.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+    let normalized = trimmed
+        .strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix("0X"))
+        .or_else(|| trimmed.strip_prefix('#'))
+        .unwrap_or(trimmed);
+    if normalized.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(normalized, 16).ok()
+}
+
+fn parse_colors_from_env(var_name: &str) -> Vec<u32> {
+    let Ok(raw) = env::var(var_name) else {
+        return Vec::new();
+    };
+    raw.split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter_map(parse_hex_color)
+        .collect()
+}
+
+/// Tokens in a color env var that `parse_hex_color` rejects.
+fn invalid_color_tokens(var_name: &str) -> Vec<String> {
+    let Ok(raw) = env::var(var_name) else {
+        return Vec::new();
+    };
+    raw.split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter(|token| !token.trim().is_empty() && parse_hex_color(token).is_none())
+        .map(str::to_string)
+        .collect()
+}
+
+fn parse_u32_from_env(var_name: &str, default_value: u32) -> u32 {
+    env::var(var_name)
+        .ok()
+        .and_then(|raw| raw.trim().parse::<u32>().ok())
+        .unwrap_or(default_value)
+}
+
+/// A per-channel color tolerance from `var_name`, capped at 255.
+fn parse_tolerance_from_env(var_name: &str, default_value: u8) -> u8 {
+    parse_u32_from_env(var_name, default_value as u32).min(u8::MAX as u32) as u8
+}
+
+fn parse_bool_from_env(var_name: &str, default_value: bool) -> bool {
+    match env::var(var_name).map(|raw| raw.trim().to_lowercase()).as_deref() {
+        Ok("1" | "true" | "yes" | "on") => true,
+        Ok("0" | "false" | "no" | "off") => false,
+        _ => default_value,
+    }
+}
+
+fn parse_f64_from_env(var_name: &str) -> Option<f64> {
+    env::var(var_name)
+        .ok()
+        .and_then(|raw| raw.trim().parse::<f64>().ok())
+        .filter(|value| value.is_finite() && *value > 0.0)
+}
+
+/// Parses a `colors.json` palette: a JSON array of hex strings such as
+/// `"#EE5FB7"` or `"0xee5fb7"`. Every entry must be a valid 24-bit color and
+/// the list must not be empty.
+fn parse_colors_json(raw: &str) -> Result<Vec<u32>> {
+    let entries: Vec<String> = serde_json::from_str(raw)?;
+    if entries.is_empty() {
+        anyhow::bail!("color list is empty");
+    }
+    entries
+        .iter()
+        .map(|entry| {
+            parse_hex_color(entry).ok_or_else(|| anyhow::anyhow!("'{}' is not a #RRGGBB or 0xRRGGBB color", entry))
+        })
+        .collect()
+}
+
+fn colors_file_path() -> Option<std::path::PathBuf> {
+    Some(env::current_exe().ok()?.parent()?.join(COLORS_FILE))
+}
+
+/// User palette from `colors.json` next to the executable. `None` when the
+/// file is missing, or malformed (logged), so the built-in colors stay.
+fn load_colors_file() -> Option<Vec<u32>> {
+    load_colors_from(&colors_file_path()?)
+}
+
+fn load_colors_from(path: &std::path::Path) -> Option<Vec<u32>> {
+    let raw = std::fs::read_to_string(path).ok()?;
+    match parse_colors_json(&raw) {
+        Ok(colors) => Some(colors),
+        Err(e) => {
+            error!("Ignoring malformed {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Browser color scheme, which picks the built-in palette and the fallback
+/// background.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Theme {
+    Dark,
+    Light,
+}
+
+impl Theme {
+    /// `TABGROUP_HOVER_THEME`: `dark` or `light`; `None` for `auto` (the
+    /// default), which leaves it to [`Theme::detect`].
+    fn from_env() -> Option<Self> {
+        match env::var("TABGROUP_HOVER_THEME").map(|raw| raw.trim().to_lowercase()).as_deref() {
+            Ok("dark") => Some(Theme::Dark),
+            Ok("light") => Some(Theme::Light),
+            _ => None,
+        }
+    }
+
+    /// Guesses the theme from the frame at the top left of the capture, above
+    /// the tabs: light when the median luma there is above mid-gray.
+    fn detect(capture: &RgbaImage) -> Self {
+        let mut lumas: Vec<u32> = (1..4)
+            .flat_map(|y| (4..20).map(move |x| (x, y)))
+            .filter_map(|(x, y)| get_pixel_color(capture, x, y))
+            .map(color_luma)
+            .collect();
+        lumas.sort_unstable();
+        match lumas.get(lumas.len() / 2) {
+            Some(luma) if *luma > 128 => Theme::Light,
+            _ => Theme::Dark,
+        }
+    }
+
+    /// The configured theme, detected from `capture` in `auto` mode.
+    fn resolve(capture: &RgbaImage) -> Self {
+        Self::from_env().unwrap_or_else(|| Self::detect(capture))
+    }
+
+    fn background(self) -> u32 {
+        match self {
+            Theme::Dark => BACKGROUND_COLOR,
+            Theme::Light => BACKGROUND_COLOR_LIGHT,
+        }
+    }
+}
+
+/// `colors.json` if present, else the built-in palette of the browser named
+/// `app_name` for `theme`, plus `TABGROUP_HOVER_EXTRA_COLORS`.
+fn target_colors(theme: Theme, app_name: &str) -> Vec<u32> {
+    let mut colors = match load_colors_file() {
+        Some(colors) => colors,
+        None => {
+            let palette = browser_palette(app_name);
+            let sets = match theme {
+                Theme::Dark => palette.dark,
+                Theme::Light => palette.light,
+            };
+            sets.iter().flat_map(|set| set.iter().copied()).collect()
+        }
+    };
+    colors.extend(parse_colors_from_env("TABGROUP_HOVER_EXTRA_COLORS"));
+    colors
+}
+
+fn color_channel_spread(color: u32) -> u32 {
+    let r = (color >> 16) & 0xFF;
+    let g = (color >> 8) & 0xFF;
+    let b = color & 0xFF;
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    max - min
+}
+
+/// Perceived brightness (Rec. 601 luma), 0–255.
+fn color_luma(color: u32) -> u32 {
+    let r = (color >> 16) & 0xFF;
+    let g = (color >> 8) & 0xFF;
+    let b = color & 0xFF;
+    (299 * r + 587 * g + 114 * b) / 1000
+}
+
+fn color_max_channel(color: u32) -> u32 {
+    let r = (color >> 16) & 0xFF;
+    let g = (color >> 8) & 0xFF;
+    let b = color & 0xFF;
+    r.max(g).max(b)
+}
+
+/// Path of the ICC profile of the primary display, via the Windows color
+/// management API.
+fn display_color_profile() -> Option<String> {
+    unsafe {
+        let hdc = GetDC(HWND(0));
+        if hdc.is_invalid() {
+            return None;
+        }
+        let mut buffer = [0u16; 260];
+        let mut size = buffer.len() as u32;
+        let found = GetICMProfileW(hdc, &mut size, PWSTR(buffer.as_mut_ptr())).as_bool();
+        ReleaseDC(HWND(0), hdc);
+        if !found {
+            return None;
+        }
+        let len = buffer.iter().position(|c| *c == 0).unwrap_or(buffer.len());
+        Some(String::from_utf16_lossy(&buffer[..len]))
+    }
+}
+
+/// Whether a profile is the stock sRGB one, under which captured pixels are
+/// the colors the palette was taken from.
+fn is_srgb_profile(path: &str) -> bool {
+    let name = path.rsplit(['\\', '/']).next().unwrap_or(path);
+    name.to_lowercase().contains("srgb")
+}
+
+/// Group color tolerance (`TABGROUP_HOVER_COLOR_TOLERANCE`) for the active
+/// display profile. Non-sRGB profiles shift captured values, so
+/// `TABGROUP_HOVER_NON_SRGB_TOLERANCE` can widen it there.
+fn palette_tolerance(profile: Option<&str>) -> u8 {
+    let tolerance = parse_tolerance_from_env("TABGROUP_HOVER_COLOR_TOLERANCE", TARGET_COLOR_TOLERANCE);
+    match profile {
+        Some(profile) if !is_srgb_profile(profile) => {
+            parse_tolerance_from_env("TABGROUP_HOVER_NON_SRGB_TOLERANCE", tolerance)
+        }
+        _ => tolerance,
+    }
+}
+
+/// Background color tolerance: `TABGROUP_HOVER_BG_TOLERANCE`, or else the
+/// group color tolerance (`TABGROUP_HOVER_COLOR_TOLERANCE`, which the
+/// extension's `set_config` also sets) so one setting widens both matches.
+/// With neither set it is the tighter [`BACKGROUND_COLOR_TOLERANCE`].
+fn background_tolerance() -> u8 {
+    let tolerance = parse_tolerance_from_env("TABGROUP_HOVER_COLOR_TOLERANCE", BACKGROUND_COLOR_TOLERANCE);
+    parse_tolerance_from_env("TABGROUP_HOVER_BG_TOLERANCE", tolerance)
+}
+
+/// HSV saturation of a color, from 0.0 (gray) to 1.0.
+fn saturation(color: u32) -> f64 {
+    let r = (color >> 16) & 0xFF;
+    let g = (color >> 8) & 0xFF;
+    let b = color & 0xFF;
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    if max == 0 {
+        return 0.0;
+    }
+    (max - min) as f64 / max as f64
+}
+
+/// Intrinsic color of a pill drawn with opacity `alpha` over `backdrop`,
+/// undoing `captured = alpha * pill + (1 - alpha) * backdrop` per channel.
+fn unblend(color: u32, backdrop: u32, alpha: f64) -> u32 {
+    [16, 8, 0].iter().fold(0, |out, shift| {
+        let captured = ((color >> shift) & 0xFF) as f64;
+        let behind = ((backdrop >> shift) & 0xFF) as f64;
+        let channel = ((captured - (1.0 - alpha) * behind) / alpha).round().clamp(0.0, 255.0) as u32;
+        out | (channel << shift)
+    })
+}
+
+/// Channel bits kept per color in the cells of the palette lookup table: 5
+/// bits give 32768 cells of 8x8x8 colors.
+const PALETTE_CELL_BITS: u32 = 5;
+
+/// Cell reached by the tolerance boxes of several palette entries.
+const PALETTE_CELL_SHARED: u8 = u8::MAX;
+
+/// Group palette prepared for the per-pixel scan loop.
+///
+/// A table over quantized colors records, per cell, which entry's tolerance
+/// box reaches it: none, exactly one, or several. The bulk of a row
+/// (background, text, icons) is rejected with one lookup, and a pill pixel is
+/// checked against its one entry rather than the whole palette. With
+/// un-blending the check runs twice per pixel and is memoized per color,
+/// since a row repeats the same few colors.
+struct Palette {
+    colors: Vec<u32>,
+    /// `0` for no entry, `i + 1` for entry `i` alone, or [`PALETTE_CELL_SHARED`].
+    cells: Vec<u8>,
+    fuzzy_cache: RefCell<HashMap<u32, bool>>,
+    /// Pixels less saturated than this never match, so neutral noise cannot
+    /// land within tolerance of a desaturated entry. `0` disables the gate.
+    min_saturation: f64,
+    /// Entries that are meant to be near-neutral (the gray group color) and
+    /// still match pixels below `min_saturation`.
+    low_saturation: HashSet<u32>,
+    tolerance: u8,
+    /// Backdrop color and pill opacity for translucent (Mica/acrylic) themes;
+    /// a pixel that does not match as captured is retried un-blended.
+    unblend: Option<(u32, f64)>,
+}
+
+impl Palette {
+    fn new(colors: Vec<u32>) -> Self {
+        let cells = palette_cells(&colors, TARGET_COLOR_TOLERANCE);
+        Self {
+            colors,
+            cells,
+            fuzzy_cache: RefCell::new(HashMap::new()),
+            min_saturation: 0.0,
+            low_saturation: HashSet::new(),
+            tolerance: TARGET_COLOR_TOLERANCE,
+            unblend: None,
+        }
+    }
+
+    fn with_unblend(mut self, unblend: Option<(u32, f64)>) -> Self {
+        self.unblend = unblend;
+        self
+    }
+
+    fn with_tolerance(mut self, tolerance: u8) -> Self {
+        self.tolerance = tolerance;
+        self.cells = palette_cells(&self.colors, tolerance);
+        self
+    }
+
+    fn with_min_saturation(mut self, min_saturation: f64, low_saturation: &[u32]) -> Self {
+        self.min_saturation = min_saturation;
+        self.low_saturation = low_saturation.iter().copied().collect();
+        self
+    }
+
+    /// Target palette with the saturation gate from `TABGROUP_HOVER_MIN_SATURATION`
+    /// and exempt entries from `TABGROUP_HOVER_LOW_SATURATION_COLORS`, matched
+    /// with the tolerance for the display `profile`. With both
+    /// `TABGROUP_HOVER_UNBLEND_BACKDROP` and `TABGROUP_HOVER_UNBLEND_ALPHA`
+    /// set, translucent pills are un-blended before matching.
+    fn from_env(profile: Option<&str>, theme: Theme, app_name: &str) -> Self {
+        let unblend = parse_colors_from_env("TABGROUP_HOVER_UNBLEND_BACKDROP")
+            .first()
+            .copied()
+            .zip(parse_f64_from_env("TABGROUP_HOVER_UNBLEND_ALPHA").filter(|alpha| *alpha < 1.0));
+        Self::new(target_colors(theme, app_name))
+            .with_min_saturation(
+                parse_f64_from_env("TABGROUP_HOVER_MIN_SATURATION").unwrap_or(0.0),
+                &parse_colors_from_env("TABGROUP_HOVER_LOW_SATURATION_COLORS"),
+            )
+            .with_tolerance(palette_tolerance(profile))
+            .with_unblend(unblend)
+    }
+
+    fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    fn matches(&self, color: u32) -> bool {
+        if self.unblend.is_none() {
+            return self.matches_intrinsic(color);
+        }
+        *self
+            .fuzzy_cache
+            .borrow_mut()
+            .entry(color)
+            .or_insert_with(|| self.matches_uncached(color))
+    }
+
+    fn matches_uncached(&self, color: u32) -> bool {
+        self.matches_intrinsic(color)
+            || self
+                .unblend
+                .is_some_and(|(backdrop, alpha)| self.matches_intrinsic(unblend(color, backdrop, alpha)))
+    }
+
+    fn matches_intrinsic(&self, color: u32) -> bool {
+        let entry = self.cells[palette_cell(color)];
+        if entry == 0 {
+            return false;
+        }
+        let gated = self.min_saturation > 0.0 && saturation(color) < self.min_saturation;
+        let matches = |target: &u32| {
+            (!gated || self.low_saturation.contains(target)) && color_matches(color, *target, self.tolerance)
+        };
+        match entry {
+            PALETTE_CELL_SHARED => self.colors.iter().any(matches),
+            entry => matches(&self.colors[usize::from(entry) - 1]),
+        }
+    }
+}
+
+/// Index of the quantized cell holding `color`.
+fn palette_cell(color: u32) -> usize {
+    let shift = 8 - PALETTE_CELL_BITS;
+    let channel = |offset: u32| ((color >> (offset + shift)) & ((1 << PALETTE_CELL_BITS) - 1)) as usize;
+    (channel(16) << (2 * PALETTE_CELL_BITS)) | (channel(8) << PALETTE_CELL_BITS) | channel(0)
+}
+
+/// Lookup table of the entries whose `tolerance` box reaches each cell; see
+/// [`Palette`].
+fn palette_cells(colors: &[u32], tolerance: u8) -> Vec<u8> {
+    let mut cells = vec![0u8; 1 << (3 * PALETTE_CELL_BITS)];
+    let shift = 8 - PALETTE_CELL_BITS;
+    for (index, &color) in colors.iter().enumerate() {
+        let entry = u8::try_from(index + 1).unwrap_or(PALETTE_CELL_SHARED);
+        let range = |offset: u32| {
+            let channel = ((color >> offset) & 0xFF) as u8;
+            (channel.saturating_sub(tolerance) >> shift)..=(channel.saturating_add(tolerance) >> shift)
+        };
+        for r in range(16) {
+            for g in range(8) {
+                for b in range(0) {
+                    let cell = (usize::from(r) << (2 * PALETTE_CELL_BITS)) | (usize::from(g) << PALETTE_CELL_BITS) | usize::from(b);
+                    cells[cell] = match cells[cell] {
+                        0 => entry,
+                        _ => PALETTE_CELL_SHARED,
+                    };
+                }
+            }
+        }
+    }
+    cells
+}
+
+fn is_background_color(color: u32, background_candidates: &[u32], tolerance: u8) -> bool {
+    background_candidates
+        .iter()
+        .any(|candidate| color_matches(color, *candidate, tolerance))
+}
+
+/// What counts as background on the scan line: learned or configured
+/// candidate colors, or, once calibrated, the exact empty-titlebar pixel at
+/// each position, which also covers gradient and acrylic backgrounds.
+struct Background {
+    candidates: Vec<u32>,
+    reference: Option<Vec<u32>>,
+    /// [`background_tolerance`], so near-background noise does not end up
+    /// counted as group pixels or group boundaries.
+    tolerance: u8,
+}
+
+impl Background {
+    fn new(candidates: Vec<u32>, reference: Option<Vec<u32>>) -> Self {
+        Self {
+            candidates,
+            reference,
+            tolerance: background_tolerance(),
+        }
+    }
+
+    fn matches(&self, x: u32, color: u32) -> bool {
+        match self.reference.as_ref().and_then(|row| row.get(x as usize)) {
+            Some(reference) => color_matches(color, *reference, self.tolerance),
+            None => self.matches_candidate(color),
+        }
+    }
+
+    /// Position-independent check against the candidate colors only.
+    fn matches_candidate(&self, color: u32) -> bool {
+        is_background_color(color, &self.candidates, self.tolerance)
+    }
+}
+
+fn reference_file() -> String {
+    env::var("TABGROUP_HOVER_REFERENCE_FILE").unwrap_or_else(|_| REFERENCE_FILE_DEFAULT.to_string())
+}
+
+/// References are per browser and capture width, since the titlebar layout
+/// differs between both.
+fn reference_key(app_name: &str, width: u32) -> String {
+    format!("{}:{}", app_name.to_lowercase(), width)
+}
+
+/// Calibrated empty-titlebar row for this window, if one was recorded at the
+/// same scan line.
+fn load_reference_row(app_name: &str, width: u32, scan_y: u32) -> Option<Vec<u32>> {
+    let raw = std::fs::read_to_string(reference_file()).ok()?;
+    let references: serde_json::Value = serde_json::from_str(&raw).ok()?;
+    let entry = references.get(reference_key(app_name, width))?;
+    if entry["scan_y"].as_u64() != Some(scan_y as u64) {
+        return None;
+    }
+    entry["row"]
+        .as_array()?
+        .iter()
+        .map(|color| color.as_str().and_then(parse_hex_color))
+        .collect()
+}
+
+/// `--calibrate`: records the scan line of the browser window while it shows
+/// no groups, as the background reference for later detections.
+pub fn calibrate_reference() -> Result<()> {
+    let cursor = get_cursor_pos()?;
+    let windows = all_windows()?;
+    let window = resolve_browser_window(&windows, cursor)?;
+    let capture = capture_window(window)?;
+    let (dpi, _) = window_dpi(&WindowInfo::from_window(window));
+    let scan_y = ScanGeometry::for_dpi(dpi, GroupStyle::from_env()).scan_y;
+    let row: Vec<u32> = (0..capture.width())
+        .map(|x| get_pixel_color(&capture, x, scan_y).unwrap_or(BACKGROUND_COLOR))
+        .collect();
+
+    let path = reference_file();
+    let mut references = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|raw| serde_json::from_str::<serde_json::Value>(&raw).ok())
+        .filter(|value| value.is_object())
+        .unwrap_or_else(|| serde_json::json!({}));
+    let key = reference_key(window.app_name(), capture.width());
+    references[&key] = serde_json::json!({
+        "scan_y": scan_y,
+        "row": format_colors(&row),
+    });
+    std::fs::write(&path, serde_json::to_string(&references)?)?;
+    println!("Recorded {} px titlebar reference for '{}' in {}", row.len(), key, path);
+    Ok(())
+}
+
+/// RGB value of a DWM `COLORREF` (0x00BBGGRR), or `None` for the "not set"
+/// and "no color" markers.
+fn colorref_to_rgb(colorref: u32) -> Option<u32> {
+    if colorref == DWMWA_COLOR_DEFAULT || colorref == DWMWA_COLOR_NONE {
+        return None;
+    }
+    Some(swap_red_blue(colorref & 0xFFFFFF))
+}
+
+/// Caption color the browser set on its window through DWM, which newer
+/// Edge/Chrome builds keep in sync with the theme's frame color.
+fn window_theme_color(window_id: u32) -> Option<u32> {
+    if !parse_bool_from_env("TABGROUP_HOVER_USE_THEME_COLOR", true) {
+        return None;
+    }
+    caption_colorref(window_id).and_then(colorref_to_rgb)
+}
+
+fn caption_colorref(window_id: u32) -> Option<u32> {
+    let mut colorref = 0u32;
+    unsafe {
+        DwmGetWindowAttribute(
+            HWND(window_id as isize),
+            DWMWA_CAPTION_COLOR,
+            &mut colorref as *mut u32 as *mut _,
+            std::mem::size_of::<u32>() as u32,
+        )
+    }
+    .ok()?;
+    Some(colorref)
+}
+
+/// The color making up at least `min_share` of `colors`, `None` when no
+/// color is that dominant (or there are no colors).
+fn dominant_color(colors: impl IntoIterator<Item = u32>, min_share: f64) -> Option<u32> {
+    let mut counts: HashMap<u32, u32> = HashMap::new();
+    let mut total = 0;
+    for color in colors {
+        *counts.entry(color).or_insert(0) += 1;
+        total += 1;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .filter(|(_, count)| total > 0 && *count as f64 >= total as f64 * min_share)
+        .map(|(color, _)| color)
+}
+
+/// Scan-line x range next to the caption buttons, where the strip is empty
+/// unless the tabs fill the whole window width. `TABGROUP_HOVER_BG_SAMPLE_WIDTH`
+/// sets its width at 96 DPI (default 24, `0` disables sampling it).
+fn background_sample_area(width: u32, dpi: u32) -> std::ops::Range<u32> {
+    let sample_width = scale_for_dpi(
+        parse_u32_from_env("TABGROUP_HOVER_BG_SAMPLE_WIDTH", BG_SAMPLE_WIDTH_DEFAULT) as f64,
+        dpi,
+    );
+    let end = width.saturating_sub(scale_for_dpi(CAPTION_BUTTONS_WIDTH, dpi));
+    end.saturating_sub(sample_width)..end
+}
+
+/// Background colors for the scan `line`: `TABGROUP_HOVER_BG_COLORS` if set,
+/// else the window's `theme_color` if it reported one. Otherwise the color
+/// that clearly dominates the `empty_area` of the line (positions along it)
+/// comes first, whatever its tint, followed by the most common neutral colors
+/// sampled from the line that are dark (or light, for the light `theme`).
+fn resolve_background_candidates(
+    img: &RgbaImage,
+    line: &Sweep,
+    targets: &Palette,
+    theme_color: Option<u32>,
+    theme: Theme,
+    empty_area: std::ops::Range<u32>,
+) -> Vec<u32> {
+    let user_candidates = parse_colors_from_env("TABGROUP_HOVER_BG_COLORS");
+    if !user_candidates.is_empty() {
+        return user_candidates;
+    }
+    if let Some(theme_color) = theme_color {
+        return vec![theme_color];
+    }
+    let mut candidates = Vec::new();
+    // An ambiguous area (a tab reaching into it) is left to the line sampling.
+    let empty_color = dominant_color(empty_area.filter_map(|t| line.sample(img, t)), BG_SAMPLE_MIN_SHARE)
+        .filter(|color| !targets.matches(*color));
+    candidates.extend(empty_color);
+
+    let mut counts: HashMap<u32, u32> = HashMap::new();
+    for t in 0..line.length {
+        if let Some(color) = line.sample(img, t) {
+            if !targets.matches(color) {
+                *counts.entry(color).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let min_count = ((line.length as f64) * 0.005).max(6.0) as u32;
+    let mut sorted: Vec<(u32, u32)> = counts.into_iter().collect();
+    sorted.sort_by_key(|entry| std::cmp::Reverse(entry.1));
+
+    for (color, count) in sorted {
+        if count < min_count {
+            break;
+        }
+        let spread = color_channel_spread(color);
+        let shade_fits = match theme {
+            Theme::Dark => color_max_channel(color) <= 120,
+            Theme::Light => color_luma(color) >= 180,
+        };
+        if spread <= 28 && shade_fits && Some(color) != empty_color {
+            candidates.push(color);
+            if candidates.len() >= MAX_BACKGROUND_COLORS {
+                break;
+            }
+        }
+    }
+
+    if candidates.is_empty() {
+        candidates.push(theme.background());
+    }
+    candidates
+}
+
+/// Runs `enumerate` until it yields a non-empty list, up to `attempts` times.
+/// Enumeration can fail or come back empty while windows are being created
+/// or destroyed, e.g. right after the browser starts.
+fn enumerate_with_retry<T>(
+    attempts: u32,
+    delay: Duration,
+    mut enumerate: impl FnMut() -> Result<Vec<T>>,
+) -> Result<Vec<T>> {
+    let mut attempt = 1;
+    loop {
+        let outcome = enumerate();
+        let retry_reason = match &outcome {
+            Ok(items) if items.is_empty() => "empty result".to_string(),
+            Ok(_) => return outcome,
+            Err(e) => e.to_string(),
+        };
+        if attempt >= attempts {
+            return outcome;
+        }
+        log_to_file(&format!(
+            "Window enumeration attempt {}/{} failed ({}), retrying",
+            attempt, attempts, retry_reason
+        ))?;
+        thread::sleep(delay);
+        attempt += 1;
+    }
+}
+
+fn all_windows() -> Result<Vec<Window>> {
+    enumerate_with_retry(WINDOW_ENUM_ATTEMPTS, WINDOW_ENUM_RETRY_DELAY, || Ok(Window::all()?))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CaptureStrategy {
+    /// Capture the window itself, falling back to `ScreenCrop` if that fails.
+    Auto,
+    /// Capture the window itself only.
+    Window,
+    /// Capture the window's monitor and crop it to the window, for windows
+    /// that cannot be captured individually (protected or overlay content).
+    ScreenCrop,
+}
+
+impl CaptureStrategy {
+    fn from_env() -> Self {
+        match env::var("TABGROUP_HOVER_CAPTURE_STRATEGY")
+            .map(|raw| raw.trim().to_lowercase())
+            .as_deref()
+        {
+            Ok("window") => CaptureStrategy::Window,
+            Ok("screen_crop") => CaptureStrategy::ScreenCrop,
+            _ => CaptureStrategy::Auto,
+        }
+    }
+}
+
+/// Cuts the `window` rectangle (x, y, width, height) out of a capture of the
+/// monitor at `monitor` (same layout). Both rectangles are in the same screen
+/// units; the capture may be at a different pixel density, which the crop
+/// follows. Parts of the window off the monitor stay transparent so window
+/// pixels keep their window-relative positions.
+fn crop_window_from_screen(screen: &RgbaImage, monitor: (i32, i32, u32, u32), window: (i32, i32, u32, u32)) -> RgbaImage {
+    let scale_x = screen.width() as f64 / monitor.2.max(1) as f64;
+    let scale_y = screen.height() as f64 / monitor.3.max(1) as f64;
+    let left = ((window.0 - monitor.0) as f64 * scale_x).round() as i64;
+    let top = ((window.1 - monitor.1) as f64 * scale_y).round() as i64;
+    let width = (window.2 as f64 * scale_x).round() as u32;
+    let height = (window.3 as f64 * scale_y).round() as u32;
+    RgbaImage::from_fn(width, height, |x, y| {
+        let (sx, sy) = (left + x as i64, top + y as i64);
+        if sx >= 0 && sy >= 0 && (sx as u64) < screen.width() as u64 && (sy as u64) < screen.height() as u64 {
+            *screen.get_pixel(sx as u32, sy as u32)
+        } else {
+            image::Rgba([0, 0, 0, 0])
+        }
+    })
+}
+
+fn capture_screen_crop(window: &Window) -> Result<RgbaImage> {
+    let monitor = window.current_monitor();
+    let screen = monitor.capture_image()?;
+    Ok(crop_window_from_screen(
+        &screen,
+        (monitor.x(), monitor.y(), monitor.width(), monitor.height()),
+        (window.x(), window.y(), window.width(), window.height()),
+    ))
+}
+
+/// Captures `window` with the `TABGROUP_HOVER_CAPTURE_STRATEGY` in effect.
+fn capture_window(window: &Window) -> Result<RgbaImage> {
+    let strategy = CaptureStrategy::from_env();
+    if strategy == CaptureStrategy::ScreenCrop {
+        return capture_screen_crop(window);
+    }
+    let captured = window
+        .capture_image()
+        .map_err(anyhow::Error::from)
+        .and_then(|image| {
+            if image.width() == 0 || image.height() == 0 {
+                anyhow::bail!("window capture is empty");
+            }
+            Ok(image)
+        });
+    match captured {
+        Err(e) if strategy == CaptureStrategy::Auto => {
+            log_to_file(&format!("Window capture failed ({}), falling back to screen crop", e))?;
+            capture_screen_crop(window)
+        }
+        other => other,
+    }
+}
+
+/// Captures `window` and, for horizontal tabs, keeps only its top
+/// `strip_height` rows.
+fn capture_strip(window: &Window, strip_height: u32) -> Result<RgbaImage> {
+    let captured = capture_window(window)?;
+    let full_height = captured.height();
+    if Orientation::from_env() != Orientation::Horizontal {
+        return Ok(captured);
+    }
+    let image = crop_to_strip(captured, strip_height);
+    if image.height() < full_height {
+        log_to_file(&format!("Cropped {}px capture to the {}px tab strip", full_height, image.height()))?;
+    }
+    Ok(image)
+}
+
+/// The top of a window capture that the group scan can reach: full width,
+/// `strip_height` rows (every allowed tab row). Coordinates are unchanged
+/// since the crop is anchored at the top left. A custom sweep may run
+/// anywhere in the window, so it keeps the whole capture.
+fn crop_to_strip(image: RgbaImage, strip_height: u32) -> RgbaImage {
+    let custom_sweep = ["TABGROUP_HOVER_SWEEP_ORIGIN", "TABGROUP_HOVER_SWEEP_DIRECTION"]
+        .iter()
+        .any(|name| env::var_os(name).is_some());
+    if custom_sweep || strip_height == 0 || strip_height >= image.height() {
+        return image;
+    }
+    image::imageops::crop_imm(&image, 0, 0, image.width(), strip_height).to_image()
+}
+
+/// Chromium browsers with tab groups, matched as lowercase substrings of a
+/// window's app name or executable file name.
+const BROWSERS_DEFAULT: [&str; 6] = ["edge", "chrome", "chromium", "brave", "vivaldi", "opera"];
+
+/// `TABGROUP_HOVER_BROWSERS` (comma/space separated) if set and non-empty,
+/// else `BROWSERS_DEFAULT`.
+fn browser_names() -> Vec<String> {
+    parse_browser_names(&env::var("TABGROUP_HOVER_BROWSERS").unwrap_or_default())
+}
+
+fn parse_browser_names(raw: &str) -> Vec<String> {
+    let names: Vec<String> = raw
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|name| !name.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    if names.is_empty() {
+        BROWSERS_DEFAULT.iter().map(|name| name.to_string()).collect()
+    } else {
+        names
+    }
+}
+
+/// Lowercase file name of the executable of process `pid`, if it can be read.
+fn process_file_name(pid: u32) -> Option<String> {
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+        let mut buffer = [0u16; 1024];
+        let mut len = buffer.len() as u32;
+        let ok = QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(buffer.as_mut_ptr()), &mut len);
+        CloseHandle(process);
+        if !ok.as_bool() {
+            return None;
+        }
+        let path = String::from_utf16_lossy(&buffer[..len as usize]);
+        std::path::Path::new(&path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+    }
+}
+
+/// Whether `window` belongs to one of `browsers`. The app name is the
+/// executable's product description, so the executable's file name is
+/// checked too (`brave.exe`, `vivaldi.exe`) when that does not match.
+fn is_browser_window(window: &Window, browsers: &[String]) -> bool {
+    is_browser(window.app_name(), || process_file_name(window.pid()), browsers)
+}
+
+/// `is_browser_window` for an app name and a lazily read executable file name.
+fn is_browser(app_name: &str, process_file: impl FnOnce() -> Option<String>, browsers: &[String]) -> bool {
+    let app_name = app_name.to_lowercase();
+    if browsers.iter().any(|name| app_name.contains(name.as_str())) {
+        return true;
+    }
+    process_file().is_some_and(|file| browsers.iter().any(|name| file.contains(name.as_str())))
+}
+
+fn is_point_in_window(cursor: POINT, window: &Window) -> bool {
+    let left = window.x();
+    let top = window.y();
+    let right = left + window.width() as i32;
+    let bottom = top + window.height() as i32;
+
+    cursor.x >= left && cursor.x < right && cursor.y >= top && cursor.y < bottom
+}
+
+fn push_unique_handle(handles: &mut Vec<HWND>, hwnd: HWND) {
+    if hwnd.0 != 0 && !handles.iter().any(|h| h.0 == hwnd.0) {
+        handles.push(hwnd);
+    }
+}
+
+/// Whether a window style belongs to a child control rather than a frame:
+/// `WS_CHILD`, or no caption at all.
+fn looks_like_child(style: u32) -> bool {
+    style & WS_CHILD.0 != 0 || style & WS_CAPTION.0 != WS_CAPTION.0
+}
+
+/// Top-level frame for `hwnd`. The foreground or hit-tested handle can be a
+/// child control such as Edge's render widget, whose geometry is the content
+/// area, so scanning its "top" would miss the tab strip entirely.
+fn resolve_top_level(hwnd: HWND) -> HWND {
+    top_level_for(
+        hwnd,
+        |hwnd| unsafe { GetWindowLongW(hwnd, GWL_STYLE) } as u32,
+        |hwnd| unsafe { GetAncestor(hwnd, GA_ROOT) },
+    )
+}
+
+/// `resolve_top_level` over the given style and root ancestor lookups.
+fn top_level_for(hwnd: HWND, style: impl Fn(HWND) -> u32, root: impl Fn(HWND) -> HWND) -> HWND {
+    if !looks_like_child(style(hwnd)) {
+        return hwnd;
+    }
+    match root(hwnd) {
+        root if root.0 != 0 => root,
+        _ => hwnd,
+    }
+}
+
+fn add_handle_candidates(handles: &mut Vec<HWND>, start: HWND) {
+    if start.0 == 0 {
+        return;
+    }
+
+    push_unique_handle(handles, resolve_top_level(start));
+    push_unique_handle(handles, start);
+
+    unsafe {
+        push_unique_handle(handles, GetAncestor(start, GA_ROOTOWNER));
+        push_unique_handle(handles, GetAncestor(start, GA_ROOT));
+    }
+
+    let mut current = start;
+    for _ in 0..8 {
+        unsafe {
+            let owner = GetWindow(current, GW_OWNER);
+            if owner.0 == 0 || owner.0 == current.0 {
+                break;
+            }
+            push_unique_handle(handles, owner);
+            push_unique_handle(handles, GetAncestor(owner, GA_ROOTOWNER));
+            push_unique_handle(handles, GetAncestor(owner, GA_ROOT));
+            current = owner;
+        }
+    }
+}
+
+/// Whether one of `handles` is a browser popup: a browser-owned window with an
+/// empty title, as Edge's context menus and dropdowns are.
+fn is_browser_popup(windows: &[Window], handles: &[HWND]) -> bool {
+    let browsers = browser_names();
+    handles.iter().any(|hwnd| {
+        windows.iter().any(|w| {
+            w.id() as isize == hwnd.0
+                && w.title().is_empty()
+                && is_browser_window(w, &browsers)
+        })
+    })
+}
+
+/// The `PopupActive` result when `ignore_during_popup` is set and
+/// `popup_open` finds a browser popup; the popup is only looked for then.
+fn popup_detection(cursor: POINT, ignore_during_popup: bool, popup_open: impl FnOnce() -> bool) -> Option<Detection> {
+    (ignore_during_popup && popup_open()).then(|| Detection::empty(cursor).with_reason(Reason::PopupActive))
+}
+
+/// The browser window that `popup` (a menu or dropdown without an owner
+/// chain to follow) belongs to: among the titled windows of the same
+/// process, the first one containing the popup's top left corner, which is
+/// the frontmost since `Window::all` lists windows front to back. `None` when
+/// the process id can't be read or no window of it matches, leaving the
+/// spatial fallbacks.
+fn resolve_by_process<'a>(windows: &'a [Window], popup: HWND, browsers: &[String]) -> Option<&'a Window> {
+    if popup.0 == 0 {
+        return None;
+    }
+    let mut pid = 0u32;
+    let mut rect = RECT::default();
+    unsafe {
+        GetWindowThreadProcessId(popup, Some(&mut pid));
+        if pid == 0 || !GetWindowRect(popup, &mut rect).as_bool() {
+            return None;
+        }
+    }
+    let corner = POINT { x: rect.left, y: rect.top };
+    frontmost_process_window(
+        windows,
+        pid,
+        |w| (w.pid(), !w.title().is_empty(), is_point_in_window(corner, w)),
+        |w| is_browser_window(w, browsers),
+    )
+}
+
+/// The window picked by `resolve_by_process` from `windows` (front to back),
+/// given each window's process id, whether it has a title and whether it
+/// contains the popup's top left corner.
+fn frontmost_process_window<W>(
+    windows: &[W],
+    pid: u32,
+    describe: impl Fn(&W) -> (u32, bool, bool),
+    is_browser: impl Fn(&W) -> bool,
+) -> Option<&W> {
+    windows
+        .iter()
+        .filter(|w| matches!(describe(w), (window_pid, true, true) if window_pid == pid))
+        .find(|w| is_browser(w))
+}
+
+fn resolve_browser_window(windows: &[Window], cursor: POINT) -> Result<&Window> {
+    let browsers = browser_names();
+    let mut candidates = Vec::new();
+    unsafe {
+        add_handle_candidates(&mut candidates, WindowFromPoint(cursor));
+        add_handle_candidates(&mut candidates, GetForegroundWindow());
+    }
+
+    if is_verbose() {
+        let handles = candidates
+            .iter()
+            .map(|h| format!("{}", h.0))
+            .collect::<Vec<_>>()
+            .join(", ");
+        log_to_file(&format!("HWND candidates: [{}]", handles))?;
+    }
+
+    for hwnd in &candidates {
+        if let Some(window) = windows.iter().find(|w| w.id() as isize == hwnd.0) {
+            if !window.title().is_empty() && is_browser_window(window, &browsers) {
+                log_to_file(&format!(
+                    "Resolved browser via HWND chain: id={}, title='{}', app='{}'",
+                    window.id(),
+                    window.title(),
+                    window.app_name()
+                ))?;
+                return Ok(window);
+            }
+        }
+    }
+
+    if let Some(window) = resolve_by_process(windows, unsafe { WindowFromPoint(cursor) }, &browsers) {
+        log_to_file(&format!(
+            "Resolved browser via owning process: id={}, pid={}, title='{}', app='{}'",
+            window.id(),
+            window.pid(),
+            window.title(),
+            window.app_name()
+        ))?;
+        return Ok(window);
+    }
+
+    if let Some(window) = windows
+        .iter()
+        .filter(|w| !w.title().is_empty())
+        .filter(|w| is_point_in_window(cursor, w))
+        .filter(|w| is_browser_window(w, &browsers))
+        .max_by_key(|w| (w.width() as u64) * (w.height() as u64))
+    {
+        log_to_file(&format!(
+            "Resolved browser via cursor containment fallback: id={}, title='{}', app='{}'",
+            window.id(),
+            window.title(),
+            window.app_name()
+        ))?;
+        return Ok(window);
+    }
+
+    if let Some(window) = windows
+        .iter()
+        .find(|w| w.is_focused() && is_browser_window(w, &browsers))
+    {
+        log_to_file(&format!(
+            "Resolved browser via focused window fallback: id={}, title='{}', app='{}'",
+            window.id(),
+            window.title(),
+            window.app_name()
+        ))?;
+        return Ok(window);
+    }
+
+    Err(anyhow::anyhow!(
+        "No browser window found for hover detection"
+    ))
+}
+
+fn get_pixel_color(img: &RgbaImage, x: u32, y: u32) -> Option<u32> {
+    if x < img.width() && y < img.height() {
+        let pixel = img.get_pixel(x, y);
+        let [r, g, b, _] = pixel.0;
+        Some(((r as u32) << 16) | ((g as u32) << 8) | (b as u32))
+    } else {
+        None
+    }
+}
+
+/// 3x5 bitmaps of the digits 0-9 for screenshot labels, one row per entry
+/// with the leftmost pixel in bit 2.
+const DIGIT_GLYPHS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+/// Side of a group's color swatch, and the pixel size of its label digits.
+const SWATCH_SIZE: u32 = 8;
+const LABEL_SCALE: u32 = 2;
+
+/// Copies the tab strip out of a capture (all of it for a vertical sweep)
+/// and draws the scan line (red), the cursor (green) and the detected group
+/// boundaries (blue) on top.
+///
+/// Each group also gets a swatch of its detected color (`colors`, parallel
+/// to `groups`) and its 1-based index at the start of its range, and the
+/// group at position `hovered` (0-based), the one the run concluded the
+/// cursor is in, is tinted yellow.
+#[allow(clippy::too_many_arguments)]
+fn annotate_capture(
+    img: &RgbaImage,
+    geometry: &ScanGeometry,
+    sweep: &Sweep,
+    cursor_x: u32,
+    cursor_y: u32,
+    groups: &[(u32, u32)],
+    colors: &[Option<u32>],
+    hovered: Option<usize>,
+) -> RgbImage {
+    // A vertical sweep runs down the whole capture rather than the top strip.
+    let vertical = sweep.direction.is_vertical();
+    let height = if vertical { img.height() } else { geometry.strip_height().min(img.height()) };
+    let mut debug_img = ImageBuffer::new(img.width(), height);
+    let mut put = |x: u32, y: u32, color: Rgb<u8>| {
+        if x < img.width() && y < height {
+            debug_img.put_pixel(x, y, color);
+        }
+    };
+
+    // Copy pixels from captured image
+    let hovered_span = hovered.and_then(|i| groups.get(i)).copied();
+    for y in 0..height {
+        for x in 0..img.width() {
+            if let Some(color) = get_pixel_color(img, x, y) {
+                let r = (color >> 16) & 0xFF;
+                let g = (color >> 8) & 0xFF;
+                let b = color & 0xFF;
+                // Tint the hovered group yellow across the whole strip.
+                let t = if vertical { y } else { x };
+                let pixel = if hovered_span.is_some_and(|(start, end)| t >= start && t < end) {
+                    Rgb([(r / 2 + 127) as u8, (g / 2 + 127) as u8, (b / 2) as u8])
+                } else {
+                    Rgb([r as u8, g as u8, b as u8])
+                };
+                put(x, y, pixel);
+            }
+        }
+    }
+
+    // Draw scan lines: the main one red, voting ones orange
+    for offset in geometry.scan_line_offsets() {
+        let Some(line) = sweep.offset_across(offset) else {
+            continue;
+        };
+        let color = if offset == 0 { Rgb([255, 0, 0]) } else { Rgb([255, 160, 0]) };
+        for (x, y) in (0..line.length).filter_map(|t| line.point(t)) {
+            put(x, y, color);
+        }
+    }
+
+    // Draw cursor position
+    for x in cursor_x.saturating_sub(5)..=cursor_x.saturating_add(5) {
+        put(x, cursor_y, Rgb([0, 255, 0]));
+    }
+    for y in cursor_y.saturating_sub(5)..=cursor_y.saturating_add(5) {
+        put(cursor_x, y, Rgb([0, 255, 0]));
+    }
+
+    // Draw group boundaries across the sweep
+    for (x, y) in groups.iter().flat_map(|(start, end)| [*start, *end]).filter_map(|t| sweep.point(t)) {
+        if vertical {
+            for x in 0..img.width() {
+                put(x, y, Rgb([0, 0, 255]));
+            }
+        } else {
+            for y in 0..height {
+                put(x, y, Rgb([0, 0, 255]));
+            }
+        }
+    }
+
+    // Swatch and index label at the start of each group, inside the strip
+    // edge so they stay clear of the scan line.
+    for (i, (start, _)) in groups.iter().enumerate() {
+        let Some((x, y)) = sweep.point(start + 2) else {
+            continue;
+        };
+        let (x, y) = if vertical { (1, y) } else { (x, 1) };
+        if let Some(color) = colors.get(i).copied().flatten() {
+            let fill = Rgb([(color >> 16) as u8, (color >> 8) as u8, color as u8]);
+            for dy in 0..SWATCH_SIZE {
+                for dx in 0..SWATCH_SIZE {
+                    let edge = dx == 0 || dy == 0 || dx == SWATCH_SIZE - 1 || dy == SWATCH_SIZE - 1;
+                    put(x + dx, y + dy, if edge { Rgb([255, 255, 255]) } else { fill });
+                }
+            }
+        }
+        let label = (i + 1).to_string();
+        for (n, digit) in label.bytes().map(|b| (b - b'0') as usize).enumerate() {
+            let left = x + SWATCH_SIZE + 2 + n as u32 * 4 * LABEL_SCALE;
+            for (row, bits) in DIGIT_GLYPHS[digit].iter().enumerate() {
+                for col in 0..3 {
+                    if bits & (0b100 >> col) == 0 {
+                        continue;
+                    }
+                    for dy in 0..LABEL_SCALE {
+                        for dx in 0..LABEL_SCALE {
+                            let (px, py) = (left + col * LABEL_SCALE + dx, y + row as u32 * LABEL_SCALE + dy);
+                            // A dark shadow keeps the label readable on light pills.
+                            put(px + 1, py + 1, Rgb([0, 0, 0]));
+                            put(px, py, Rgb([255, 255, 255]));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    debug_img
+}
+
+#[allow(clippy::too_many_arguments)]
+fn save_screenshot(
+    img: &RgbaImage,
+    geometry: &ScanGeometry,
+    sweep: &Sweep,
+    cursor_x: u32,
+    cursor_y: u32,
+    groups: &[(u32, u32)],
+    colors: &[Option<u32>],
+    hovered: Option<usize>,
+    path: &std::path::Path,
+    sidecar: &serde_json::Value,
+) -> Result<()> {
+    let mut png = Cursor::new(Vec::new());
+    annotate_capture(img, geometry, sweep, cursor_x, cursor_y, groups, colors, hovered)
+        .write_to(&mut png, ImageFormat::Png)?;
+    // A screenshot is only a debugging aid; failing to save one must not fail
+    // the detection it documents.
+    let files = [
+        (path.with_extension("png"), png.into_inner()),
+        (path.with_extension("json"), serde_json::to_vec_pretty(sidecar)?),
+    ];
+    for (path, contents) in &files {
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| write_file_atomically(&path.to_string_lossy(), contents));
+        if let Err(e) = written {
+            error!("Failed to save screenshot {}: {}", path.display(), e);
+            log_to_file(&format!("Failed to save screenshot {}: {}", path.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+/// What the detector concluded about a debug screenshot, saved next to it:
+/// the cursor (on screen and in the capture), the scan line, the groups
+/// found so far with their bounds and colors, the index the hovered group
+/// would be reported as and the window that was captured.
+fn screenshot_sidecar(
+    detection: &Detection,
+    sweep: &Sweep,
+    cursor: (u32, u32),
+    groups: &[(u32, u32)],
+    hovered: Option<usize>,
+) -> serde_json::Value {
+    serde_json::json!({
+        "cursor": { "x": detection.cursor.x, "y": detection.cursor.y },
+        "capture_cursor": { "x": cursor.0, "y": cursor.1 },
+        "scan_y": sweep.origin.1,
+        "sweep": {
+            "origin": { "x": sweep.origin.0, "y": sweep.origin.1 },
+            "direction": format!("{:?}", sweep.direction).to_lowercase(),
+            "length": sweep.length,
+        },
+        "groups": groups
+            .iter()
+            .enumerate()
+            .map(|(i, (start, end))| serde_json::json!({
+                "index": i + 1,
+                "start": start,
+                "end": end,
+                "color": detection.group_colors.get(i).copied().flatten().map(|c| format!("#{:06X}", c)),
+                "window_box": detection.group_boxes.get(i).copied().flatten().map(|b| rect_json(b, 0, 0)),
+            }))
+            .collect::<Vec<_>>(),
+        "index": hovered.map_or(0, |i| i + 1),
+        "window": detection.window.as_ref().map(|w| serde_json::json!({
+            "title": w.title,
+            "app_name": w.app_name,
+        })),
+    })
+}
+
+/// Where debug screenshots go: `TABGROUP_SCREENSHOT_DIR` (created if needed),
+/// or the working directory.
+fn screenshot_dir() -> std::path::PathBuf {
+    env::var_os("TABGROUP_SCREENSHOT_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map_or_else(std::path::PathBuf::new, std::path::PathBuf::from)
+}
+
+/// What a debug screenshot shows, for deciding whether it is worth saving:
+/// the group under the cursor and the full group layout.
+fn detection_signature(cursor_x: u32, groups: &[(u32, u32)]) -> String {
+    let hovered = groups
+        .iter()
+        .position(|(start, end)| cursor_x >= *start && cursor_x < *end);
+    format!("{:?} {:?}", hovered, groups)
+}
+
+/// With `TABGROUP_HOVER_SCREENSHOT_ON_CHANGE`, only detections whose result
+/// differs from the last saved screenshot are worth a new one. The last
+/// signature lives in a file so one-shot runs and `--serve` share it.
+fn screenshot_wanted(signature: &str) -> Result<bool> {
+    if !parse_bool_from_env("TABGROUP_HOVER_SCREENSHOT_ON_CHANGE", false) {
+        return Ok(true);
+    }
+    signature_changed(std::path::Path::new(SCREENSHOT_STATE_FILE), signature)
+}
+
+/// Whether `signature` differs from the one recorded in `state_file`,
+/// recording it when it does.
+fn signature_changed(state_file: &std::path::Path, signature: &str) -> Result<bool> {
+    let previous = std::fs::read_to_string(state_file).ok();
+    if previous.as_deref() == Some(signature) {
+        return Ok(false);
+    }
+    std::fs::write(state_file, signature)?;
+    Ok(true)
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn encode_png_base64(img: &RgbImage) -> Result<String> {
+    let mut png = Cursor::new(Vec::new());
+    img.write_to(&mut png, ImageFormat::Png)?;
+    Ok(encode_base64(png.get_ref()))
+}
+
+/// The browser window a detection ran against, in screen coordinates.
+#[derive(Clone, Debug)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    pub app_name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowInfo {
+    /// A frame loaded from disk or handed in by a caller, with no live window
+    /// behind it (id 0). The system's DPI, color filter and display profile
+    /// say nothing about such a frame, so they are not queried for it.
+    fn is_offline(&self) -> bool {
+        self.id == 0
+    }
+
+    fn from_window(window: &Window) -> Self {
+        Self {
+            id: window.id(),
+            title: window.title().to_string(),
+            app_name: window.app_name().to_string(),
+            x: window.x(),
+            y: window.y(),
+            width: window.width(),
+            height: window.height(),
+        }
+    }
+}
+
+/// Why a detection run did or did not report a group.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    Hit,
+    NoBrowserWindow,
+    NotBrowser,
+    CursorOutside,
+    NotOnGroup,
+    NoGroupAtCursor,
+    /// A Windows color filter or high contrast is active and could not be
+    /// undone, so group colors in the capture may not match the palette.
+    AccessibilityFilterActive,
+    /// `--deadline-ms` ran out before the group scan finished.
+    DeadlineExceeded,
+    /// The window is a browser by name, but its title bar has no tab-strip
+    /// structure (settings and other popup windows).
+    NoTabStrip,
+    /// A browser popup such as a context menu is open and
+    /// `TABGROUP_HOVER_IGNORE_DURING_POPUP` is set.
+    PopupActive,
+    /// The window was being moved or resized, so a capture would not match
+    /// its geometry.
+    WindowInMotion,
+    /// The cursor is on a group that `TABGROUP_HOVER_INDEX_MAP` ignores.
+    IndexIgnored,
+    /// Hardly any pixel on the scan line is background, so another window
+    /// (a tooltip, a popup) most likely covers the tab strip.
+    StripOccluded,
+}
+
+impl Reason {
+    /// Wire code in the `--binary` result. Codes are append-only; the native
+    /// host decodes them by position, so keep both lists in the same order.
+    pub fn code(self) -> u8 {
+        match self {
+            Reason::Hit => 0,
+            Reason::NoBrowserWindow => 1,
+            Reason::NotBrowser => 2,
+            Reason::CursorOutside => 3,
+            Reason::NotOnGroup => 4,
+            Reason::NoGroupAtCursor => 5,
+            Reason::AccessibilityFilterActive => 6,
+            Reason::DeadlineExceeded => 7,
+            Reason::NoTabStrip => 8,
+            Reason::PopupActive => 9,
+            Reason::WindowInMotion => 10,
+            Reason::IndexIgnored => 11,
+            Reason::StripOccluded => 12,
+        }
+    }
+
+    /// Coarse outcome for `--format json` consumers: whether a browser strip
+    /// was examined at all, or why not.
+    pub fn status(self) -> &'static str {
+        match self {
+            Reason::NoBrowserWindow | Reason::NotBrowser => "not_browser",
+            Reason::CursorOutside => "cursor_outside",
+            Reason::StripOccluded => "occluded",
+            _ => "ok",
+        }
+    }
+}
+
+/// What a plain run tells its caller, collapsed from [`Reason`] so scripts can
+/// branch on the exit code alone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DetectionOutcome {
+    /// No browser window under the cursor (`NoBrowserWindow`, `NotBrowser`).
+    NotBrowser,
+    /// A browser window, but the cursor is not over its tab strip.
+    CursorOutside,
+    /// The strip was examined and no group is hovered, for any other reason.
+    NoGroup,
+    /// The hovered group, 1-based.
+    Group(u32),
+}
+
+impl DetectionOutcome {
+    /// Exit code of the plain output mode. The index is still printed on
+    /// stdout in every case, so callers that only read it keep working.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            DetectionOutcome::Group(_) => 0,
+            DetectionOutcome::NotBrowser => 10,
+            DetectionOutcome::CursorOutside => 11,
+            DetectionOutcome::NoGroup => 12,
+        }
+    }
+}
+
+/// Outcome of one detection run: the hovered group (1-based, 0 for none) and
+/// every group span found on the scan line, in window-relative x coordinates.
+pub struct Detection {
+    pub index: u32,
+    pub reason: Reason,
+    pub groups: Vec<(u32, u32)>,
+    window: Option<WindowInfo>,
+    cursor: POINT,
+    cursor_color: Option<CursorHit>,
+    /// The scanned capture with the scan row and cursor in image coordinates,
+    /// kept so diagnostics can render an annotated copy.
+    capture: Option<ScannedCapture>,
+    /// The deadline expired before the full scan; `index` is not reliable.
+    pub partial: bool,
+    /// Measured group pill band (top, bottom) in capture pixels and the UI
+    /// density it suggests; `None` without groups to measure.
+    band: Option<(u32, u32)>,
+    density: Option<Density>,
+    /// Most common group color inside each span, parallel to `groups`.
+    group_colors: Vec<Option<u32>>,
+    /// Bounding box of each group in window coordinates, parallel to `groups`.
+    group_boxes: Vec<Option<RECT>>,
+    /// With `TABGROUP_HOVER_MAX_TAB_ROWS` above 1 and wrapped tabs, the tab
+    /// row (1-based) that was scanned; `groups` and `index` are within it.
+    row: Option<u32>,
+    row_count: u32,
+    /// Whether the hovered group looks collapsed; `None` without a hovered
+    /// group, a measured band, or with the heuristic disabled.
+    collapsed: Option<bool>,
+    /// The same for every group, parallel to `groups`.
+    group_collapsed: Vec<Option<bool>>,
+    /// The cursor was in the tab strip but the scan line was plain background
+    /// without a single group-colored pixel, as in a capture taken mid-repaint.
+    blank_strip: bool,
+    /// Unix time in milliseconds of the live capture that was scanned; `None`
+    /// when nothing was captured (an early miss or an injected frame).
+    pub captured_at_ms: Option<u64>,
+}
+
+/// How reported group colors are snapped before counting, so a pill drawn
+/// with a slight gradient reports one color rather than whichever shade
+/// happened to win.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Quantize {
+    Off,
+    /// Nearest palette entry.
+    Palette,
+    /// Each channel rounded to a multiple of the step.
+    Grid(u32),
+}
+
+impl Quantize {
+    fn from_env() -> Self {
+        match env::var("TABGROUP_HOVER_COLOR_QUANTIZE")
+            .map(|raw| raw.trim().to_lowercase())
+            .as_deref()
+        {
+            Ok("palette") => Quantize::Palette,
+            Ok("grid") => Quantize::Grid(parse_u32_from_env("TABGROUP_HOVER_COLOR_QUANTIZE_STEP", 16).clamp(1, 128)),
+            _ => Quantize::Off,
+        }
+    }
+
+    fn apply(self, color: u32, palette: &[u32]) -> u32 {
+        match self {
+            Quantize::Off => color,
+            Quantize::Palette => palette
+                .iter()
+                .copied()
+                .min_by_key(|entry| color_distance(color, *entry))
+                .unwrap_or(color),
+            Quantize::Grid(step) => [16, 8, 0].iter().fold(0, |out, shift| {
+                let channel = (color >> shift) & 0xFF;
+                let snapped = (((channel + step / 2) / step) * step).min(255);
+                out | (snapped << shift)
+            }),
+        }
+    }
+}
+
+/// Most frequent palette-matching color of the scan line inside `span`,
+/// after `quantize`.
+fn group_color(capture: &RgbaImage, sweep: &Sweep, span: (u32, u32), targets: &Palette, quantize: Quantize) -> Option<u32> {
+    let mut counts: HashMap<u32, u32> = HashMap::new();
+    for t in span.0..span.1 {
+        if let Some(color) = sweep.sample(capture, t).filter(|color| targets.matches(*color)) {
+            *counts.entry(quantize.apply(color, &targets.colors)).or_insert(0) += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(color, count)| (*count, std::cmp::Reverse(*color)))
+        .map(|(color, _)| color)
+}
+
+/// FNV-1a over the little-endian bytes of `values`. Fixed constants keep it
+/// stable across runs.
+fn fnv1a(values: impl IntoIterator<Item = u32>) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for value in values {
+        for byte in value.to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+/// FNV-1a hash of the ordered group spans and colors, stable across runs so
+/// callers can compare it with an earlier answer.
+fn layout_hash(groups: &[(u32, u32)], colors: &[Option<u32>]) -> u64 {
+    fnv1a(groups.iter().enumerate().flat_map(|(i, (start, end))| {
+        [*start, *end, colors.get(i).copied().flatten().unwrap_or(u32::MAX)]
+    }))
+}
+
+/// Edge UI density, as inferred from the group pill height.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Density {
+    Compact,
+    Default,
+    Touch,
+}
+
+impl Density {
+    /// Buckets a pill band height given in 96-DPI pixels.
+    fn from_band_height(height: f64) -> Self {
+        if height <= DENSITY_COMPACT_MAX_BAND {
+            Density::Compact
+        } else if height <= DENSITY_DEFAULT_MAX_BAND {
+            Density::Default
+        } else {
+            Density::Touch
+        }
+    }
+}
+
+/// Vertical extent of the widest group's pill, found by walking up and down
+/// its middle column from the scan line while the pixels stay group-colored.
+fn measure_band(capture: &RgbaImage, sweep: &Sweep, groups: &[(u32, u32)], targets: &Palette) -> Option<(u32, u32)> {
+    let (start, end) = groups.iter().max_by_key(|(start, end)| end - start)?;
+    let (x, y) = sweep.point((start + end) / 2)?;
+    // The band runs across the sweep: rows for a horizontal one, columns for a vertical one.
+    let vertical = sweep.direction.is_vertical();
+    let (middle, limit) = if vertical { (x, capture.width()) } else { (y, capture.height()) };
+    let is_pill = |across: u32| {
+        let (px, py) = if vertical { (across, y) } else { (x, across) };
+        get_pixel_color(capture, px, py).is_some_and(|color| targets.matches(color))
+    };
+    if !is_pill(middle) {
+        return None;
+    }
+    let mut top = middle;
+    while top > 0 && is_pill(top - 1) {
+        top -= 1;
+    }
+    let mut bottom = middle + 1;
+    while bottom < limit && is_pill(bottom) {
+        bottom += 1;
+    }
+    Some((top, bottom))
+}
+
+/// Capture-pixel box of the group `span` on `sweep`: the span along the
+/// sweep and the measured pill `band` across it, or just the sweep line
+/// when no band was measured.
+fn group_box(sweep: &Sweep, (start, end): (u32, u32), band: Option<(u32, u32)>) -> Option<RECT> {
+    let first = sweep.point(start)?;
+    let last = sweep.point(end.checked_sub(1)?)?;
+    let vertical = sweep.direction.is_vertical();
+    let (across_start, across_end) = band.unwrap_or(if vertical { (first.0, first.0 + 1) } else { (first.1, first.1 + 1) });
+    let (along_start, along_end) = if vertical {
+        (first.1.min(last.1), first.1.max(last.1) + 1)
+    } else {
+        (first.0.min(last.0), first.0.max(last.0) + 1)
+    };
+    let (left, top, right, bottom) = if vertical {
+        (across_start, along_start, across_end, along_end)
+    } else {
+        (along_start, across_start, along_end, across_end)
+    };
+    Some(RECT {
+        left: left as i32,
+        top: top as i32,
+        right: right as i32,
+        bottom: bottom as i32,
+    })
+}
+
+/// `rect` shifted by (`dx`, `dy`) as a JSON object.
+fn rect_json(rect: RECT, dx: i32, dy: i32) -> serde_json::Value {
+    serde_json::json!({
+        "left": rect.left + dx,
+        "top": rect.top + dy,
+        "right": rect.right + dx,
+        "bottom": rect.bottom + dy,
+    })
+}
+
+/// Vertical extents (top, bottom) of the tab rows in the top `height` pixels
+/// of the capture. A line belongs to a row when it has a few pixels that are
+/// not background; rows are separated by background bands at least `min_gap`
+/// lines high, and content bands thinner than `min_height` (window border,
+/// tab separators) are dropped.
+fn find_tab_rows(
+    capture: &RgbaImage,
+    height: u32,
+    background: &Background,
+    min_height: u32,
+    min_gap: u32,
+) -> Vec<(u32, u32)> {
+    let has_content = |y: u32| {
+        (0..capture.width())
+            .filter_map(|x| get_pixel_color(capture, x, y))
+            .filter(|color| !background.matches_candidate(*color))
+            .nth(TAB_ROW_CONTENT_PIXELS - 1)
+            .is_some()
+    };
+    let mut rows: Vec<(u32, u32)> = Vec::new();
+    let mut current: Option<(u32, u32)> = None;
+    for y in 0..height.min(capture.height()) {
+        if !has_content(y) {
+            continue;
+        }
+        current = match current {
+            Some((top, bottom)) if y - bottom < min_gap => Some((top, y + 1)),
+            Some(row) => {
+                rows.push(row);
+                Some((y, y + 1))
+            }
+            None => Some((y, y + 1)),
+        };
+    }
+    rows.extend(current);
+    rows.retain(|(top, bottom)| bottom - top >= min_height);
+    rows
+}
+
+/// `find_tab_rows` over the whole strip, with the row limits at the
+/// geometry's DPI.
+fn strip_tab_rows(capture: &RgbaImage, geometry: &ScanGeometry, background: &Background) -> Vec<(u32, u32)> {
+    find_tab_rows(
+        capture,
+        geometry.strip_height(),
+        background,
+        scale_for_dpi(TAB_ROW_MIN_HEIGHT, geometry.dpi),
+        scale_for_dpi(TAB_ROW_MIN_GAP, geometry.dpi).max(1),
+    )
+}
+
+/// Scan line within a tab row: the middle of the row for group pills, the
+/// configured offset below the row top for top borders.
+fn row_scan_y(row: (u32, u32), style: GroupStyle, geometry: &ScanGeometry) -> u32 {
+    match style {
+        GroupStyle::Pill => (row.0 + row.1) / 2,
+        GroupStyle::TopBorder => (row.0 + geometry.scan_y).min(row.1.saturating_sub(1)),
+    }
+}
+
+/// A collapsed group shows only its label chip, so its span is short for the
+/// pill height; an expanded one also runs under its tabs. Collapsed when the
+/// width is at most `max_ratio` band heights.
+fn is_collapsed(span: (u32, u32), band_height: u32, max_ratio: f64) -> bool {
+    span.1.saturating_sub(span.0) as f64 <= band_height as f64 * max_ratio
+}
+
+/// Share of the positions along `sweep` whose color is background.
+fn background_share(capture: &RgbaImage, sweep: &Sweep, background: &Background) -> f64 {
+    let (mut matched, mut total) = (0u32, 0u32);
+    for t in 0..sweep.length {
+        if let Some(color) = sweep.sample(capture, t) {
+            total += 1;
+            if background.matches(t, color) {
+                matched += 1;
+            }
+        }
+    }
+    if total == 0 {
+        return 0.0;
+    }
+    matched as f64 / total as f64
+}
+
+/// `TABGROUP_HOVER_MIN_BACKGROUND_SHARE`; 0 turns the occlusion check off.
+fn min_background_share() -> f64 {
+    env::var("TABGROUP_HOVER_MIN_BACKGROUND_SHARE")
+        .ok()
+        .and_then(|raw| raw.trim().parse::<f64>().ok())
+        .unwrap_or(MIN_BACKGROUND_SHARE_DEFAULT)
+}
+
+/// `TABGROUP_HOVER_COLLAPSED_MAX_RATIO`; 0 turns the band heuristic off.
+fn collapsed_max_ratio() -> f64 {
+    env::var("TABGROUP_HOVER_COLLAPSED_MAX_RATIO")
+        .ok()
+        .and_then(|raw| raw.trim().parse::<f64>().ok())
+        .unwrap_or(COLLAPSED_MAX_RATIO_DEFAULT)
+}
+
+/// Whether the group `span`, at `position` in the scan, is collapsed. A span
+/// narrower than `min_group_width` was only kept as a collapsed chip (see
+/// [`ScanOptions::is_collapsed_chip`]), so it is one without measuring;
+/// wider ones go by [`is_collapsed`] against the measured band.
+fn group_collapsed(
+    span: (u32, u32),
+    position: usize,
+    options: &ScanOptions,
+    band: Option<(u32, u32)>,
+    max_ratio: f64,
+) -> Option<bool> {
+    if options.is_collapsed_chip(span, position) {
+        return Some(true);
+    }
+    if max_ratio <= 0.0 {
+        return None;
+    }
+    band.map(|(top, bottom)| is_collapsed(span, bottom - top, max_ratio))
+}
+
+struct ScannedCapture {
+    image: RgbaImage,
+    geometry: ScanGeometry,
+    sweep: Sweep,
+    cursor_x: u32,
+    cursor_y: u32,
+    /// Position in `groups` of the group the cursor was found in, if any.
+    hovered: Option<usize>,
+}
+
+impl Detection {
+    pub fn outcome(&self) -> DetectionOutcome {
+        match self.reason {
+            _ if self.index > 0 => DetectionOutcome::Group(self.index),
+            Reason::NoBrowserWindow | Reason::NotBrowser => DetectionOutcome::NotBrowser,
+            Reason::CursorOutside => DetectionOutcome::CursorOutside,
+            _ => DetectionOutcome::NoGroup,
+        }
+    }
+
+    fn empty(cursor: POINT) -> Self {
+        Self {
+            index: 0,
+            reason: Reason::NoGroupAtCursor,
+            groups: Vec::new(),
+            window: None,
+            cursor,
+            cursor_color: None,
+            capture: None,
+            partial: false,
+            band: None,
+            density: None,
+            group_colors: Vec::new(),
+            group_boxes: Vec::new(),
+            row: None,
+            row_count: 0,
+            collapsed: None,
+            group_collapsed: Vec::new(),
+            blank_strip: false,
+            captured_at_ms: None,
+        }
+    }
+
+    /// Best-effort result for a run cut short by its deadline.
+    fn out_of_time(mut self) -> Self {
+        self.partial = true;
+        self.with_reason(Reason::DeadlineExceeded)
+    }
+
+    fn with_reason(mut self, reason: Reason) -> Self {
+        self.reason = reason;
+        self
+    }
+
+    /// The scanned capture with scan line, cursor and groups drawn in, as a
+    /// base64 PNG; `None` when the run stopped before capturing.
+    pub fn annotated_capture_base64(&self) -> Result<Option<String>> {
+        self.capture
+            .as_ref()
+            .map(|c| {
+                encode_png_base64(&annotate_capture(
+                    &c.image,
+                    &c.geometry,
+                    &c.sweep,
+                    c.cursor_x,
+                    c.cursor_y,
+                    &self.groups,
+                    &self.group_colors,
+                    c.hovered,
+                ))
+            })
+            .transpose()
+    }
+
+    /// Fixed 9-byte result for `--binary`: index (u32 LE), reason code (u8),
+    /// cursor color (u32 LE, `u32::MAX` when there is none).
+    pub fn to_binary(&self) -> [u8; 9] {
+        let mut out = [0u8; 9];
+        out[..4].copy_from_slice(&self.index.to_le_bytes());
+        out[4] = self.reason.code();
+        let color = self.cursor_color.map_or(u32::MAX, |hit| hit.color);
+        out[5..].copy_from_slice(&color.to_le_bytes());
+        out
+    }
+
+    /// One `--serve` answer line: the index, ` partial` for a deadline-limited
+    /// result, ` reason=<code>` with the `--binary` reason code and, when the
+    /// window was captured, ` captured_at_ms=<unix ms>`.
+    pub fn to_serve_line(&self) -> String {
+        format!(
+            "{}{} reason={}{}",
+            self.index,
+            if self.partial { " partial" } else { "" },
+            self.reason.code(),
+            self.captured_at_ms.map_or_else(String::new, |ms| format!(" captured_at_ms={}", ms))
+        )
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "status": self.reason.status(),
+            "index": self.index,
+            "reason": format!("{:?}", self.reason),
+            "partial": self.partial,
+            "captured_at_ms": self.captured_at_ms,
+            "band": self.band.map(|(top, bottom)| serde_json::json!({ "top": top, "bottom": bottom })),
+            "density": self.density.map(|density| format!("{:?}", density).to_lowercase()),
+            "ordinal_zero_based": self.index.checked_sub(1),
+            "row": self.row,
+            "collapsed": self.collapsed,
+            "toggle_action": self.collapsed.map(|collapsed| if collapsed { "expand" } else { "collapse" }),
+            "row_count": self.row_count,
+            "cursor": { "x": self.cursor.x, "y": self.cursor.y },
+            "color": self.cursor_color.map(|hit| format!("#{:06X}", hit.color)),
+            "exact_hit": self.cursor_color.map(|hit| hit.offset == 0),
+            "window": self.window.as_ref().map(|w| serde_json::json!({
+                "id": w.id,
+                "title": w.title,
+                "app_name": w.app_name,
+                "x": w.x,
+                "y": w.y,
+                "width": w.width,
+            })),
+            "groups": self.groups
+                .iter()
+                .enumerate()
+                .map(|(i, (start, end))| serde_json::json!({
+                    "index": i + 1,
+                    "start": start,
+                    "end": end,
+                    "color": self.group_colors.get(i).copied().flatten().map(|c| format!("#{:06X}", c)),
+                    "collapsed": self.group_collapsed.get(i).copied().flatten(),
+                    "window_box": self.group_boxes.get(i).copied().flatten().map(|b| rect_json(b, 0, 0)),
+                    "screen_box": self.group_boxes.get(i).copied().flatten().zip(self.window.as_ref())
+                        .map(|(b, w)| rect_json(b, w.x, w.y)),
+                }))
+                .collect::<Vec<_>>(),
+            "layout_hash": format!("{:016x}", layout_hash(&self.groups, &self.group_colors)),
+            "hovered_group": self.hovered_group().map(|i| {
+                let (start, end) = self.groups[i];
+                let window_box = self.group_boxes.get(i).copied().flatten();
+                serde_json::json!({
+                    "index": i + 1,
+                    "start": start,
+                    "end": end,
+                    "width": end - start,
+                    "window_left": self.window.as_ref().map(|w| w.x),
+                    "window_box": window_box.map(|b| rect_json(b, 0, 0)),
+                    "screen_box": window_box.zip(self.window.as_ref()).map(|(b, w)| rect_json(b, w.x, w.y)),
+                })
+            }),
+        })
+    }
+
+    /// Position in `groups` of the group `index` reports, if any.
+    fn hovered_group(&self) -> Option<usize> {
+        let i = self.index.checked_sub(1)? as usize;
+        (i < self.groups.len()).then_some(i)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DetectionMode {
+    /// Groups are runs of group-colored pixels, closed by background gaps.
+    Fill,
+    /// Groups are the stretches between background gaps that contain group
+    /// colors; tolerates noisy fills as long as the gaps are clean.
+    Gaps,
+}
+
+impl DetectionMode {
+    fn from_env() -> Self {
+        match env::var("TABGROUP_HOVER_DETECTION_MODE")
+            .map(|raw| raw.trim().to_lowercase())
+            .as_deref()
+        {
+            Ok("gaps") => DetectionMode::Gaps,
+            _ => DetectionMode::Fill,
+        }
+    }
+}
+
+/// How the theme draws group membership on the tabs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum GroupStyle {
+    /// Filled group pill, visible halfway up the title bar.
+    Pill,
+    /// Only a thin colored border along the top of the tabs.
+    TopBorder,
+}
+
+impl GroupStyle {
+    fn from_env() -> Self {
+        match env::var("TABGROUP_HOVER_GROUP_STYLE")
+            .map(|raw| raw.trim().to_lowercase())
+            .as_deref()
+        {
+            Ok("top_border") => GroupStyle::TopBorder,
+            _ => GroupStyle::Pill,
+        }
+    }
+
+    /// Y position to scan for tab groups in a strip `threshold` pixels high.
+    fn scan_y(self, threshold: u32, dpi: u32) -> u32 {
+        match self {
+            // Halfway up the title bar
+            GroupStyle::Pill => threshold / 2,
+            // A few pixels down, where the border sits; mid-height misses it entirely
+            GroupStyle::TopBorder => scale_for_dpi(
+                parse_u32_from_env("TABGROUP_HOVER_TOP_BORDER_OFFSET", TOP_BORDER_OFFSET_DEFAULT)
+                    as f64,
+                dpi,
+            )
+            .min(threshold.saturating_sub(1)),
+        }
+    }
+}
+
+/// DPI-dependent measurements for one detection, all derived from a single
+/// DPI query so the strip height, scan line and cursor sweep cannot disagree.
+#[derive(Clone, Copy, Debug)]
+struct ScanGeometry {
+    dpi: u32,
+    /// Height of the tab strip in physical pixels.
+    vertical_threshold: u32,
+    scan_y: u32,
+    /// How far before (left of, or above) and after the cursor a group color
+    /// still counts as under it, in physical pixels.
+    proximity: (i32, i32),
+    /// Rows at the top of the strip (resize border, drag region) where the
+    /// cursor never counts as over a group.
+    top_dead_zone: u32,
+    /// Tab rows the strip may wrap onto; the searched strip is this many
+    /// `vertical_threshold`s high.
+    tab_rows: u32,
+    /// Parallel scan lines that vote on the hovered group, `scan_row_spacing`
+    /// pixels apart around the main one.
+    scan_rows: u32,
+    scan_row_spacing: u32,
+    /// Width of the vertical tab pane at the left of the window, and the
+    /// column inside it that is swept downwards in vertical orientation.
+    pane_width: u32,
+    pane_scan_x: u32,
+}
+
+impl ScanGeometry {
+    fn for_dpi(dpi: u32, style: GroupStyle) -> Self {
+        let dpi = dpi.max(1);
+        let vertical_threshold = vertical_threshold(dpi);
+        Self {
+            dpi,
+            vertical_threshold,
+            scan_y: style.scan_y(vertical_threshold, dpi),
+            proximity: proximity_reach(dpi),
+            top_dead_zone: scale_for_dpi(
+                parse_u32_from_env("TABGROUP_HOVER_TOP_DEAD_ZONE", 0) as f64,
+                dpi,
+            ),
+            tab_rows: parse_u32_from_env("TABGROUP_HOVER_MAX_TAB_ROWS", 1).max(1),
+            scan_rows: parse_u32_from_env("TABGROUP_HOVER_SCAN_ROWS", 1).clamp(1, 9),
+            scan_row_spacing: scale_for_dpi(
+                parse_u32_from_env("TABGROUP_HOVER_SCAN_ROW_SPACING", SCAN_ROW_SPACING_DEFAULT) as f64,
+                dpi,
+            )
+            .max(1),
+            pane_width: scale_for_dpi(
+                parse_u32_from_env("TABGROUP_HOVER_VERTICAL_PANE_WIDTH", VERTICAL_PANE_WIDTH_DEFAULT) as f64,
+                dpi,
+            ),
+            pane_scan_x: scale_for_dpi(
+                parse_u32_from_env("TABGROUP_HOVER_VERTICAL_SCAN_X", VERTICAL_SCAN_X_DEFAULT) as f64,
+                dpi,
+            ),
+        }
+    }
+
+    /// Offsets of the voting scan lines from the main one: `0` and then
+    /// alternately before and after it.
+    fn scan_line_offsets(&self) -> Vec<i32> {
+        (0..self.scan_rows as i32)
+            .map(|k| {
+                let step = (k + 1) / 2;
+                let side = if k % 2 == 1 { -1 } else { 1 };
+                side * step * self.scan_row_spacing as i32
+            })
+            .collect()
+    }
+
+    /// Factor from window-relative cursor coordinates to capture pixels for a
+    /// `capture_width` capture of a `window_width` window, snapped with the
+    /// same DPI as every other measurement here (see [`resolve_cursor_scale`]).
+    fn cursor_scale(&self, capture_width: u32, window_width: u32, system_dpi: Option<u32>) -> (f64, &'static str) {
+        resolve_cursor_scale(
+            capture_width,
+            window_width,
+            system_dpi.map(|system| (self.dpi, system)),
+            parse_f64_from_env("TABGROUP_HOVER_DPI_SCALE"),
+        )
+    }
+
+    /// Height of the searched strip, covering every allowed tab row.
+    fn strip_height(&self) -> u32 {
+        self.vertical_threshold.saturating_mul(self.tab_rows)
+    }
+
+    /// Physical pixels per 96-DPI pixel.
+    fn scale(&self) -> f64 {
+        self.dpi as f64 / BASE_DPI as f64
+    }
+}
+
+/// Tunables for the horizontal group scan, read from `TABGROUP_HOVER_*` env vars.
+struct ScanOptions {
+    mode: DetectionMode,
+    /// Width a closed group must span to be kept (or be a collapsed chip).
+    /// Applied after the scan, so it bounds the result rather than the
+    /// thresholds below: a group opens on `min_start_run` pixels, closes on
+    /// `min_bg_gap_width`, and is then measured from start to gap.
+    min_group_width: u32,
+    /// Consecutive background pixels that close a group. Pill text and
+    /// favicons can come within background tolerance for a few pixels, so
+    /// this is wider than `min_start_run`.
+    min_bg_gap_width: u32,
+    /// Consecutive group-colored pixels needed before fill mode opens a group,
+    /// so a stray matching pixel cannot start one (or drag a group's start
+    /// to the left). The group then starts at the first pixel of the run.
+    /// Kept separate from `min_bg_gap_width`: opening counts group pixels and
+    /// only has to beat speckle, while closing counts background pixels and
+    /// has to survive gaps inside a pill, so one N cannot fit both.
+    min_start_run: u32,
+    /// Width of a run of non-background, non-group pixels that ends a group
+    /// like a background gap would. `0` keeps such runs inside the group.
+    unknown_separator_width: u32,
+    /// Divider colors that end a group immediately, even between adjacent
+    /// same-colored groups with no background gap.
+    separator_colors: Vec<u32>,
+    /// Candidates narrower than this that sit inside neutral, ungrouped tab
+    /// surroundings are favicon blips rather than groups. `0` disables.
+    ungrouped_blip_width: u32,
+    /// Pixels at the left of the strip that are never scanned. Edge can place
+    /// the search/address pill next to the tabs, and its accent-colored pixels
+    /// would otherwise be counted as a leading group.
+    left_margin: u32,
+    /// Colors of the active tab, which Edge draws slightly over the group
+    /// pill's edge. Inside a group they continue it instead of ending it.
+    active_tab_colors: Vec<u32>,
+    /// A group whose first pixel is the first scanned one is clipped by the
+    /// strip edge, so its visible width says nothing about whether it is a
+    /// real group. When set, such a group is kept regardless of
+    /// `min_group_width`; off by default, since a narrow run at the edge is
+    /// more often a favicon or the address pill than a scrolled-off group.
+    keep_clipped_first_group: bool,
+    /// Narrower candidates down to this width are kept as collapsed groups,
+    /// whose chip is much narrower than an expanded group. `0` drops them
+    /// like any other narrow candidate.
+    collapsed_chip_min_width: u32,
+}
+
+impl ScanOptions {
+    fn from_env() -> Self {
+        Self {
+            mode: DetectionMode::from_env(),
+            min_group_width: parse_u32_from_env(
+                "TABGROUP_HOVER_MIN_GROUP_WIDTH",
+                MIN_GROUP_WIDTH_DEFAULT,
+            ),
+            min_bg_gap_width: parse_u32_from_env(
+                "TABGROUP_HOVER_MIN_BG_GAP_WIDTH",
+                MIN_BACKGROUND_GAP_WIDTH_DEFAULT,
+            ),
+            min_start_run: parse_u32_from_env("TABGROUP_HOVER_MIN_START_RUN", MIN_START_RUN_DEFAULT).max(1),
+            unknown_separator_width: parse_u32_from_env(
+                "TABGROUP_HOVER_UNKNOWN_SEPARATOR_WIDTH",
+                UNKNOWN_SEPARATOR_WIDTH_DEFAULT,
+            ),
+            separator_colors: parse_colors_from_env("TABGROUP_HOVER_SEPARATOR_COLORS"),
+            ungrouped_blip_width: parse_u32_from_env(
+                "TABGROUP_HOVER_UNGROUPED_BLIP_WIDTH",
+                UNGROUPED_BLIP_WIDTH_DEFAULT,
+            ),
+            left_margin: parse_u32_from_env("TABGROUP_HOVER_LEFT_MARGIN", 0),
+            active_tab_colors: parse_colors_from_env("TABGROUP_HOVER_ACTIVE_TAB_COLORS"),
+            keep_clipped_first_group: parse_bool_from_env("TABGROUP_HOVER_KEEP_CLIPPED_FIRST_GROUP", false),
+            collapsed_chip_min_width: parse_u32_from_env(
+                "TABGROUP_HOVER_COLLAPSED_CHIP_MIN_WIDTH",
+                COLLAPSED_CHIP_MIN_WIDTH_DEFAULT,
+            ),
+        }
+    }
+
+    /// A group at `position` in the scan that starts at the scan origin is
+    /// cut off by the strip edge (see `keep_clipped_first_group`).
+    fn is_clipped(&self, span: (u32, u32), position: usize) -> bool {
+        self.keep_clipped_first_group && position == 0 && span.0 <= self.left_margin && span.1 > span.0
+    }
+
+    /// Whether `span` is too narrow for an expanded group but wide enough for
+    /// a collapsed one's chip. Clipped groups are not judged by width.
+    fn is_collapsed_chip(&self, span: (u32, u32), position: usize) -> bool {
+        let width = span.1.saturating_sub(span.0);
+        self.collapsed_chip_min_width > 0
+            && width >= self.collapsed_chip_min_width
+            && width < self.min_group_width
+            && !self.is_clipped(span, position)
+    }
+
+    /// First x position scanned in a row `width` pixels wide.
+    fn scan_start(&self, width: u32) -> u32 {
+        self.left_margin.min(width)
+    }
+}
+
+fn format_colors(colors: &[u32]) -> Vec<String> {
+    colors.iter().map(|c| format!("#{:06X}", c)).collect()
+}
+
+/// Effective detection settings after env overrides, for diagnostics.
+pub fn effective_config_json() -> serde_json::Value {
+    let options = ScanOptions::from_env();
+    let style = GroupStyle::from_env();
+    let geometry = ScanGeometry::for_dpi(BASE_DPI, style);
+    serde_json::json!({
+        "vertical_threshold": geometry.vertical_threshold,
+        "clamp_to_caption": parse_bool_from_env("TABGROUP_HOVER_CLAMP_TO_CAPTION", false),
+        "group_style": format!("{:?}", style),
+        "scan_y": geometry.scan_y,
+        "proximity_left": geometry.proximity.0,
+        "proximity_right": geometry.proximity.1,
+        "top_dead_zone": geometry.top_dead_zone,
+        "max_tab_rows": geometry.tab_rows,
+        "scan_rows": geometry.scan_rows,
+        "scan_row_spacing": geometry.scan_row_spacing,
+        "dpi_override": parse_f64_from_env("TABGROUP_HOVER_DPI"),
+        "screenshot_dir": screenshot_dir(),
+        "detection_mode": format!("{:?}", options.mode),
+        "min_group_width": options.min_group_width,
+        "collapsed_chip_min_width": options.collapsed_chip_min_width,
+        "min_background_share": min_background_share(),
+        "blank_retries": parse_u32_from_env("TABGROUP_HOVER_BLANK_RETRIES", BLANK_RETRIES_DEFAULT),
+        "blank_retry_ms": parse_u32_from_env("TABGROUP_HOVER_BLANK_RETRY_MS", BLANK_RETRY_DELAY_MS_DEFAULT),
+        "min_bg_gap_width": options.min_bg_gap_width,
+        "min_start_run": options.min_start_run,
+        "unknown_separator_width": options.unknown_separator_width,
+        "ungrouped_blip_width": options.ungrouped_blip_width,
+        "left_margin": options.left_margin,
+        "keep_clipped_first_group": options.keep_clipped_first_group,
+        "active_tab_colors": format_colors(&options.active_tab_colors),
+        "ignore_during_popup": parse_bool_from_env("TABGROUP_HOVER_IGNORE_DURING_POPUP", false),
+        "tab_strip_min_regions": parse_u32_from_env("TABGROUP_HOVER_TAB_STRIP_MIN_REGIONS", 0),
+        "separator_colors": format_colors(&options.separator_colors),
+        "theme": Theme::from_env().map_or("Auto".to_string(), |theme| format!("{:?}", theme)),
+        "target_colors": format_colors(&target_colors(Theme::from_env().unwrap_or(Theme::Dark), "")),
+        "browser_palette": browser_palette("").name,
+        "browsers": browser_names(),
+        "min_saturation": parse_f64_from_env("TABGROUP_HOVER_MIN_SATURATION").unwrap_or(0.0),
+        "low_saturation_colors": format_colors(&parse_colors_from_env("TABGROUP_HOVER_LOW_SATURATION_COLORS")),
+        "background_override": format_colors(&parse_colors_from_env("TABGROUP_HOVER_BG_COLORS")),
+        "background_sample_width": parse_u32_from_env("TABGROUP_HOVER_BG_SAMPLE_WIDTH", BG_SAMPLE_WIDTH_DEFAULT),
+        "dpi_scale_override": parse_f64_from_env("TABGROUP_HOVER_DPI_SCALE"),
+        "display_color_profile": display_color_profile(),
+        "use_theme_color": parse_bool_from_env("TABGROUP_HOVER_USE_THEME_COLOR", true),
+        "orientation": format!("{:?}", Orientation::from_env()),
+        "vertical_pane_width": geometry.pane_width,
+        "vertical_scan_x": geometry.pane_scan_x,
+        "capture_strategy": format!("{:?}", CaptureStrategy::from_env()),
+        "window_cache_ms": parse_u32_from_env("TABGROUP_HOVER_WINDOW_CACHE_MS", WINDOW_CACHE_MS_DEFAULT),
+        "color_tolerance": palette_tolerance(None),
+        "background_tolerance": background_tolerance(),
+        "color_quantize": format!("{:?}", Quantize::from_env()),
+        "exact_pixel_first": parse_bool_from_env("TABGROUP_HOVER_EXACT_PIXEL_FIRST", true),
+        "index_map": env::var("TABGROUP_HOVER_INDEX_MAP").ok(),
+        "unblend_backdrop": format_colors(&parse_colors_from_env("TABGROUP_HOVER_UNBLEND_BACKDROP")),
+        "unblend_alpha": parse_f64_from_env("TABGROUP_HOVER_UNBLEND_ALPHA"),
+        "sweep_origin": env::var("TABGROUP_HOVER_SWEEP_ORIGIN").ok(),
+        "sweep_direction": env::var("TABGROUP_HOVER_SWEEP_DIRECTION").ok(),
+        "sweep_length": env::var("TABGROUP_HOVER_SWEEP_LENGTH").ok(),
+        "sweep_thickness": parse_u32_from_env("TABGROUP_HOVER_SWEEP_THICKNESS", 1).max(1),
+    })
+}
+
+impl ScanOptions {
+    fn is_separator_color(&self, color: u32) -> bool {
+        self.separator_colors
+            .iter()
+            .any(|separator| color_matches(color, *separator, SEPARATOR_COLOR_TOLERANCE))
+    }
+
+    fn is_active_tab_color(&self, color: u32) -> bool {
+        self.active_tab_colors
+            .iter()
+            .any(|active| color_matches(color, *active, TARGET_COLOR_TOLERANCE))
+    }
+}
+
+/// Where the browser puts its tabs, from `TABGROUP_HOVER_ORIENTATION`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Orientation {
+    /// Tab strip across the top of the window, swept left to right.
+    Horizontal,
+    /// Edge's vertical tabs: a pane at the left, swept top to bottom along
+    /// `TABGROUP_HOVER_VERTICAL_SCAN_X`.
+    Vertical,
+    /// Horizontal, unless the top scan line holds no group color and the
+    /// vertical column does.
+    Auto,
+}
+
+impl Orientation {
+    fn from_env() -> Self {
+        match env::var("TABGROUP_HOVER_ORIENTATION")
+            .map(|raw| raw.trim().to_lowercase())
+            .as_deref()
+        {
+            Ok("vertical") => Orientation::Vertical,
+            Ok("auto") => Orientation::Auto,
+            _ => Orientation::Horizontal,
+        }
+    }
+}
+
+/// Direction a `Sweep` walks through the capture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SweepDirection {
+    Right,
+    Left,
+    Down,
+    Up,
+}
+
+impl SweepDirection {
+    fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_lowercase().as_str() {
+            "right" => Some(SweepDirection::Right),
+            "left" => Some(SweepDirection::Left),
+            "down" => Some(SweepDirection::Down),
+            "up" => Some(SweepDirection::Up),
+            _ => None,
+        }
+    }
+
+    /// Pixel offset of one step along the sweep.
+    fn step(self) -> (i64, i64) {
+        match self {
+            SweepDirection::Right => (1, 0),
+            SweepDirection::Left => (-1, 0),
+            SweepDirection::Down => (0, 1),
+            SweepDirection::Up => (0, -1),
+        }
+    }
+
+    fn is_vertical(self) -> bool {
+        matches!(self, SweepDirection::Down | SweepDirection::Up)
+    }
+}
+
+/// Straight line through the capture that the group scan walks, one position
+/// `t` per pixel from `origin` in `direction`. Group spans, the left margin
+/// and the cursor are all measured in `t`.
+///
+/// Each position samples `thickness` pixels across the line and takes the
+/// most common color (the one nearest the line on a tie), which rides out
+/// single-pixel noise. The default is the horizontal scan line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Sweep {
+    origin: (u32, u32),
+    direction: SweepDirection,
+    length: u32,
+    thickness: u32,
+}
+
+impl Sweep {
+    /// Left to right along `scan_y`, one pixel thick.
+    fn horizontal(width: u32, scan_y: u32) -> Self {
+        Self {
+            origin: (0, scan_y),
+            direction: SweepDirection::Right,
+            length: width,
+            thickness: 1,
+        }
+    }
+
+    /// Top to bottom along column `x`, one pixel thick.
+    fn vertical(x: u32, height: u32) -> Self {
+        Self {
+            origin: (x, 0),
+            direction: SweepDirection::Down,
+            length: height,
+            thickness: 1,
+        }
+    }
+
+    /// `preset` with any `TABGROUP_HOVER_SWEEP_*` overrides for a `width` x
+    /// `height` capture. The length defaults to the distance from the origin
+    /// to the capture edge.
+    fn from_env(width: u32, height: u32, preset: Sweep) -> Self {
+        let origin = env::var("TABGROUP_HOVER_SWEEP_ORIGIN")
+            .ok()
+            .and_then(|raw| parse_point(&raw))
+            .filter(|point| point.x >= 0 && point.y >= 0)
+            .map_or(preset.origin, |point| (point.x as u32, point.y as u32));
+        let direction = env::var("TABGROUP_HOVER_SWEEP_DIRECTION")
+            .ok()
+            .and_then(|raw| SweepDirection::parse(&raw))
+            .unwrap_or(preset.direction);
+        let to_edge = match direction {
+            SweepDirection::Right => width.saturating_sub(origin.0),
+            SweepDirection::Left => origin.0.saturating_add(1).min(width),
+            SweepDirection::Down => height.saturating_sub(origin.1),
+            SweepDirection::Up => origin.1.saturating_add(1).min(height),
+        };
+        Self {
+            origin,
+            direction,
+            length: parse_u32_from_env("TABGROUP_HOVER_SWEEP_LENGTH", to_edge).min(to_edge),
+            thickness: parse_u32_from_env("TABGROUP_HOVER_SWEEP_THICKNESS", 1).max(1),
+        }
+    }
+
+    /// The same sweep moved `delta` pixels across itself (down for a
+    /// horizontal sweep, right for a vertical one), `None` past the origin.
+    fn offset_across(&self, delta: i32) -> Option<Self> {
+        let (x, y) = self.origin;
+        let origin = if self.direction.is_vertical() {
+            (x.checked_add_signed(delta)?, y)
+        } else {
+            (x, y.checked_add_signed(delta)?)
+        };
+        Some(Self { origin, ..*self })
+    }
+
+    /// Capture pixel at position `t`, or `None` before the capture origin.
+    fn point(&self, t: u32) -> Option<(u32, u32)> {
+        let (dx, dy) = self.direction.step();
+        let x = self.origin.0 as i64 + dx * t as i64;
+        let y = self.origin.1 as i64 + dy * t as i64;
+        Some((u32::try_from(x).ok()?, u32::try_from(y).ok()?))
+    }
+
+    /// Position of capture pixel (`x`, `y`) along the sweep, `None` if it lies
+    /// before the origin.
+    fn position_of(&self, x: u32, y: u32) -> Option<u32> {
+        let (ox, oy) = self.origin;
+        match self.direction {
+            SweepDirection::Right => x.checked_sub(ox),
+            SweepDirection::Left => ox.checked_sub(x),
+            SweepDirection::Down => y.checked_sub(oy),
+            SweepDirection::Up => oy.checked_sub(y),
+        }
+    }
+
+    /// Color at position `t`.
+    fn sample(&self, capture: &RgbaImage, t: u32) -> Option<u32> {
+        let (x, y) = self.point(t)?;
+        if self.thickness <= 1 {
+            return get_pixel_color(capture, x, y);
+        }
+        let (across_x, across_y) = if self.direction.is_vertical() { (1, 0) } else { (0, 1) };
+        let half = (self.thickness / 2) as i64;
+        // (color, count, distance of its nearest sample from the line)
+        let mut tally: Vec<(u32, u32, i64)> = Vec::new();
+        for k in -half..self.thickness as i64 - half {
+            let (Ok(px), Ok(py)) = (u32::try_from(x as i64 + across_x * k), u32::try_from(y as i64 + across_y * k)) else {
+                continue;
+            };
+            let Some(color) = get_pixel_color(capture, px, py) else {
+                continue;
+            };
+            match tally.iter_mut().find(|(c, _, _)| *c == color) {
+                Some(entry) => {
+                    entry.1 += 1;
+                    entry.2 = entry.2.min(k.abs());
+                }
+                None => tally.push((color, 1, k.abs())),
+            }
+        }
+        tally
+            .into_iter()
+            .max_by_key(|(_, count, distance)| (*count, std::cmp::Reverse(*distance)))
+            .map(|(color, _, _)| color)
+    }
+}
+
+fn close_group(
+    groups: &mut Vec<(u32, u32)>,
+    group_start: u32,
+    group_end: u32,
+    options: &ScanOptions,
+    label: &str,
+) -> Result<()> {
+    let group_width = group_end.saturating_sub(group_start);
+    // Starting at the scan origin means the strip edge cut it off; it begins
+    // at the origin and counts however narrow the visible part is.
+    let clipped = options.is_clipped((group_start, group_end), groups.len());
+    // A collapsed group keeps its place in the order, so its narrow chip
+    // must count for the indices after it to match the browser's.
+    let chip = options.is_collapsed_chip((group_start, group_end), groups.len());
+    let label = if clipped {
+        "edge-clipped "
+    } else if chip {
+        "collapsed "
+    } else {
+        label
+    };
+    if group_width >= options.min_group_width || clipped || chip {
+        groups.push((group_start, group_end));
+        log_to_file(&format!(
+            "Accepted {}tab group {}: start={}, end={}, width={}",
+            label,
+            groups.len(),
+            group_start,
+            group_end,
+            group_width
+        ))?;
+    } else {
+        log_to_file(&format!(
+            "Ignored narrow {}group candidate: start={}, end={}, width={}",
+            label,
+            group_start,
+            group_end,
+            group_width
+        ))?;
+    }
+    Ok(())
+}
+
+fn scan_tab_groups(
+    capture: &RgbaImage,
+    sweep: &Sweep,
+    targets: &Palette,
+    background: &Background,
+    options: &ScanOptions,
+) -> Result<Vec<(u32, u32)>> {
+    let groups = match options.mode {
+        DetectionMode::Fill => scan_tab_groups_by_fill(capture, sweep, targets, background, options)?,
+        DetectionMode::Gaps => scan_tab_groups_by_gaps(capture, sweep, targets, background, options)?,
+    };
+    drop_ungrouped_blips(capture, sweep, groups, targets, options)
+}
+
+/// Whether the run `[from, to)` of the scan line looks like ungrouped tabs:
+/// mostly neutral (low saturation) pixels and no group color.
+fn is_ungrouped_context(capture: &RgbaImage, sweep: &Sweep, from: u32, to: u32, targets: &Palette) -> bool {
+    let mut neutral = 0u32;
+    let mut total = 0u32;
+    for x in from..to.min(sweep.length) {
+        let Some(color) = sweep.sample(capture, x) else {
+            continue;
+        };
+        if targets.matches(color) {
+            return false;
+        }
+        total += 1;
+        if color_channel_spread(color) <= 28 {
+            neutral += 1;
+        }
+    }
+    total > 0 && neutral as f64 >= total as f64 * UNGROUPED_NEUTRAL_RATIO
+}
+
+/// Removes narrow palette-colored blips (typically favicons) surrounded on
+/// both sides by ungrouped tabs, which would otherwise count as phantom groups.
+fn drop_ungrouped_blips(
+    capture: &RgbaImage,
+    sweep: &Sweep,
+    groups: Vec<(u32, u32)>,
+    targets: &Palette,
+    options: &ScanOptions,
+) -> Result<Vec<(u32, u32)>> {
+    if options.ungrouped_blip_width == 0 {
+        return Ok(groups);
+    }
+    let mut kept = Vec::with_capacity(groups.len());
+    for (start, end) in groups {
+        let narrow = end.saturating_sub(start) < options.ungrouped_blip_width;
+        // Clipped at the strip edge there is no context on that side; keep it.
+        let has_context = start >= UNGROUPED_CONTEXT_WIDTH
+            && end + UNGROUPED_CONTEXT_WIDTH <= sweep.length;
+        if narrow
+            && has_context
+            && is_ungrouped_context(capture, sweep, start - UNGROUPED_CONTEXT_WIDTH, start, targets)
+            && is_ungrouped_context(capture, sweep, end, end + UNGROUPED_CONTEXT_WIDTH, targets)
+        {
+            log_to_file(&format!(
+                "Dropped blip in ungrouped tabs: start={}, end={}",
+                start, end
+            ))?;
+            continue;
+        }
+        kept.push((start, end));
+    }
+    Ok(kept)
+}
+
+/// Splits the scan line at background gaps of at least `min_bg_gap_width` and
+/// keeps the segments in between that contain enough group color. Each group
+/// is trimmed to its first and last group-colored pixel so spans (and hence
+/// indices) line up with fill mode.
+fn finish_gap_segment(
+    groups: &mut Vec<(u32, u32)>,
+    first_target: Option<u32>,
+    last_target: u32,
+    target_pixels: u32,
+    options: &ScanOptions,
+) -> Result<()> {
+    let Some(start) = first_target else {
+        return Ok(());
+    };
+    let chip_pixels = match options.collapsed_chip_min_width {
+        0 => u32::MAX,
+        width => width,
+    };
+    if target_pixels >= (options.min_group_width / 2).min(chip_pixels).max(1) {
+        close_group(groups, start, last_target + 1, options, "gap-delimited ")
+    } else {
+        log_to_file(&format!(
+            "Ignored sparse segment starting at {} ({} group pixels)",
+            start, target_pixels
+        ))
+    }
+}
+
+fn scan_tab_groups_by_gaps(
+    capture: &RgbaImage,
+    sweep: &Sweep,
+    targets: &Palette,
+    background: &Background,
+    options: &ScanOptions,
+) -> Result<Vec<(u32, u32)>> {
+    let mut groups = Vec::new();
+    let mut segment_targets = 0u32;
+    let mut first_target: Option<u32> = None;
+    let mut last_target = 0u32;
+    let mut bg_run_start: Option<u32> = None;
+
+    for x in options.scan_start(sweep.length)..sweep.length {
+        let Some(color) = sweep.sample(capture, x) else {
+            continue;
+        };
+        if options.is_separator_color(color) {
+            finish_gap_segment(&mut groups, first_target, last_target, segment_targets, options)?;
+            first_target = None;
+            segment_targets = 0;
+            bg_run_start = None;
+            continue;
+        }
+        if background.matches(x, color) {
+            let run_start = *bg_run_start.get_or_insert(x);
+            if x + 1 - run_start == options.min_bg_gap_width.max(1) {
+                finish_gap_segment(&mut groups, first_target, last_target, segment_targets, options)?;
+                first_target = None;
+                segment_targets = 0;
+            }
+            continue;
+        }
+        bg_run_start = None;
+        if targets.matches(color) {
+            first_target.get_or_insert(x);
+            last_target = x;
+            segment_targets += 1;
+        } else if first_target.is_some() && options.is_active_tab_color(color) {
+            // Active-tab overhang at the group edge still belongs to the group.
+            last_target = x;
+        }
+    }
+    finish_gap_segment(&mut groups, first_target, last_target, segment_targets, options)?;
+
+    Ok(groups)
+}
+
+fn scan_tab_groups_by_fill(
+    capture: &RgbaImage,
+    sweep: &Sweep,
+    targets: &Palette,
+    background: &Background,
+    options: &ScanOptions,
+) -> Result<Vec<(u32, u32)>> {
+    // Variables to track tab groups
+    let mut groups = Vec::new();
+    let mut active_group_start: Option<u32> = None;
+    let mut pending_bg_start: Option<u32> = None;
+    let mut pending_unknown_start: Option<u32> = None;
+    let mut start_run: Option<u32> = None;
+
+    // Scan horizontally for tab groups
+    for x in options.scan_start(sweep.length)..sweep.length {
+        if let Some(current_color) = sweep.sample(capture, x) {
+            // Separators are checked first so they win over a close palette match.
+            if options.is_separator_color(current_color) {
+                if let Some(group_start) = active_group_start.take() {
+                    let group_end = pending_bg_start.unwrap_or(x);
+                    log_to_file(&format!("Separator color #{:06X} at x={}", current_color, x))?;
+                    close_group(&mut groups, group_start, group_end, options, "")?;
+                }
+                pending_bg_start = None;
+                pending_unknown_start = None;
+                start_run = None;
+                continue;
+            }
+
+            let current_is_target = targets.matches(current_color);
+            let current_is_background = background.matches(x, current_color);
+
+            let Some(group_start) = active_group_start else {
+                if !current_is_target {
+                    start_run = None;
+                    continue;
+                }
+                let run_start = *start_run.get_or_insert(x);
+                if x + 1 - run_start >= options.min_start_run {
+                    active_group_start = Some(run_start);
+                    start_run = None;
+                    pending_bg_start = None;
+                    pending_unknown_start = None;
+                }
+                continue;
+            };
+
+            // Active-tab overhang at the group edge still belongs to the group.
+            if current_is_target || options.is_active_tab_color(current_color) {
+                pending_bg_start = None;
+                pending_unknown_start = None;
+                continue;
+            }
+
+            if current_is_background {
+                pending_unknown_start = None;
+                let bg_start = *pending_bg_start.get_or_insert(x);
+                let bg_width = x.saturating_sub(bg_start) + 1;
+                if bg_width >= options.min_bg_gap_width {
+                    close_group(&mut groups, group_start, bg_start, options, "")?;
+                    active_group_start = None;
+                    pending_bg_start = None;
+                }
+                continue;
+            }
+
+            // Neither group nor background color (favicons, text, other chrome).
+            if options.unknown_separator_width > 0 {
+                let unknown_start = *pending_unknown_start.get_or_insert(x);
+                let unknown_width = x.saturating_sub(unknown_start) + 1;
+                if unknown_width >= options.unknown_separator_width {
+                    // A background run directly before the unknown run still belongs to the gap.
+                    let group_end = pending_bg_start.unwrap_or(unknown_start);
+                    log_to_file(&format!(
+                        "Unknown color run of {}px at x={} treated as separator",
+                        unknown_width, unknown_start
+                    ))?;
+                    close_group(&mut groups, group_start, group_end, options, "")?;
+                    active_group_start = None;
+                    pending_bg_start = None;
+                    pending_unknown_start = None;
+                }
+            }
+        }
+    }
+    
+    // Handle case where cursor is in last group that extends to window edge
+    if let Some(group_start) = active_group_start {
+        close_group(&mut groups, group_start, sweep.length, options, "trailing ")?;
+    }
+
+    Ok(groups)
+}
+
+/// Factor from window-relative screen coordinates to capture pixels.
+///
+/// Normally derived from the capture width versus the reported window width;
+/// `forced` (`TABGROUP_HOVER_DPI_SCALE`) is used instead when that
+/// derivation is wrong.
+///
+/// `dpis` is the `(window, system)` DPI pair: the window DPI the rest of
+/// [`ScanGeometry`] comes from, and the [`system_dpi`]. When the window sits
+/// on a monitor whose DPI differs from the system DPI, the only scales that
+/// make sense are 1, their ratio and its inverse, so a detected ratio close
+/// to one of those is snapped to it exactly. Example: primary at
+/// 100%, Edge on a secondary at 150%. The capture includes the window frame
+/// and comes back 2880 px wide for a 1904 px client width, a detected 1.513
+/// instead of 1.5, which puts a cursor 1800 px into the strip 23 px too far
+/// right and onto the next group's chip; snapping restores 1.5. With a single
+/// monitor (or equal DPIs) the detected ratio is used as before.
+fn resolve_cursor_scale(
+    capture_width: u32,
+    window_width: u32,
+    dpis: Option<(u32, u32)>,
+    forced: Option<f64>,
+) -> (f64, &'static str) {
+    if let Some(scale) = forced {
+        return (scale, "override");
+    }
+    if window_width == 0 {
+        return (1.0, "default");
+    }
+    let detected = capture_width as f64 / window_width as f64;
+    match dpis {
+        Some((window_dpi, system_dpi)) if window_dpi != system_dpi => {
+            let factor = window_dpi as f64 / system_dpi as f64;
+            let nearest = [1.0, factor, 1.0 / factor]
+                .into_iter()
+                .min_by(|a, b| (detected - a).abs().total_cmp(&(detected - b).abs()))
+                .unwrap_or(detected);
+            if (detected - nearest).abs() <= MONITOR_SCALE_TOLERANCE {
+                (nearest, "monitor")
+            } else {
+                (detected, "detected")
+            }
+        }
+        _ => (detected, "detected"),
+    }
+}
+
+fn map_to_image(offset: i32, scale: f64) -> u32 {
+    (offset.max(0) as f64 * scale).round() as u32
+}
+
+/// Group color found at (or next to) the cursor on the scan line.
+#[derive(Clone, Copy)]
+struct CursorHit {
+    color: u32,
+    /// Horizontal distance from the cursor pixel; 0 is an exact hit.
+    offset: i32,
+}
+
+/// Proximity reach before and after the cursor at `dpi`. Both sides default
+/// to `TABGROUP_HOVER_PROXIMITY_RADIUS`; `TABGROUP_HOVER_PROXIMITY_LEFT` and
+/// `TABGROUP_HOVER_PROXIMITY_RIGHT` set them apart, so a cursor on the seam
+/// between two groups can be biased toward one of them. All are in 96-DPI
+/// pixels; a side of 0 does not look that way at all.
+fn proximity_reach(dpi: u32) -> (i32, i32) {
+    let radius = parse_u32_from_env("TABGROUP_HOVER_PROXIMITY_RADIUS", PROXIMITY_RADIUS as u32);
+    let side = |var_name: &str| {
+        let value = parse_u32_from_env(var_name, radius);
+        if value == 0 {
+            0
+        } else {
+            scale_for_dpi(value as f64, dpi).max(1) as i32
+        }
+    };
+    (side("TABGROUP_HOVER_PROXIMITY_LEFT"), side("TABGROUP_HOVER_PROXIMITY_RIGHT"))
+}
+
+/// Looks for a group color under the cursor on the scan line, up to
+/// `reach.0` pixels before the cursor and `reach.1` after it.
+///
+/// With `exact_first`, the pixel directly under the cursor is tried before
+/// the sweep, and the sweep then goes nearest-first, so the reported color is
+/// the one actually under the cursor whenever possible. Without it the sweep
+/// runs left to right as it always has.
+fn find_cursor_color(
+    capture: &RgbaImage,
+    cursor_t: u32,
+    sweep: &Sweep,
+    reach: (i32, i32),
+    targets: &Palette,
+    exact_first: bool,
+) -> Option<CursorHit> {
+    let (before, after) = reach;
+    let offsets: Vec<i32> = if exact_first {
+        std::iter::once(0)
+            .chain((1..=before.max(after)).flat_map(|d| {
+                (d <= before).then_some(-d).into_iter().chain((d <= after).then_some(d))
+            }))
+            .collect()
+    } else {
+        (-before..=after).collect()
+    };
+    offsets.into_iter().find_map(|offset| {
+        let check_t = cursor_t as i32 + offset;
+        if check_t < 0 {
+            return None;
+        }
+        sweep.sample(capture, check_t as u32)
+            .filter(|color| targets.matches(*color))
+            .map(|color| CursorHit { color, offset })
+    })
+}
+
+/// A captured browser window: its pixels plus the screen geometry they map to.
+pub struct Frame {
+    image: RgbaImage,
+    window: WindowInfo,
+}
+
+/// Where a detection run gets its pixels from.
+pub enum CaptureSource {
+    /// Resolve the browser window under the cursor and capture it.
+    Live,
+    /// Scan a frame captured elsewhere (an external capturer, a cached frame,
+    /// a PNG on disk). The cursor is read live unless one is supplied.
+    Injected { frame: Frame, cursor: Option<POINT> },
+}
+
+/// Counts clusters of color edges along the scan line, leaving out the caption
+/// buttons at the right. Every tab contributes its favicon, title and close
+/// button, while a plain title bar only has its icon and caption text, so the
+/// count is a cheap structural check for a tab strip.
+fn count_edge_clusters(capture: &RgbaImage, geometry: &ScanGeometry) -> u32 {
+    let caption_buttons = scale_for_dpi(CAPTION_BUTTONS_WIDTH, geometry.dpi);
+    let gap = scale_for_dpi(TAB_EDGE_CLUSTER_GAP, geometry.dpi).max(1);
+    let end = capture.width().saturating_sub(caption_buttons);
+    let mut clusters = 0;
+    let mut last_edge: Option<u32> = None;
+    let mut previous: Option<u32> = None;
+    for x in 0..end {
+        let Some(color) = get_pixel_color(capture, x, geometry.scan_y) else {
+            continue;
+        };
+        if previous.is_some_and(|prev| color_distance(prev, color) > TAB_EDGE_DISTANCE) {
+            if last_edge.is_none_or(|last| x - last > gap) {
+                clusters += 1;
+            }
+            last_edge = Some(x);
+        }
+        previous = Some(color);
+    }
+    clusters
+}
+
+/// Whether the structural check with `TABGROUP_HOVER_TAB_STRIP_MIN_REGIONS`
+/// (`0` disables it) finds too few edge clusters for a tab strip.
+fn lacks_tab_strip(capture: &RgbaImage, geometry: &ScanGeometry, min_regions: u32) -> Result<bool> {
+    if min_regions == 0 {
+        return Ok(false);
+    }
+    let regions = count_edge_clusters(capture, geometry);
+    log_to_file(&format!("Tab strip check: {} edge clusters (need {})", regions, min_regions))?;
+    Ok(regions < min_regions)
+}
+
+/// Point in time a detection should answer by, from `--deadline-ms`.
+///
+/// Checked between stages; once it has passed, the run stops at the next
+/// checkpoint and reports what it has as a partial result.
+#[derive(Clone, Copy)]
+pub struct Deadline(Option<Instant>);
+
+impl Deadline {
+    pub fn none() -> Self {
+        Deadline(None)
+    }
+
+    pub fn after_ms(ms: u64) -> Self {
+        Deadline(Some(Instant::now() + Duration::from_millis(ms)))
+    }
+
+    fn expired(&self) -> bool {
+        self.0.is_some_and(|at| Instant::now() >= at)
+    }
+}
+
+/// What to do when the window is being dragged or resized, from
+/// `TABGROUP_HOVER_WINDOW_MOTION`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MotionPolicy {
+    /// Capture right away, as before.
+    Ignore,
+    /// Report `WindowInMotion` if two quick reads of the geometry differ.
+    Skip,
+    /// Wait up to `TABGROUP_HOVER_MOTION_SETTLE_MS` for the geometry to settle.
+    Wait,
+}
+
+impl MotionPolicy {
+    fn from_env() -> Self {
+        match env::var("TABGROUP_HOVER_WINDOW_MOTION")
+            .map(|raw| raw.trim().to_lowercase())
+            .as_deref()
+        {
+            Ok("skip") => MotionPolicy::Skip,
+            Ok("wait") => MotionPolicy::Wait,
+            _ => MotionPolicy::Ignore,
+        }
+    }
+}
+
+enum WindowMotion {
+    /// The geometry could not be read.
+    Unknown,
+    /// Two consecutive reads agreed; `from` is the first read, `to` the last.
+    Settled { from: RECT, to: RECT },
+    Moving,
+}
+
+/// Samples the geometry every `interval` until two consecutive reads agree
+/// or `timeout` passes. A zero timeout compares just two reads.
+fn sample_motion(mut read: impl FnMut() -> Option<RECT>, interval: Duration, timeout: Duration) -> WindowMotion {
+    let Some(first) = read() else {
+        return WindowMotion::Unknown;
+    };
+    let started = Instant::now();
+    let mut previous = first;
+    loop {
+        thread::sleep(interval);
+        let Some(current) = read() else {
+            return WindowMotion::Unknown;
+        };
+        if current == previous {
+            return WindowMotion::Settled { from: first, to: current };
+        }
+        if started.elapsed() >= timeout {
+            return WindowMotion::Moving;
+        }
+        previous = current;
+    }
+}
+
+fn window_rect(id: u32) -> Option<RECT> {
+    let mut rect = RECT::default();
+    unsafe { GetWindowRect(HWND(id as isize), &mut rect) }
+        .as_bool()
+        .then_some(rect)
+}
+
+/// Identifies queries that must get the same answer: the same window in the
+/// same place and focus state, with the cursor in the same horizontal bucket.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct CacheKey {
+    window_id: u32,
+    x: i32,
+    y: i32,
+    width: u32,
+    foreground: isize,
+    bucket: i32,
+}
+
+/// Recent hover answers for `--serve`. The answer only changes when the
+/// cursor crosses a group boundary, so while it stays inside one bucket of
+/// the strip the capture and scan can be skipped.
+struct ResultCache {
+    ttl: Duration,
+    bucket_width: u32,
+    entries: HashMap<CacheKey, (Instant, u32, Reason)>,
+}
+
+impl ResultCache {
+    fn new(ttl: Duration, bucket_width: u32) -> Self {
+        Self {
+            ttl,
+            bucket_width: bucket_width.max(1),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Enabled by a non-zero `TABGROUP_HOVER_RESULT_CACHE_TTL_MS`.
+    fn from_env() -> Option<Self> {
+        let ttl_ms = parse_u32_from_env("TABGROUP_HOVER_RESULT_CACHE_TTL_MS", 0);
+        (ttl_ms > 0).then(|| {
+            Self::new(
+                Duration::from_millis(ttl_ms as u64),
+                parse_u32_from_env("TABGROUP_HOVER_RESULT_CACHE_BUCKET", 8),
+            )
+        })
+    }
+
+    fn key(&self, window: &WindowInfo, cursor: POINT, foreground: HWND) -> CacheKey {
+        CacheKey {
+            window_id: window.id,
+            x: window.x,
+            y: window.y,
+            width: window.width,
+            foreground: foreground.0,
+            bucket: (cursor.x - window.x).div_euclid(self.bucket_width as i32),
+        }
+    }
+
+    fn get(&self, key: &CacheKey, now: Instant) -> Option<(u32, Reason)> {
+        self.entries
+            .get(key)
+            .filter(|(at, _, _)| now.duration_since(*at) < self.ttl)
+            .map(|(_, index, reason)| (*index, *reason))
+    }
+
+    fn insert(&mut self, key: CacheKey, now: Instant, index: u32, reason: Reason) {
+        let ttl = self.ttl;
+        self.entries.retain(|_, (at, _, _)| now.duration_since(*at) < ttl);
+        self.entries.insert(key, (now, index, reason));
+    }
+}
+
+/// Last window enumeration, reused by long-running callers for checks in
+/// quick succession (a drag across the strip) instead of enumerating every
+/// window again. The entry is dropped once `ttl` has passed or another window
+/// comes to the foreground.
+struct WindowCache {
+    ttl: Duration,
+    last: Option<(Instant, isize, Vec<Window>)>,
+}
+
+impl WindowCache {
+    fn new(ttl: Duration) -> Self {
+        Self { ttl, last: None }
+    }
+
+    /// Enabled by a non-zero `TABGROUP_HOVER_WINDOW_CACHE_MS` (default 250).
+    fn from_env() -> Option<Self> {
+        let ttl_ms = parse_u32_from_env("TABGROUP_HOVER_WINDOW_CACHE_MS", WINDOW_CACHE_MS_DEFAULT);
+        (ttl_ms > 0).then(|| Self::new(Duration::from_millis(ttl_ms as u64)))
+    }
+
+    fn get(&mut self, now: Instant, foreground: HWND) -> Option<Vec<Window>> {
+        let ttl = self.ttl;
+        self.last = self
+            .last
+            .take()
+            .filter(|(at, hwnd, _)| now.duration_since(*at) < ttl && *hwnd == foreground.0);
+        self.last.as_ref().map(|(_, _, windows)| windows.clone())
+    }
+
+    fn insert(&mut self, now: Instant, foreground: HWND, windows: Vec<Window>) {
+        self.last = Some((now, foreground.0, windows));
+    }
+}
+
+/// Last group scan per window in `--serve`, keyed by a hash of everything the
+/// scan reads: the swept pixels and the background it compares them with. A
+/// capture whose hash matches reuses the groups instead of scanning again.
+#[derive(Default)]
+struct ScanMemo {
+    entries: HashMap<u32, (u64, Vec<(u32, u32)>)>,
+}
+
+impl ScanMemo {
+    fn get(&self, window_id: u32, hash: u64) -> Option<Vec<(u32, u32)>> {
+        self.entries
+            .get(&window_id)
+            .filter(|(stored, _)| *stored == hash)
+            .map(|(_, groups)| groups.clone())
+    }
+
+    fn insert(&mut self, window_id: u32, hash: u64, groups: Vec<(u32, u32)>) {
+        self.entries.insert(window_id, (hash, groups));
+    }
+}
+
+fn scan_input_hash(capture: &RgbaImage, sweep: &Sweep, background: &Background) -> u64 {
+    let samples = (0..sweep.length).map(|t| sweep.sample(capture, t).unwrap_or(u32::MAX));
+    let reference = background.reference.iter().flatten().copied();
+    fnv1a(
+        samples
+            .chain([u32::MAX])
+            .chain(background.candidates.iter().copied())
+            .chain([u32::MAX])
+            .chain(reference),
+    )
+}
+
+/// Post-processing of the hovered index, configured with a small JSON object
+/// in `TABGROUP_HOVER_INDEX_MAP` such as `{"ignore": [1], "offset": 2}`.
+/// Ignored raw indices report no group; the others are shifted by `offset`.
+#[derive(Debug, Default)]
+struct IndexMap {
+    ignore: Vec<u32>,
+    offset: i64,
+}
+
+impl IndexMap {
+    fn parse(raw: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(raw)?;
+        let object = value
+            .as_object()
+            .ok_or_else(|| anyhow::anyhow!("expected a JSON object, got {}", value))?;
+        let mut map = Self::default();
+        for (key, value) in object {
+            match key.as_str() {
+                "ignore" => {
+                    map.ignore = value
+                        .as_array()
+                        .ok_or_else(|| anyhow::anyhow!("'ignore' must be an array, got {}", value))?
+                        .iter()
+                        .map(|entry| {
+                            entry
+                                .as_u64()
+                                .filter(|index| *index > 0)
+                                .and_then(|index| u32::try_from(index).ok())
+                                .ok_or_else(|| anyhow::anyhow!("'ignore' entries must be group indices (1 or more), got {}", entry))
+                        })
+                        .collect::<Result<_>>()?;
+                }
+                "offset" => {
+                    map.offset = value
+                        .as_i64()
+                        .ok_or_else(|| anyhow::anyhow!("'offset' must be an integer, got {}", value))?;
+                }
+                other => anyhow::bail!("unknown key '{}', expected 'ignore' or 'offset'", other),
+            }
+        }
+        Ok(map)
+    }
+
+    /// The configured map, `None` when unset; an invalid value is an error.
+    fn from_env() -> Result<Option<Self>> {
+        match env::var("TABGROUP_HOVER_INDEX_MAP") {
+            Ok(raw) if !raw.trim().is_empty() => Self::parse(&raw)
+                .map(Some)
+                .map_err(|e| anyhow::anyhow!("Invalid TABGROUP_HOVER_INDEX_MAP: {}", e)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Final index for a raw hovered `index`, `None` when it is ignored or
+    /// shifted below 1. Index `0` (no group) is left alone.
+    fn apply(&self, index: u32) -> Option<u32> {
+        if index == 0 {
+            return Some(0);
+        }
+        if self.ignore.contains(&index) {
+            return None;
+        }
+        u32::try_from(index as i64 + self.offset).ok().filter(|index| *index > 0)
+    }
+}
+
+/// Applies the configured `IndexMap` to a finished detection. An invalid map
+/// is logged and skipped rather than failing the detection.
+fn map_index(mut detection: Detection) -> Result<Detection> {
+    let map = match IndexMap::from_env() {
+        Ok(Some(map)) => map,
+        Ok(None) => return Ok(detection),
+        Err(e) => {
+            error!("{}", e);
+            return Ok(detection);
+        }
+    };
+    match map.apply(detection.index) {
+        Some(index) => {
+            if index != detection.index {
+                log_to_file(&format!("Index map: {} -> {}", detection.index, index))?;
+            }
+            detection.index = index;
+            Ok(detection)
+        }
+        None => {
+            log_to_file(&format!("Index map ignores group {}", detection.index))?;
+            detection.index = 0;
+            Ok(detection.with_reason(Reason::IndexIgnored))
+        }
+    }
+}
+
+/// Screen rectangle of the strip where tab groups are searched for.
+fn strip_bounds(window: &WindowInfo, geometry: &ScanGeometry) -> RECT {
+    RECT {
+        left: window.x,
+        top: window.y,
+        right: window.x + window.width as i32,
+        bottom: window.y + geometry.strip_height() as i32,
+    }
+}
+
+fn is_point_in_rect(point: POINT, rect: &RECT) -> bool {
+    point.x >= rect.left && point.x < rect.right && point.y >= rect.top && point.y < rect.bottom
+}
+
+/// Screen rectangle of the vertical tab pane at the left of the window.
+fn pane_bounds(window: &WindowInfo, geometry: &ScanGeometry) -> RECT {
+    RECT {
+        left: window.x,
+        top: window.y,
+        right: window.x + geometry.pane_width.min(window.width) as i32,
+        bottom: window.y + window.height as i32,
+    }
+}
+
+/// Whether the cursor is where groups can be hit for `orientation`: the top
+/// strip, the vertical tab pane, or (in auto orientation) either.
+fn cursor_in_scan_area(cursor: POINT, window: &WindowInfo, geometry: &ScanGeometry, orientation: Orientation) -> bool {
+    let in_strip = || is_cursor_in_strip(cursor, &strip_bounds(window, geometry), geometry);
+    let in_pane = || is_point_in_rect(cursor, &pane_bounds(window, geometry));
+    match orientation {
+        Orientation::Horizontal => in_strip(),
+        Orientation::Vertical => in_pane(),
+        Orientation::Auto => in_strip() || in_pane(),
+    }
+}
+
+/// Whether the cursor is in the part of the strip where groups can be hit,
+/// i.e. inside `bounds` but below the top dead zone.
+fn is_cursor_in_strip(cursor: POINT, bounds: &RECT, geometry: &ScanGeometry) -> bool {
+    is_point_in_rect(cursor, bounds) && cursor.y >= bounds.top + geometry.top_dead_zone as i32
+}
+
+/// Runs hover detection against the browser window under the cursor, or
+/// against an injected frame.
+///
+/// With `enumerate` set, the cursor gates (inside the strip, on a group color)
+/// are skipped so the full group layout is reported wherever the cursor is.
+///
+/// `deadline` is checked before the capture and before the full group scan;
+/// a run that misses it returns early with `partial` set.
+///
+/// With a `cache`, live hover checks (not `enumerate`) first look for a fresh
+/// answer for the same window and cursor bucket, and store their own. With a
+/// `memo`, the group scan is skipped when the swept pixels are unchanged.
+fn detect_tab_groups(
+    source: CaptureSource,
+    enumerate: bool,
+    deadline: Deadline,
+    cache: Option<&mut ResultCache>,
+    memo: Option<&mut ScanMemo>,
+    window_cache: Option<&mut WindowCache>,
+) -> Result<Detection> {
+    let timestamp = Local::now().format("%Y%m%d_%H%M%S").to_string();
+    log_to_file(&format!("Starting hover detection at {}", timestamp))?;
+    let screenshot = screenshot_dir().join(format!("screenshot_{}", timestamp));
+    let mut cache_key = None;
+    // The window and strip height to capture again if the first frame is blank.
+    let mut recapture = None;
+    let mut captured_at_ms = None;
+
+    let (frame, cursor) = match source {
+        CaptureSource::Live => {
+            let cursor = get_cursor_pos()?;
+            log_to_file(&format!("Cursor position: x={}, y={}", cursor.x, cursor.y))?;
+
+            // Get all windows, reusing a recent enumeration if allowed
+            let foreground = unsafe { GetForegroundWindow() };
+            let windows = match window_cache {
+                Some(window_cache) => match window_cache.get(Instant::now(), foreground) {
+                    Some(windows) => {
+                        log_to_file(&format!("Reusing cached enumeration of {} windows", windows.len()))?;
+                        windows
+                    }
+                    None => {
+                        let windows = all_windows()?;
+                        window_cache.insert(Instant::now(), foreground, windows.clone());
+                        windows
+                    }
+                },
+                None => all_windows()?,
+            };
+            
+            // Log all windows for debugging
+            for window in &windows {
+                log_to_file(&format!(
+                    "Window state: id={}, title='{}', app_name='{}', focused={}", 
+                    window.id(), window.title(), window.app_name(), window.is_focused()
+                ))?;
+            }
+
+            // Normally a popup is resolved to its browser window below; on
+            // request, treat an open popup as "do not detect" instead.
+            let ignore_during_popup = parse_bool_from_env("TABGROUP_HOVER_IGNORE_DURING_POPUP", false);
+            if let Some(detection) = popup_detection(cursor, ignore_during_popup, || {
+                let handles = unsafe { [WindowFromPoint(cursor), GetForegroundWindow()] };
+                is_browser_popup(&windows, &handles)
+            }) {
+                log_to_file("Browser popup active, skipping detection")?;
+                return Ok(detection);
+            }
+
+            let focused_window = match resolve_browser_window(&windows, cursor) {
+                Ok(window) => window,
+                Err(e) => {
+                    log_to_file(&format!("Browser window resolution failed: {}", e))?;
+                    return Ok(Detection::empty(cursor).with_reason(Reason::NoBrowserWindow));
+                }
+            };
+            
+            log_to_file(&format!("Selected window for hover detection: '{}' ({})", 
+                focused_window.title(), focused_window.app_name()))?;
+            
+            // Check if it's a browser window by app or executable name
+            if !is_browser_window(focused_window, &browser_names()) {
+                log_to_file("Not a browser window")?;
+                return Ok(Detection::empty(cursor).with_reason(Reason::NotBrowser));
+            }
+
+            let mut window = WindowInfo::from_window(focused_window);
+            let policy = MotionPolicy::from_env();
+            if policy != MotionPolicy::Ignore {
+                let timeout = match policy {
+                    MotionPolicy::Wait => Duration::from_millis(
+                        parse_u32_from_env("TABGROUP_HOVER_MOTION_SETTLE_MS", MOTION_SETTLE_MS_DEFAULT) as u64,
+                    ),
+                    _ => Duration::ZERO,
+                };
+                match sample_motion(|| window_rect(window.id), MOTION_SAMPLE_INTERVAL, timeout) {
+                    WindowMotion::Moving => {
+                        log_to_file("Window is moving or resizing, skipping detection")?;
+                        return Ok(Detection {
+                            window: Some(window),
+                            ..Detection::empty(cursor).with_reason(Reason::WindowInMotion)
+                        });
+                    }
+                    WindowMotion::Settled { from, to } if from != to => {
+                        // Enumerated geometry predates the move; shift it along.
+                        log_to_file("Window settled after moving, adjusting geometry")?;
+                        window.x += to.left - from.left;
+                        window.y += to.top - from.top;
+                        window.width = (window.width as i32 + (to.right - to.left) - (from.right - from.left))
+                            .max(0) as u32;
+                    }
+                    _ => {}
+                }
+            }
+            let (dpi, _) = window_dpi(&window);
+            let geometry = ScanGeometry::for_dpi(dpi, GroupStyle::from_env());
+            // Skip the capture entirely when the answer can only be "no group".
+            if !enumerate && !cursor_in_scan_area(cursor, &window, &geometry, Orientation::from_env()) {
+                log_to_file("Cursor outside tab group area")?;
+                return Ok(Detection {
+                    window: Some(window),
+                    ..Detection::empty(cursor).with_reason(Reason::CursorOutside)
+                });
+            }
+
+            if let Some(cache) = cache.as_deref().filter(|_| !enumerate) {
+                let key = cache.key(&window, cursor, unsafe { GetForegroundWindow() });
+                if let Some((index, reason)) = cache.get(&key, Instant::now()) {
+                    log_to_file(&format!("Result cache hit: index={}, reason={:?}", index, reason))?;
+                    return Ok(Detection {
+                        index,
+                        window: Some(window),
+                        ..Detection::empty(cursor).with_reason(reason)
+                    });
+                }
+                cache_key = Some(key);
+            }
+
+            if deadline.expired() {
+                log_to_file("Deadline reached before capture")?;
+                return Ok(Detection {
+                    window: Some(window),
+                    ..Detection::empty(cursor).out_of_time()
+                });
+            }
+
+            captured_at_ms = Some(unix_millis_now());
+            let image = capture_strip(focused_window, geometry.strip_height())?;
+            recapture = Some((focused_window.clone(), geometry.strip_height()));
+            (Frame { image, window }, cursor)
+        }
+        CaptureSource::Injected { frame, cursor } => {
+            let cursor = match cursor {
+                Some(cursor) => cursor,
+                None => get_cursor_pos()?,
+            };
+            log_to_file(&format!(
+                "Using injected {}x{} frame at ({}, {}), cursor x={}, y={}",
+                frame.image.width(),
+                frame.image.height(),
+                frame.window.x,
+                frame.window.y,
+                cursor.x,
+                cursor.y
+            ))?;
+            (frame, cursor)
+        }
+    };
+
+    let mut memo = memo;
+    let window = frame.window.clone();
+    let mut detection = scan_frame(frame, cursor, enumerate, deadline, &screenshot, memo.as_deref_mut())?;
+    if let Some((live_window, strip_height)) = recapture {
+        let retries = parse_u32_from_env("TABGROUP_HOVER_BLANK_RETRIES", BLANK_RETRIES_DEFAULT);
+        let delay = parse_u32_from_env("TABGROUP_HOVER_BLANK_RETRY_MS", BLANK_RETRY_DELAY_MS_DEFAULT);
+        detection = retry_blank_strip(detection, retries, Duration::from_millis(delay as u64), deadline, || {
+            captured_at_ms = Some(unix_millis_now());
+            let frame = Frame { image: capture_strip(&live_window, strip_height)?, window: window.clone() };
+            scan_frame(frame, cursor, enumerate, deadline, &screenshot, memo.as_deref_mut())
+        })?;
+    }
+    detection.captured_at_ms = captured_at_ms;
+    let detection = map_index(detection)?;
+    if let (Some(cache), Some(key)) = (cache, cache_key) {
+        if !detection.partial {
+            cache.insert(key, Instant::now(), detection.index, detection.reason);
+        }
+    }
+    Ok(detection)
+}
+
+/// Scans a fresh capture with `rescan`, `delay` apart and up to `retries`
+/// times, while `detection` came from a blank strip. A live capture that came
+/// out blank may have caught the browser mid-repaint (during a drag, say), so
+/// it is looked at again before answering "no group".
+fn retry_blank_strip(
+    mut detection: Detection,
+    retries: u32,
+    delay: Duration,
+    deadline: Deadline,
+    mut rescan: impl FnMut() -> Result<Detection>,
+) -> Result<Detection> {
+    let first = (detection.index, detection.reason);
+    for attempt in 1..=retries {
+        if !detection.blank_strip || deadline.expired() {
+            break;
+        }
+        log_to_file(&format!(
+            "Blank tab strip, re-capturing in {} ms (attempt {}/{})",
+            delay.as_millis(),
+            attempt,
+            retries
+        ))?;
+        thread::sleep(delay);
+        detection = rescan()?;
+        if (detection.index, detection.reason) != first {
+            log_to_file(&format!(
+                "Re-capture changed the outcome: index={}, reason={:?} (was index={}, reason={:?})",
+                detection.index, detection.reason, first.0, first.1
+            ))?;
+        }
+    }
+    Ok(detection)
+}
+
+/// Runs the group scan over `image` as the capture of a browser window at the
+/// screen origin, with the cursor at `cursor` in image pixels, and nothing
+/// else: no window lookup, capture or display query. This is `--input-image`
+/// as a function, for replaying saved strip captures in regression checks;
+/// `TABGROUP_HOVER_DPI` stands in for the DPI of the window they came from.
+pub fn scan_image(image: &RgbaImage, cursor: (i32, i32), config: &Config) -> Result<DetectionResult> {
+    let window = WindowInfo {
+        id: 0,
+        title: String::new(),
+        app_name: "input-image".to_string(),
+        x: 0,
+        y: 0,
+        width: image.width(),
+        height: image.height(),
+    };
+    let source = CaptureSource::Injected {
+        frame: Frame { image: image.clone(), window },
+        cursor: Some(POINT { x: cursor.0, y: cursor.1 }),
+    };
+    let deadline = config.deadline_ms.map_or_else(Deadline::none, Deadline::after_ms);
+    with_verbose(config.verbose, || Ok(detect(source, config.enumerate, deadline)?.into()))
+}
+
+/// Options for [`detect_hovered_group`]. Everything else is configured the
+/// same way as for the CLI, through the `TABGROUP_HOVER_*` variables.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Config {
+    /// Stop at the next checkpoint once this many milliseconds have passed
+    /// and report a partial result, like `--deadline-ms`.
+    pub deadline_ms: Option<u64>,
+    /// Scan all groups even when the cursor is not on one, like `--json`.
+    pub enumerate: bool,
+    /// Write the detailed log and screenshots for this call, like
+    /// `TABGROUP_HOVER_DETECTOR_VERBOSE` does for the whole process.
+    pub verbose: bool,
+}
+
+/// One detected tab group, left to right.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GroupBounds {
+    /// Span along the scan line in capture pixels (end exclusive).
+    pub start: u32,
+    pub end: u32,
+    /// Most common group color in the span, as `0xRRGGBB`.
+    pub color: Option<u32>,
+    /// Bounding box (left, top, right, bottom) in window coordinates.
+    pub window_box: Option<(i32, i32, i32, i32)>,
+    /// Whether the group is collapsed; `None` when that could not be judged.
+    pub collapsed: Option<bool>,
+}
+
+impl GroupBounds {
+    /// Length of the span along the scan line, in capture pixels.
+    pub fn width(&self) -> u32 {
+        self.end - self.start
+    }
+
+    /// `window_box` moved to screen coordinates by the window's top left.
+    pub fn screen_box(&self, window: &WindowInfo) -> Option<(i32, i32, i32, i32)> {
+        self.window_box
+            .map(|(left, top, right, bottom)| (left + window.x, top + window.y, right + window.x, bottom + window.y))
+    }
+}
+
+/// Result of [`detect_hovered_group`].
+#[derive(Clone, Debug)]
+pub struct DetectionResult {
+    /// 1-based index of the hovered group, 0 for none.
+    pub index: u32,
+    pub reason: Reason,
+    /// The deadline expired before the full scan; `index` is not reliable.
+    pub partial: bool,
+    /// Group color under the cursor, as `0xRRGGBB`.
+    pub cursor_color: Option<u32>,
+    pub groups: Vec<GroupBounds>,
+    /// The entry of `groups` that `index` reports, for sizing an overlay;
+    /// [`GroupBounds::screen_box`] places it on screen.
+    pub hovered: Option<GroupBounds>,
+    /// The browser window that was examined, if one was found.
+    pub window: Option<WindowInfo>,
+    /// Unix time in milliseconds of the capture that was scanned, if any.
+    pub captured_at_ms: Option<u64>,
+}
+
+impl From<Detection> for DetectionResult {
+    fn from(detection: Detection) -> Self {
+        let groups = detection
+            .groups
+            .iter()
+            .enumerate()
+            .map(|(i, (start, end))| GroupBounds {
+                start: *start,
+                end: *end,
+                color: detection.group_colors.get(i).copied().flatten(),
+                window_box: detection
+                    .group_boxes
+                    .get(i)
+                    .copied()
+                    .flatten()
+                    .map(|b| (b.left, b.top, b.right, b.bottom)),
+                collapsed: detection.group_collapsed.get(i).copied().flatten(),
+            })
+            .collect::<Vec<_>>();
+        Self {
+            hovered: detection.hovered_group().map(|i| groups[i]),
+            index: detection.index,
+            reason: detection.reason,
+            partial: detection.partial,
+            cursor_color: detection.cursor_color.map(|hit| hit.color),
+            groups,
+            window: detection.window,
+            captured_at_ms: detection.captured_at_ms,
+        }
+    }
+}
+
+/// Makes the process per-monitor DPI aware, which detection relies on for
+/// window geometry and captures to agree. Call once at startup.
+pub fn enable_dpi_awareness() -> Result<()> {
+    unsafe {
+        SetProcessDpiAwareness(PROCESS_PER_MONITOR_DPI_AWARE)
+            .map_err(|e| anyhow::anyhow!("Failed to set DPI awareness: {}", e))
+    }
+}
+
+/// Detects the tab group under the cursor in the foreground browser window,
+/// for callers that link the detector instead of running the executable.
+/// Results are published (result file, toast) as for a CLI run.
+pub fn detect_hovered_group(config: &Config) -> Result<DetectionResult> {
+    let deadline = config.deadline_ms.map_or_else(Deadline::none, Deadline::after_ms);
+    let detection = with_verbose(config.verbose, || detect(CaptureSource::Live, config.enumerate, deadline))?;
+    publish_result(&detection);
+    notify_toast(&detection);
+    Ok(detection.into())
+}
+
+/// Caches kept across [`Session::detect_hovered_group`] calls by a caller that
+/// links the detector and checks repeatedly, like `--serve` does: the recent
+/// window enumeration and the last group scan per window.
+pub struct Session {
+    window_cache: Option<WindowCache>,
+    memo: ScanMemo,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self {
+            window_cache: WindowCache::from_env(),
+            memo: ScanMemo::default(),
+        }
+    }
+
+    /// Same as [`detect_hovered_group`], reusing this session's caches.
+    pub fn detect_hovered_group(&mut self, config: &Config) -> Result<DetectionResult> {
+        let deadline = config.deadline_ms.map_or_else(Deadline::none, Deadline::after_ms);
+        let detection = with_verbose(config.verbose, || {
+            detect_tab_groups(
+                CaptureSource::Live,
+                config.enumerate,
+                deadline,
+                None,
+                Some(&mut self.memo),
+                self.window_cache.as_mut(),
+            )
+        })?;
+        publish_result(&detection);
+        notify_toast(&detection);
+        Ok(detection.into())
+    }
+}
+
+/// One detection run from `source` without the cross-run caches `serve` keeps.
+pub fn detect(source: CaptureSource, enumerate: bool, deadline: Deadline) -> Result<Detection> {
+    detect_tab_groups(source, enumerate, deadline, None, None, None)
+}
+
+/// 1-based index of the group in `groups` containing `cursor_t`, 0 for none.
+fn hovered_index(groups: &[(u32, u32)], cursor_t: Option<u32>) -> u32 {
+    cursor_t
+        .and_then(|t| groups.iter().position(|(start, end)| t >= *start && t < *end))
+        .map_or(0, |i| i as u32 + 1)
+}
+
+/// Picks the scan line whose answer wins, from each line's hovered index and
+/// whether the cursor pixel itself matched a group color on it: the first
+/// line with a majority index, else the first line with an exact cursor
+/// match, else the main line (the first).
+fn vote_scan_lines(votes: &[(u32, bool)]) -> usize {
+    let majority = votes
+        .iter()
+        .position(|(index, _)| votes.iter().filter(|(other, _)| other == index).count() * 2 > votes.len());
+    majority
+        .or_else(|| votes.iter().position(|(_, exact)| *exact))
+        .unwrap_or(0)
+}
+
+/// Locates the groups in a captured frame and the one under `cursor`
+/// (screen coordinates, mapped into the frame via its window geometry).
+/// Verbose scans save their annotated capture as `screenshot` with a `.png`
+/// extension and its sidecar with `.json`.
+fn scan_frame(
+    frame: Frame,
+    cursor: POINT,
+    enumerate: bool,
+    deadline: Deadline,
+    screenshot: &std::path::Path,
+    memo: Option<&mut ScanMemo>,
+) -> Result<Detection> {
+    let Frame { image: mut capture, window } = frame;
+    let window_offline = window.is_offline();
+    let style = GroupStyle::from_env();
+    let (dpi, dpi_source) = window_dpi(&window);
+    let mut geometry = ScanGeometry::for_dpi(dpi, style);
+    log_to_file(&format!(
+        "Window DPI: {} ({}), scale {:.2}: strip height {}, scan line y={} ({:?} style), proximity {:?}",
+        geometry.dpi,
+        dpi_source,
+        geometry.scale(),
+        geometry.vertical_threshold,
+        geometry.scan_y,
+        style,
+        geometry.proximity
+    ))?;
+    let mut scan_y = geometry.scan_y;
+    let bounds = strip_bounds(&window, &geometry);
+    let orientation = Orientation::from_env();
+    // Check if cursor is within tab group area (either one in auto orientation)
+    let mut cursor_in_area = cursor_in_scan_area(cursor, &window, &geometry, orientation);
+    let mut detection = Detection {
+        window: Some(window),
+        ..Detection::empty(cursor)
+    };
+    
+    log_to_file(&format!("Window bounds: left={}, top={}, right={}, bottom={}", 
+        bounds.left, bounds.top, bounds.right, bounds.bottom))?;
+    
+    if !cursor_in_area {
+        log_to_file("Cursor outside tab group area")?;
+        if !enumerate {
+            return Ok(detection.with_reason(Reason::CursorOutside));
+        }
+    }
+
+    let transform = if window_offline { ColorTransform::None } else { active_color_transform() };
+    let filter_unrecovered = undo_color_transform(
+        &mut capture,
+        transform,
+        parse_bool_from_env("TABGROUP_HOVER_UNDO_COLOR_FILTER", true),
+    )?;
+
+    let min_tab_regions = parse_u32_from_env("TABGROUP_HOVER_TAB_STRIP_MIN_REGIONS", 0);
+    if lacks_tab_strip(&capture, &geometry, min_tab_regions)? {
+        return Ok(detection.with_reason(Reason::NoTabStrip));
+    }
+
+    let profile = if window_offline { None } else { display_color_profile() };
+    if let Some(profile) = profile.as_deref().filter(|p| !is_srgb_profile(p)) {
+        log_to_file(&format!(
+            "Non-sRGB display color profile active: {} (group color tolerance {})",
+            profile,
+            palette_tolerance(Some(profile))
+        ))?;
+    }
+    let theme = Theme::resolve(&capture);
+    log_to_file(&format!("Browser theme: {:?}", theme))?;
+    let app_name = detection.window.as_ref().map_or("", |w| w.app_name.as_str());
+    log_to_file(&format!("Browser palette: {}", browser_palette(app_name).name))?;
+    let targets = Palette::from_env(profile.as_deref(), theme, app_name);
+    let vertical = match orientation {
+        Orientation::Horizontal => false,
+        Orientation::Vertical => true,
+        Orientation::Auto => {
+            let has_group_color =
+                |line: Sweep| (0..line.length).any(|t| line.sample(&capture, t).is_some_and(|c| targets.matches(c)));
+            !has_group_color(Sweep::horizontal(capture.width(), scan_y))
+                && has_group_color(Sweep::vertical(geometry.pane_scan_x, capture.height()))
+        }
+    };
+    if vertical {
+        log_to_file(&format!("Vertical tabs: sweeping down column x={}", geometry.pane_scan_x))?;
+    }
+    if orientation == Orientation::Auto {
+        let resolved = if vertical { Orientation::Vertical } else { Orientation::Horizontal };
+        cursor_in_area = detection
+            .window
+            .as_ref()
+            .is_some_and(|w| cursor_in_scan_area(cursor, w, &geometry, resolved));
+        if !cursor_in_area && !enumerate {
+            log_to_file("Cursor outside tab group area")?;
+            return Ok(detection.with_reason(Reason::CursorOutside));
+        }
+    }
+    let line_preset = |scan_y| {
+        if vertical {
+            Sweep::vertical(geometry.pane_scan_x, capture.height())
+        } else {
+            Sweep::horizontal(capture.width(), scan_y)
+        }
+    };
+
+    let theme_color = detection.window.as_ref().and_then(|w| window_theme_color(w.id));
+    if let Some(color) = theme_color {
+        log_to_file(&format!("Window theme color #{:06X} used as background", color))?;
+    }
+    let background_candidates = resolve_background_candidates(
+        &capture,
+        &line_preset(scan_y),
+        &targets,
+        theme_color,
+        theme,
+        // The caption buttons only border the horizontal strip.
+        if vertical { 0..0 } else { background_sample_area(capture.width(), geometry.dpi) },
+    );
+    let options = ScanOptions::from_env();
+    let reference = detection
+        .window
+        .as_ref()
+        .filter(|_| !vertical)
+        .and_then(|w| load_reference_row(&w.app_name, capture.width(), scan_y));
+    if reference.is_some() {
+        log_to_file("Using calibrated titlebar reference as background")?;
+    }
+    let mut background = Background::new(background_candidates, reference);
+    log_to_file(&format!(
+        "Using {} target colors and {} background candidates: [{}], mode={:?}, min_group_width={}, min_bg_gap_width={}, unknown_separator_width={}",
+        targets.len(),
+        background.candidates.len(),
+        background.candidates
+            .iter()
+            .map(|c| format!("#{:06X}", c))
+            .collect::<Vec<_>>()
+            .join(", "),
+        options.mode,
+        options.min_group_width,
+        options.min_bg_gap_width,
+        options.unknown_separator_width
+    ))?;
+    
+    // Convert cursor position to image coordinates
+    let system = detection.window.as_ref().and_then(system_dpi);
+    if let Some(system) = system {
+        log_to_file(&format!("Cursor mapping: window DPI {} (system {})", geometry.dpi, system))?;
+    }
+    let (scale, scale_source) = geometry.cursor_scale(
+        capture.width(),
+        detection.window.as_ref().map_or(0, |w| w.width),
+        system,
+    );
+    log_to_file(&format!("Cursor scale: {:.3} ({})", scale, scale_source))?;
+    let cursor_x = map_to_image(cursor.x - bounds.left, scale);
+    let cursor_y = map_to_image(cursor.y - bounds.top, scale);
+
+    // With wrapped tabs, scan the row under the cursor rather than the first.
+    let mut between_rows = false;
+    if geometry.tab_rows > 1 && !vertical {
+        let rows = strip_tab_rows(&capture, &geometry, &background);
+        log_to_file(&format!("Tab rows: {:?}", rows))?;
+        detection.row_count = rows.len() as u32;
+        if rows.len() > 1 {
+            let hovered = rows
+                .iter()
+                .position(|(top, bottom)| cursor_y >= *top && cursor_y < *bottom);
+            between_rows = hovered.is_none();
+            let row = hovered.unwrap_or(0);
+            scan_y = row_scan_y(rows[row], style, &geometry);
+            if scan_y != geometry.scan_y {
+                // The calibrated reference was taken on the single-row scan line.
+                background.reference = None;
+            }
+            geometry.scan_y = scan_y;
+            detection.row = Some(row as u32 + 1);
+            log_to_file(&format!(
+                "Scanning tab row {} of {} at y={}{}",
+                row + 1,
+                rows.len(),
+                scan_y,
+                if between_rows { " (cursor between rows)" } else { "" }
+            ))?;
+        }
+    }
+    
+    let preset = line_preset(scan_y);
+    let sweep = Sweep::from_env(capture.width(), capture.height(), preset);
+    if sweep != preset {
+        log_to_file(&format!("Custom sweep: {:?}", sweep))?;
+        // The calibrated reference is indexed by x on the default scan line.
+        background.reference = None;
+    }
+    // Spans and the cursor are compared in positions along the sweep.
+    let cursor_t = sweep.position_of(cursor_x, cursor_y);
+    // Further scan lines alongside the main one, voting on the hovered group.
+    let vote_sweeps: Vec<Sweep> = geometry
+        .scan_line_offsets()
+        .into_iter()
+        .filter(|offset| *offset != 0)
+        .filter_map(|offset| sweep.offset_across(offset))
+        .collect();
+
+    // A window over the strip leaves no background on the scan line, and its
+    // content would be scanned as groups; better to report no group at all.
+    let share = background_share(&capture, &sweep, &background);
+    let min_share = min_background_share();
+    log_to_file(&format!(
+        "Background pixels on the scan line: {:.1}% (need {:.1}%)",
+        share * 100.0,
+        min_share * 100.0
+    ))?;
+    if share < min_share {
+        log_to_file("Tab strip looks occluded, skipping the group scan")?;
+        return Ok(detection.with_reason(Reason::StripOccluded));
+    }
+
+    // Save initial screenshot before color detection if in verbose mode; in
+    // change-only mode just the final one is compared and saved.
+    if is_verbose() && !parse_bool_from_env("TABGROUP_HOVER_SCREENSHOT_ON_CHANGE", false) {
+        let sidecar = screenshot_sidecar(&detection, &sweep, (cursor_x, cursor_y), &[], None);
+        save_screenshot(&capture, &geometry, &sweep, cursor_x, cursor_y, &[], &[], None, screenshot, &sidecar)?;
+    }
+    
+    // Check if cursor is hovering over a target color (check at scan_y height)
+    let exact_first = parse_bool_from_env("TABGROUP_HOVER_EXACT_PIXEL_FIRST", true);
+    let cursor_hit = cursor_t.filter(|_| !between_rows).and_then(|t| {
+        std::iter::once(&sweep)
+            .chain(&vote_sweeps)
+            .find_map(|line| find_cursor_color(&capture, t, line, geometry.proximity, &targets, exact_first))
+    });
+    let quantize = Quantize::from_env();
+    let found_target_color = cursor_hit.is_some();
+    if let Some(mut hit) = cursor_hit {
+        log_to_file(&format!(
+            "Found target color #{:06x} at x-offset {}{}",
+            hit.color,
+            hit.offset,
+            if hit.offset == 0 { " (exact cursor pixel)" } else { "" }
+        ))?;
+        hit.color = quantize.apply(hit.color, &targets.colors);
+        detection.cursor_color = Some(hit);
+    }
+    
+    detection.blank_strip = !found_target_color
+        && cursor_in_area
+        && share >= BLANK_STRIP_SHARE
+        && !(0..sweep.length).any(|t| sweep.sample(&capture, t).is_some_and(|c| targets.matches(c)));
+
+    // A miss under an active filter is most likely caused by the filter.
+    let miss_reason = |reason| if filter_unrecovered { Reason::AccessibilityFilterActive } else { reason };
+
+    if !found_target_color {
+        log_to_file("Not hovering on a tab group")?;
+        if !enumerate {
+            return Ok(detection.with_reason(miss_reason(Reason::NotOnGroup)));
+        }
+    }
+
+    // The cursor check above is the coarse answer; the full scan is what a
+    // tight deadline gives up.
+    if deadline.expired() {
+        log_to_file("Deadline reached before group scan, returning partial result")?;
+        return Ok(detection.out_of_time());
+    }
+
+    log_to_file(&format!("Checking tab groups at cursor x={}", cursor_x))?;
+    
+    let scan_started = Instant::now();
+    let window_id = detection.window.as_ref().map_or(0, |w| w.id);
+    let memo_hash = memo.as_ref().map(|_| scan_input_hash(&capture, &sweep, &background));
+    let memoized = memo
+        .as_deref()
+        .zip(memo_hash)
+        .and_then(|(memo, hash)| memo.get(window_id, hash));
+    let groups = match memoized {
+        Some(groups) => {
+            log_to_file(&format!("Swept pixels unchanged, reusing {} memoized groups", groups.len()))?;
+            groups
+        }
+        None => {
+            let groups = scan_tab_groups(&capture, &sweep, &targets, &background, &options)?;
+            log_to_file(&format!(
+                "Scanned {} px row in {:?} ({} distinct un-blended colors memoized)",
+                sweep.length,
+                scan_started.elapsed(),
+                targets.fuzzy_cache.borrow().len()
+            ))?;
+            if let (Some(memo), Some(hash)) = (memo, memo_hash) {
+                memo.insert(window_id, hash, groups.clone());
+            }
+            groups
+        }
+    };
+
+    let (sweep, groups) = if vote_sweeps.is_empty() || cursor_t.is_none() {
+        (sweep, groups)
+    } else {
+        // The calibrated reference only describes the main line.
+        let vote_background = Background::new(background.candidates.clone(), None);
+        let mut lines = vec![(sweep, groups)];
+        for line in vote_sweeps {
+            lines.push((line, scan_tab_groups(&capture, &line, &targets, &vote_background, &options)?));
+        }
+        let votes: Vec<(u32, bool)> = lines
+            .iter()
+            .map(|(line, groups)| {
+                let exact = cursor_t
+                    .and_then(|t| line.sample(&capture, t))
+                    .is_some_and(|color| targets.matches(color));
+                (hovered_index(groups, cursor_t), exact)
+            })
+            .collect();
+        let winner = vote_scan_lines(&votes);
+        log_to_file(&format!("Scan line votes (index, exact cursor match): {:?}, using line {}", votes, winner))?;
+        lines.swap_remove(winner)
+    };
+
+    detection.band = measure_band(&capture, &sweep, &groups, &targets);
+    if let Some((top, bottom)) = detection.band {
+        let height = (bottom - top) as f64 / geometry.scale();
+        let density = Density::from_band_height(height);
+        log_to_file(&format!("Group band y={}..{} ({:.1} px at 96 DPI), density {:?}", top, bottom, height, density))?;
+        detection.density = Some(density);
+    }
+    detection.group_colors = groups
+        .iter()
+        .map(|span| group_color(&capture, &sweep, *span, &targets, quantize))
+        .collect();
+    // Back from capture pixels to window coordinates, the inverse of map_to_image.
+    let unscale = |v: i32| (v as f64 / scale).round() as i32;
+    detection.group_boxes = groups
+        .iter()
+        .map(|span| {
+            group_box(&sweep, *span, detection.band).map(|b| RECT {
+                left: unscale(b.left),
+                top: unscale(b.top),
+                right: unscale(b.right),
+                bottom: unscale(b.bottom),
+            })
+        })
+        .collect();
+    // The group the hit below will report, for the annotated captures.
+    let hovered = cursor_t
+        .filter(|_| cursor_in_area && found_target_color)
+        .and_then(|t| groups.iter().position(|(start, end)| t >= *start && t < *end));
+
+    if is_verbose() && screenshot_wanted(&detection_signature(cursor_t.unwrap_or(u32::MAX), &groups))? {
+        let sidecar = screenshot_sidecar(&detection, &sweep, (cursor_x, cursor_y), &groups, hovered);
+        save_screenshot(
+            &capture,
+            &geometry,
+            &sweep,
+            cursor_x,
+            cursor_y,
+            &groups,
+            &detection.group_colors,
+            hovered,
+            screenshot,
+            &sidecar,
+        )?;
+    }
+
+    let max_ratio = collapsed_max_ratio();
+    detection.group_collapsed = groups
+        .iter()
+        .enumerate()
+        .map(|(i, span)| group_collapsed(*span, i, &options, detection.band, max_ratio))
+        .collect();
+    detection.groups = groups;
+    detection.capture = Some(ScannedCapture {
+        image: capture,
+        geometry,
+        sweep,
+        cursor_x,
+        cursor_y,
+        hovered,
+    });
+    if !cursor_in_area {
+        return Ok(detection.with_reason(Reason::CursorOutside));
+    }
+    if !found_target_color {
+        return Ok(detection.with_reason(miss_reason(Reason::NotOnGroup)));
+    }
+
+    for (index, (start, end)) in detection.groups.iter().enumerate() {
+        if cursor_t.is_some_and(|t| t >= *start && t < *end) {
+            let group_index = (index + 1) as u32;
+            log_to_file(&format!(
+                "Cursor in accepted group {} (range {}..{})",
+                group_index, start, end
+            ))?;
+            detection.index = group_index;
+            detection.collapsed = detection.group_collapsed.get(index).copied().flatten();
+            return Ok(detection.with_reason(Reason::Hit));
+        }
+    }
+
+    log_to_file("No accepted tab group found at cursor position")?;
+    Ok(detection.with_reason(miss_reason(Reason::NoGroupAtCursor)))
+}
+
+fn parse_point(raw: &str) -> Option<POINT> {
+    let (x, y) = raw.split_once(',')?;
+    Some(POINT {
+        x: x.trim().parse().ok()?,
+        y: y.trim().parse().ok()?,
+    })
+}
+
+/// Builds an injected frame from `--input-image <png>`, placed on screen at
+/// `--window-origin x,y` (default `0,0`) with an optional `--cursor x,y`.
+pub fn capture_source_from_args(args: &[String]) -> Result<CaptureSource> {
+    let flag_value = |flag: &str| {
+        args.iter()
+            .position(|arg| arg == flag)
+            .and_then(|i| args.get(i + 1))
+    };
+    let Some(path) = flag_value("--input-image") else {
+        return Ok(CaptureSource::Live);
+    };
+    let parse_flag_point = |flag: &str| -> Result<Option<POINT>> {
+        flag_value(flag)
+            .map(|raw| {
+                parse_point(raw).ok_or_else(|| anyhow::anyhow!("Invalid {} value '{}', expected x,y", flag, raw))
+            })
+            .transpose()
+    };
+    let origin = parse_flag_point("--window-origin")?.unwrap_or_default();
+    let cursor = parse_flag_point("--cursor")?;
+    let image = image::open(path)?.to_rgba8();
+    let window = WindowInfo {
+        id: 0,
+        title: path.clone(),
+        app_name: "input-image".to_string(),
+        x: origin.x,
+        y: origin.y,
+        width: image.width(),
+        height: image.height(),
+    };
+    Ok(CaptureSource::Injected {
+        frame: Frame { image, window },
+        cursor,
+    })
+}
+
+fn swap_red_blue(color: u32) -> u32 {
+    ((color & 0xFF) << 16) | (color & 0x00FF00) | ((color >> 16) & 0xFF)
+}
+
+/// Compares a captured pixel with the same pixel read through GDI. A match
+/// only after swapping red and blue means the capture is in BGR order.
+fn check_pixel_format(captured: u32, reference: u32) -> Result<()> {
+    if color_matches(captured, reference, TARGET_COLOR_TOLERANCE) {
+        return Ok(());
+    }
+    if color_matches(swap_red_blue(captured), reference, TARGET_COLOR_TOLERANCE) {
+        anyhow::bail!(
+            "Capture pixel format mismatch: captured #{:06X} but the screen shows #{:06X}; \
+             red and blue channels are swapped (BGR capture read as RGB)",
+            captured,
+            reference
+        );
+    }
+    anyhow::bail!(
+        "Capture pixel format mismatch: captured #{:06X} but the screen shows #{:06X}",
+        captured,
+        reference
+    )
+}
+
+/// Screen pixel read through GDI, as 0xRRGGBB.
+fn screen_pixel(x: i32, y: i32) -> Option<u32> {
+    unsafe {
+        let hdc = GetDC(HWND(0));
+        if hdc.is_invalid() {
+            return None;
+        }
+        let pixel = GetPixel(hdc, x, y).0;
+        ReleaseDC(HWND(0), hdc);
+        // CLR_INVALID; COLORREF is 0x00BBGGRR
+        (pixel != u32::MAX).then(|| swap_red_blue(pixel))
+    }
+}
+
+/// `--verify-capture`: checks that captured pixels decode to the colors on
+/// screen, using scan-line pixels whose red and blue differ enough that a
+/// channel swap cannot go unnoticed.
+pub fn verify_capture() -> Result<()> {
+    let cursor = get_cursor_pos()?;
+    let windows = all_windows()?;
+    let window = resolve_browser_window(&windows, cursor)?;
+    let capture = capture_window(window)?;
+    let info = WindowInfo::from_window(window);
+    let (dpi, _) = window_dpi(&info);
+    let geometry = ScanGeometry::for_dpi(dpi, GroupStyle::from_env());
+    let scan_y = geometry.scan_y;
+    let (scale, _) = geometry.cursor_scale(capture.width(), info.width, system_dpi(&info));
+
+    let samples: Vec<(u32, u32)> = (0..capture.width())
+        .filter_map(|x| get_pixel_color(&capture, x, scan_y).map(|color| (x, color)))
+        .filter(|(_, color)| {
+            let red = (color >> 16) & 0xFF;
+            let blue = color & 0xFF;
+            red.abs_diff(blue) > u32::from(TARGET_COLOR_TOLERANCE) * 2
+        })
+        .step_by(7)
+        .take(5)
+        .collect();
+    if samples.is_empty() {
+        anyhow::bail!("No pixel on the scan line distinguishes red from blue; cannot verify capture format");
+    }
+
+    let mut last_error = None;
+    for (x, captured) in samples {
+        let screen_x = info.x + (x as f64 / scale).round() as i32;
+        let screen_y = info.y + (scan_y as f64 / scale).round() as i32;
+        let Some(reference) = screen_pixel(screen_x, screen_y) else {
+            continue;
+        };
+        match check_pixel_format(captured, reference) {
+            Ok(()) => {
+                log_to_file(&format!(
+                    "Capture pixel format verified at ({}, {}): #{:06X}",
+                    screen_x, screen_y, captured
+                ))?;
+                return Ok(());
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("Could not read reference pixels from the screen")))
+}
+
+/// Number of scan-line pixels matching each palette color, in palette order.
+fn palette_presence(img: &RgbaImage, scan_y: u32, palette: &[u32]) -> Vec<(u32, u32)> {
+    let row: Vec<u32> = (0..img.width())
+        .filter_map(|x| get_pixel_color(img, x, scan_y))
+        .collect();
+    palette
+        .iter()
+        .map(|target| {
+            let hits = row
+                .iter()
+                .filter(|color| color_matches(**color, *target, TARGET_COLOR_TOLERANCE))
+                .count() as u32;
+            (*target, hits)
+        })
+        .collect()
+}
+
+/// Prints the effective configuration, flags malformed settings, and checks
+/// which palette colors actually occur on the browser's scan line right now.
+pub fn check_config() -> Result<bool> {
+    let options = ScanOptions::from_env();
+    let mut ok = true;
+
+    println!("Effective configuration:");
+    println!("  detection_mode = {:?}", options.mode);
+    println!("  min_group_width = {}", options.min_group_width);
+    println!("  collapsed_chip_min_width = {}", options.collapsed_chip_min_width);
+    println!("  min_bg_gap_width = {}", options.min_bg_gap_width);
+    println!("  unknown_separator_width = {}", options.unknown_separator_width);
+    println!("  left_margin = {}", options.left_margin);
+    match display_color_profile() {
+        Some(profile) if !is_srgb_profile(&profile) => println!(
+            "  warning: display color profile '{}' is not sRGB; captured colors may drift from the palette (tolerance {})",
+            profile,
+            palette_tolerance(Some(&profile))
+        ),
+        Some(profile) => println!("  display_color_profile = {}", profile),
+        None => println!("  display_color_profile = (unknown)"),
+    }
+    println!(
+        "  separator_colors = [{}]",
+        options.separator_colors
+            .iter()
+            .map(|c| format!("#{:06X}", c))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    for var_name in [
+        "TABGROUP_HOVER_EXTRA_COLORS",
+        "TABGROUP_HOVER_BG_COLORS",
+        "TABGROUP_HOVER_SEPARATOR_COLORS",
+        "TABGROUP_HOVER_LOW_SATURATION_COLORS",
+        "TABGROUP_HOVER_ACTIVE_TAB_COLORS",
+        "TABGROUP_HOVER_UNBLEND_BACKDROP",
+    ] {
+        for token in invalid_color_tokens(var_name) {
+            println!("  error: {} contains invalid color '{}'", var_name, token);
+            ok = false;
+        }
+    }
+    if let Some(path) = colors_file_path().filter(|path| path.exists()) {
+        match std::fs::read_to_string(&path).map_err(anyhow::Error::from).and_then(|raw| parse_colors_json(&raw)) {
+            Ok(colors) => println!("  colors_file = {} ({} colors replace the built-in palette)", path.display(), colors.len()),
+            Err(e) => {
+                println!("  error: {} is malformed, using the built-in palette: {}", path.display(), e);
+                ok = false;
+            }
+        }
+    }
+    match IndexMap::from_env() {
+        Ok(Some(map)) => println!("  index_map = ignore {:?}, offset {}", map.ignore, map.offset),
+        Ok(None) => {}
+        Err(e) => {
+            println!("  error: {}", e);
+            ok = false;
+        }
+    }
+
+    let cursor = get_cursor_pos()?;
+    let windows = all_windows()?;
+    let window = match resolve_browser_window(&windows, cursor) {
+        Ok(window) => window,
+        Err(e) => {
+            println!("Live palette check skipped: {}", e);
+            return Ok(ok);
+        }
+    };
+    let capture = capture_window(window)?;
+    let (dpi, _) = window_dpi(&WindowInfo::from_window(window));
+    let scan_y = ScanGeometry::for_dpi(dpi, GroupStyle::from_env()).scan_y;
+    let palette = target_colors(Theme::resolve(&capture), window.app_name());
+    let presence = palette_presence(&capture, scan_y, &palette);
+
+    println!("Live palette check on '{}' (scan line y={}):", window.title(), scan_y);
+    for line in palette_presence_report(&presence) {
+        println!("  {}", line);
+    }
+    Ok(ok)
+}
+
+/// Per-color present/absent lines for [`palette_presence`] counts, with a
+/// closing warning when some colors do not appear at all.
+fn palette_presence_report(presence: &[(u32, u32)]) -> Vec<String> {
+    let mut lines: Vec<String> = presence
+        .iter()
+        .map(|(color, hits)| match hits {
+            0 => format!("#{:06X} absent", color),
+            hits => format!("#{:06X} present ({} px)", color, hits),
+        })
+        .collect();
+    let absent = presence.iter().filter(|(_, hits)| *hits == 0).count();
+    if absent > 0 {
+        lines.push(format!(
+            "warning: {} of {} palette colors do not appear on the tab strip; \
+             if a group uses one of them, the palette entry is likely stale",
+            absent,
+            presence.len()
+        ));
+    }
+    lines
+}
+
+/// Replaces `path` with `contents` in one step: the data goes to a sibling
+/// temp file first and is renamed over the target, so a concurrent reader
+/// sees either the previous result or the new one, never a partial write.
+/// A detector killed mid-write (e.g. by the host's timeout) leaves only the
+/// `.tmp` file behind.
+fn write_file_atomically(path: &str, contents: &[u8]) -> Result<()> {
+    write_file_atomically_with(path, |file| Ok(file.write_all(contents)?))
+}
+
+/// `write_file_atomically` with the contents produced by `write`; the target
+/// is only replaced once `write` succeeds.
+fn write_file_atomically_with(path: &str, write: impl FnOnce(&mut std::fs::File) -> Result<()>) -> Result<()> {
+    let temp_path = format!("{}.tmp", path);
+    let mut file = std::fs::File::create(&temp_path)?;
+    write(&mut file)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+/// With `TABGROUP_HOVER_RESULT_FILE` set, writes the detection there as JSON
+/// for tools that watch the file instead of using native messaging.
+pub fn publish_result(detection: &Detection) {
+    let Ok(path) = env::var("TABGROUP_HOVER_RESULT_FILE") else {
+        return;
+    };
+    if let Err(e) = write_result_file(&path, detection) {
+        error!("Failed to write result file {}: {}", path, e);
+    }
+}
+
+fn write_result_file(path: &str, detection: &Detection) -> Result<()> {
+    write_file_atomically(path, &serde_json::to_vec(&detection.to_json())?)
+}
+
+/// One JSON report of a detection for `--diagnose`: the foreground window,
+/// the window that was scanned and its bounds, the cursor, whether a group
+/// color was matched at the cursor, the groups and the final index. It needs
+/// no verbose logging, and a failed detection is reported as `error` rather
+/// than returned, so there is always a report to paste into an issue.
+pub fn diagnose(source: CaptureSource, deadline: Deadline) -> serde_json::Value {
+    let foreground = unsafe { GetForegroundWindow() };
+    let active_window = Window::all().ok().and_then(|windows| {
+        windows.into_iter().find(|w| w.id() as isize == foreground.0).map(|w| {
+            serde_json::json!({
+                "id": w.id(),
+                "title": w.title(),
+                "app_name": w.app_name(),
+                "bounds": window_rect(w.id()).map(|rect| rect_json(rect, 0, 0)),
+            })
+        })
+    });
+    diagnostic_report(active_window, detect(source, true, deadline))
+}
+
+fn diagnostic_report(active_window: Option<serde_json::Value>, detection: Result<Detection>) -> serde_json::Value {
+    let detection = match detection {
+        Ok(detection) => detection,
+        Err(e) => {
+            return serde_json::json!({
+                "version": env!("CARGO_PKG_VERSION"),
+                "active_window": active_window,
+                "error": e.to_string(),
+            })
+        }
+    };
+    let report = detection.to_json();
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "active_window": active_window,
+        "selected_window": detection.window.as_ref().map(|w| serde_json::json!({
+            "id": w.id,
+            "title": w.title,
+            "app_name": w.app_name,
+            "bounds": { "x": w.x, "y": w.y, "width": w.width, "height": w.height },
+        })),
+        "cursor": report["cursor"],
+        "cursor_color_matched": detection.cursor_color.is_some(),
+        "cursor_color": report["color"],
+        "exact_hit": report["exact_hit"],
+        "groups": report["groups"],
+        "index": detection.index,
+        "reason": report["reason"],
+        "status": report["status"],
+    })
+}
+
+/// Caps how often `--serve` captures: requests within one frame interval of
+/// the last capture get that capture's answer again.
+struct ScanThrottle {
+    interval: Option<Duration>,
+    last: Option<(Instant, String)>,
+}
+
+impl ScanThrottle {
+    fn new(max_hz: Option<f64>) -> Self {
+        Self {
+            interval: max_hz.map(|hz| Duration::from_secs_f64(1.0 / hz)),
+            last: None,
+        }
+    }
+
+    fn from_env() -> Self {
+        Self::new(parse_f64_from_env("TABGROUP_HOVER_MAX_SCAN_HZ"))
+    }
+
+    /// The previous answer, if it is still inside the frame interval at `now`.
+    fn cached(&self, now: Instant) -> Option<&str> {
+        let interval = self.interval?;
+        self.last
+            .as_ref()
+            .filter(|(at, _)| now.duration_since(*at) < interval)
+            .map(|(_, answer)| answer.as_str())
+    }
+
+    fn store(&mut self, at: Instant, answer: String) {
+        if self.interval.is_some() {
+            self.last = Some((at, answer));
+        }
+    }
+}
+
+/// Answers hover checks over stdin/stdout so a caller can keep the process
+/// warm instead of paying the spawn cost per check.
+///
+/// Each input line is one request, optionally holding a deadline in
+/// milliseconds; anything else on the line (such as `check`) is a plain
+/// request. Each answer is one line, flushed right away: the index with its
+/// reason code, as [`Detection::to_serve_line`] writes it, or
+/// `error <message>`.
+/// Returns when stdin reaches EOF.
+pub fn serve() -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    let mut throttle = ScanThrottle::from_env();
+    let mut cache = ResultCache::from_env();
+    let mut memo = ScanMemo::default();
+    let mut window_cache = WindowCache::from_env();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        let started = Instant::now();
+        if let Some(answer) = throttle.cached(started) {
+            writeln!(stdout, "{}", answer)?;
+            stdout.flush()?;
+            continue;
+        }
+        let deadline = line
+            .trim()
+            .parse::<u64>()
+            .map(Deadline::after_ms)
+            .unwrap_or_else(|_| Deadline::none());
+        let answer = match detect_tab_groups(
+            CaptureSource::Live,
+            false,
+            deadline,
+            cache.as_mut(),
+            Some(&mut memo),
+            window_cache.as_mut(),
+        ) {
+            Ok(detection) => {
+                publish_result(&detection);
+                notify_toast(&detection);
+                detection.to_serve_line()
+            }
+            Err(e) => {
+                error!("Error: {}", e);
+                format!("error {}", e.to_string().replace('\n', " "))
+            }
+        };
+        writeln!(stdout, "{}", answer)?;
+        stdout.flush()?;
+        throttle.store(started, answer);
+    }
+    Ok(())
+}
+
+/// Destination for text copied out of the detector, so the Win32 clipboard can
+/// be swapped out when exercising the export path.
+pub trait ClipboardWriter {
+    fn write_text(&mut self, text: &str) -> Result<()>;
+}
+
+pub struct WindowsClipboard;
+
+impl WindowsClipboard {
+    const OPEN_ATTEMPTS: u32 = 5;
+    const OPEN_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+    fn open() -> Result<()> {
+        // Another process may briefly hold the clipboard open, so give it a few tries.
+        for attempt in 1..=Self::OPEN_ATTEMPTS {
+            if unsafe { OpenClipboard(HWND(0)) }.as_bool() {
+                return Ok(());
+            }
+            log_to_file(&format!("OpenClipboard failed (attempt {})", attempt))?;
+            thread::sleep(Self::OPEN_RETRY_DELAY);
+        }
+        Err(anyhow::anyhow!("Clipboard is in use by another application"))
+    }
+}
+
+impl ClipboardWriter for WindowsClipboard {
+    fn write_text(&mut self, text: &str) -> Result<()> {
+        let wide: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+        Self::open()?;
+        let result = unsafe {
+            (|| -> Result<()> {
+                EmptyClipboard().ok()?;
+                let size = wide.len() * std::mem::size_of::<u16>();
+                let hmem = GlobalAlloc(GMEM_MOVEABLE, size)?;
+                let dest = GlobalLock(hmem) as *mut u16;
+                if dest.is_null() {
+                    let _ = GlobalFree(hmem);
+                    return Err(anyhow::anyhow!("Failed to lock clipboard memory"));
+                }
+                std::ptr::copy_nonoverlapping(wide.as_ptr(), dest, wide.len());
+                let _ = GlobalUnlock(hmem);
+                // On success the clipboard owns the memory; only free it on failure.
+                if let Err(e) = SetClipboardData(CF_UNICODETEXT.0 as u32, HANDLE(hmem.0)) {
+                    let _ = GlobalFree(hmem);
+                    return Err(e.into());
+                }
+                Ok(())
+            })()
+        };
+        unsafe {
+            CloseClipboard();
+        }
+        result
+    }
+}
+
+pub fn export_to_clipboard(detection: &Detection, clipboard: &mut dyn ClipboardWriter) -> Result<String> {
+    let payload = serde_json::to_string_pretty(&detection.to_json())?;
+    clipboard.write_text(&payload)?;
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn rgba(color: u32) -> image::Rgba<u8> {
+        image::Rgba([(color >> 16) as u8, (color >> 8) as u8, color as u8, 0xFF])
+    }
+
+    /// One-pixel-high strip of background with `spans` filled in their color.
+    fn strip(width: u32, spans: &[(u32, u32, u32)]) -> RgbaImage {
+        let mut img = RgbaImage::from_pixel(width, 1, rgba(BACKGROUND_COLOR));
+        for &(start, end, color) in spans {
+            for x in start..end.min(width) {
+                img.put_pixel(x, 0, rgba(color));
+            }
+        }
+        img
+    }
+
+    /// Fill-mode groups on `img`'s only row with the default palette and
+    /// background.
+    fn fill_groups(img: &RgbaImage, options: &ScanOptions) -> Vec<(u32, u32)> {
+        let sweep = Sweep::horizontal(img.width(), 0);
+        let targets = Palette::new(TARGET_COLORS.to_vec());
+        let background = Background::new(vec![BACKGROUND_COLOR], None);
+        scan_tab_groups_by_fill(img, &sweep, &targets, &background, options).unwrap()
+    }
+
+    /// `image` as an injected frame of an offline window the same size whose
+    /// top-left corner is at `(x, y)` on screen.
+    fn offline_frame_at(image: RgbaImage, x: i32, y: i32) -> Frame {
+        let window = WindowInfo {
+            id: 0,
+            title: String::new(),
+            app_name: "test".to_string(),
+            x,
+            y,
+            width: image.width(),
+            height: image.height(),
+        };
+        Frame { image, window }
+    }
+
+    /// One of the tab strip captures in `tests/fixtures`: `dark.png` is a
+    /// 640x60 dark-theme strip with groups at 40..160 and 220..360 straddling
+    /// the scan line at y=30.
+    fn fixture(name: &str) -> RgbaImage {
+        image::open(format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap().to_rgba8()
+    }
+
+    /// Scan of `image` with the cursor at `x` on its scan line.
+    fn scan_at(image: RgbaImage, x: i32, enumerate: bool) -> Detection {
+        scan_frame(offline_frame_at(image, 0, 0), POINT { x, y: 30 }, enumerate, Deadline::none(), Path::new("test"), None).unwrap()
+    }
+
+    #[derive(Default)]
+    struct RecordingClipboard {
+        written: Vec<String>,
+        fail: bool,
+    }
+
+    impl ClipboardWriter for RecordingClipboard {
+        fn write_text(&mut self, text: &str) -> Result<()> {
+            if self.fail {
+                return Err(anyhow::anyhow!("Clipboard is in use by another application"));
+            }
+            self.written.push(text.to_string());
+            Ok(())
+        }
+    }
+
+    /// A detection over two groups with the cursor on the second.
+    fn two_group_detection() -> Detection {
+        Detection {
+            index: 2,
+            groups: vec![(40, 160), (220, 360)],
+            ..Detection::empty(POINT { x: 300, y: 30 })
+        }
+    }
+
+    #[test]
+    fn clipboard_export_writes_the_json_layout() {
+        let detection = two_group_detection();
+        let mut clipboard = RecordingClipboard::default();
+        let payload = export_to_clipboard(&detection, &mut clipboard).unwrap();
+        assert_eq!(clipboard.written, vec![payload.clone()]);
+        assert_eq!(payload, serde_json::to_string_pretty(&detection.to_json()).unwrap());
+
+        let json: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(json["index"], 2);
+        let spans: Vec<(u64, u64)> = json["groups"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|group| (group["start"].as_u64().unwrap(), group["end"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(spans, vec![(40, 160), (220, 360)]);
+    }
+
+    #[test]
+    fn clipboard_failure_is_reported() {
+        let detection = two_group_detection();
+        let mut clipboard = RecordingClipboard { fail: true, ..RecordingClipboard::default() };
+        let error = export_to_clipboard(&detection, &mut clipboard).unwrap_err();
+        assert!(error.to_string().contains("in use"));
+        assert!(clipboard.written.is_empty());
+    }
+
+    #[test]
+    fn wide_unknown_run_separates_groups_under_the_policy() {
+        let blue = TARGET_COLORS[0];
+        let img = strip(400, &[(40, 150, blue), (150, 170, 0xFFFFFF), (170, 300, blue), (220, 226, 0xFFFFFF)]);
+        let ignore = ScanOptions { unknown_separator_width: 0, ..ScanOptions::from_env() };
+        assert_eq!(fill_groups(&img, &ignore), vec![(40, 300)]);
+        // The 20 px white run splits; the 6 px favicon inside the second group does not.
+        let separate = ScanOptions { unknown_separator_width: 12, ..ScanOptions::from_env() };
+        assert_eq!(fill_groups(&img, &separate), vec![(40, 150), (170, 300)]);
+    }
+
+    #[test]
+    fn dpi_scale_override_is_applied_to_the_cursor() {
+        assert_eq!(resolve_cursor_scale(2880, 1920, None, None), (1.5, "detected"));
+        assert_eq!(resolve_cursor_scale(1920, 0, None, None), (1.0, "default"));
+        // The override wins over both the detected ratio and the DPI snap.
+        let (scale, source) = resolve_cursor_scale(1920, 1920, None, Some(1.25));
+        assert_eq!((scale, source), (1.25, "override"));
+        assert_eq!(resolve_cursor_scale(2880, 1904, Some((144, 96)), Some(2.0)), (2.0, "override"));
+        assert_eq!(resolve_cursor_scale(0, 0, None, Some(2.0)), (2.0, "override"));
+        // A cursor 400 px into the window lands 500 px into the capture.
+        assert_eq!((map_to_image(400, scale), map_to_image(-3, scale)), (500, 0));
+    }
+
+    #[test]
+    fn gap_mode_segments_noisy_fills_at_clean_gaps() {
+        let groups = [(40, 160, TARGET_COLORS[0]), (220, 360, TARGET_COLORS[1]), (370, 450, TARGET_COLORS[1])];
+        let clean = strip(500, &groups);
+        // Every fifth fill pixel is white text or an icon, and every 25th is
+        // a lone background-colored speck.
+        let mut noisy = clean.clone();
+        for &(start, end, _) in &groups {
+            for x in start..end {
+                if x % 5 == 3 {
+                    noisy.put_pixel(x, 0, image::Rgba([0xFF, 0xFF, 0xFF, 0xFF]));
+                } else if x % 25 == 12 {
+                    noisy.put_pixel(x, 0, image::Rgba([0x20, 0x20, 0x20, 0xFF]));
+                }
+            }
+        }
+        let options = ScanOptions { mode: DetectionMode::Gaps, min_bg_gap_width: 6, ..ScanOptions::from_env() };
+        let targets = Palette::new(TARGET_COLORS.to_vec());
+        let background = Background::new(vec![BACKGROUND_COLOR], None);
+        let gap_groups = |img: &RgbaImage| {
+            let sweep = Sweep::horizontal(img.width(), 0);
+            scan_tab_groups_by_gaps(img, &sweep, &targets, &background, &options).unwrap()
+        };
+        let expected = vec![(40, 160), (220, 360), (370, 450)];
+        assert_eq!(gap_groups(&noisy), expected);
+        // On clean fills both modes agree, so indices mean the same thing.
+        assert_eq!(gap_groups(&clean), expected);
+        assert_eq!(fill_groups(&clean, &options), expected);
+    }
+
+    #[test]
+    fn palette_check_reports_the_absent_colors() {
+        // A strip with groups in only the first two palette colors.
+        let img = strip(640, &[(40, 160, TARGET_COLORS[0]), (220, 360, TARGET_COLORS[1])]);
+        let presence = palette_presence(&img, 0, TARGET_COLORS);
+        let report = palette_presence_report(&presence);
+        assert_eq!(report[0], "#779FF8 present (120 px)");
+        assert_eq!(report[1], "#E06AB7 present (140 px)");
+        let absent: Vec<&String> = report.iter().filter(|line| line.ends_with("absent")).collect();
+        assert_eq!(absent.len(), TARGET_COLORS.len() - 2);
+        assert_eq!(absent[0], "#C78BD9 absent");
+        assert!(report.last().unwrap().starts_with("warning: 7 of 9 palette colors"));
+
+        let all_present = palette_presence_report(&[(0x779FF8, 3)]);
+        assert_eq!(all_present, vec!["#779FF8 present (3 px)"]);
+    }
+
+    #[test]
+    fn separator_line_splits_same_colored_groups() {
+        let blue = TARGET_COLORS[0];
+        let img = strip(400, &[(40, 200, blue), (200, 201, 0x3C3C3C), (201, 360, blue)]);
+        // Without a configured separator the 1 px line is just noise inside
+        // one group.
+        let plain = ScanOptions { separator_colors: Vec::new(), ..ScanOptions::from_env() };
+        assert_eq!(fill_groups(&img, &plain), vec![(40, 360)]);
+        let options = ScanOptions { separator_colors: vec![0x3C3C3C], ..ScanOptions::from_env() };
+        assert_eq!(fill_groups(&img, &options), vec![(40, 200), (201, 360)]);
+    }
+
+    #[test]
+    fn injected_frame_maps_the_cursor_through_its_window_geometry() {
+        let inject = |x: i32, y: i32, cursor: POINT| CaptureSource::Injected {
+            frame: offline_frame_at(fixture("dark.png"), x, y),
+            cursor: Some(cursor),
+        };
+        // Screen (400, 230) is (300, 30) in a window at (100, 200): group 2.
+        let detection = detect(inject(100, 200, POINT { x: 400, y: 230 }), true, Deadline::none()).unwrap();
+        assert_eq!((detection.index, detection.reason), (2, Reason::Hit));
+        assert_eq!(detection.groups, vec![(40, 160), (220, 360)]);
+        // The same screen point over a window at the origin is past the groups.
+        let detection = detect(inject(0, 0, POINT { x: 400, y: 30 }), false, Deadline::none()).unwrap();
+        assert_eq!((detection.index, detection.reason), (0, Reason::NotOnGroup));
+        // Above the window's strip altogether.
+        let detection = detect(inject(100, 200, POINT { x: 400, y: 30 }), false, Deadline::none()).unwrap();
+        assert_eq!((detection.index, detection.reason), (0, Reason::CursorOutside));
+    }
+
+    #[test]
+    fn exact_cursor_pixel_wins_over_its_neighbors() {
+        let (blue, pink) = (TARGET_COLORS[0], TARGET_COLORS[1]);
+        let targets = Palette::new(TARGET_COLORS.to_vec());
+        let img = strip(100, &[(48, 53, blue), (50, 51, pink)]);
+        let sweep = Sweep::horizontal(img.width(), 0);
+        let hit = |cursor_t, exact_first| {
+            find_cursor_color(&img, cursor_t, &sweep, (3, 3), &targets, exact_first).map(|hit| (hit.color, hit.offset))
+        };
+        assert_eq!(hit(50, true), Some((pink, 0)));
+        // The plain sweep runs left to right and reports the neighbor.
+        assert_eq!(hit(50, false), Some((blue, -2)));
+        // Off the group, the closest match is taken, left before right.
+        let img = strip(100, &[(40, 45, blue), (46, 50, pink)]);
+        let hit = find_cursor_color(&img, 45, &sweep, (3, 3), &targets, true).map(|hit| (hit.color, hit.offset));
+        assert_eq!(hit, Some((blue, -1)));
+        assert!(find_cursor_color(&img, 90, &sweep, (3, 3), &targets, true).is_none());
+    }
+
+    #[test]
+    fn top_border_groups_are_found_only_on_the_near_top_row() {
+        // 60 px strip whose groups are 3 px borders along the top of the tabs.
+        let mut img = RgbaImage::from_pixel(400, 60, rgba(BACKGROUND_COLOR));
+        for (start, end, color) in [(40, 160, TARGET_COLORS[0]), (220, 360, TARGET_COLORS[1])] {
+            for y in 2..5 {
+                for x in start..end {
+                    img.put_pixel(x, y, rgba(color));
+                }
+            }
+        }
+        let groups_at = |y: u32| {
+            let sweep = Sweep::horizontal(img.width(), y);
+            let targets = Palette::new(TARGET_COLORS.to_vec());
+            let background = Background::new(vec![BACKGROUND_COLOR], None);
+            scan_tab_groups_by_fill(&img, &sweep, &targets, &background, &ScanOptions::from_env()).unwrap()
+        };
+        let border = ScanGeometry::for_dpi(96, GroupStyle::TopBorder);
+        let pill = ScanGeometry::for_dpi(96, GroupStyle::Pill);
+        assert_eq!((border.scan_y, pill.scan_y), (3, 30));
+        assert_eq!(groups_at(border.scan_y), vec![(40, 160), (220, 360)]);
+        assert!(groups_at(pill.scan_y).is_empty());
+        // On a wrapped second row the border is looked for below that row's top.
+        assert_eq!(row_scan_y((60, 120), GroupStyle::TopBorder, &border), 63);
+        assert_eq!(row_scan_y((60, 120), GroupStyle::Pill, &pill), 90);
+    }
+
+    #[test]
+    fn threshold_is_clamped_to_a_smaller_caption_height() {
+        let threshold = VERTICAL_THRESHOLD as u32;
+        assert_eq!(threshold, 60);
+        assert_eq!(clamp_threshold(threshold, Some(41)), 41);
+        assert_eq!(clamp_threshold(threshold, Some(80)), 60);
+        // A failed query keeps the constant.
+        assert_eq!(clamp_threshold(threshold, None), 60);
+        assert_eq!(clamp_threshold(threshold, Some(0)), 60);
+    }
+
+    #[test]
+    fn inverted_capture_is_recovered_and_other_filters_are_flagged() {
+        let mut inverted = fixture("dark.png");
+        invert_colors(&mut inverted);
+        assert_ne!(scan_at(inverted.clone(), 300, false).index, 2);
+
+        let mut capture = inverted.clone();
+        assert!(!undo_color_transform(&mut capture, ColorTransform::Inverted, true).unwrap());
+        assert_eq!(scan_at(capture, 300, false).index, 2);
+
+        // Left alone, or a filter that cannot be undone: flagged, pixels untouched.
+        for (transform, undo) in [(ColorTransform::Inverted, false), (ColorTransform::Grayscale, true)] {
+            let mut capture = inverted.clone();
+            assert!(undo_color_transform(&mut capture, transform, undo).unwrap());
+            assert_eq!(capture, inverted);
+        }
+        assert_eq!(color_transform_from_filter(true, 1), ColorTransform::Inverted);
+        assert_eq!(color_transform_from_filter(false, 1), ColorTransform::None);
+        assert_eq!(color_transform_from_filter(true, 4), ColorTransform::ColorBlindFilter(4));
+    }
+
+    #[test]
+    fn log_levels_map_to_event_severities() {
+        let error = format_event(Level::Error, "capture failed");
+        assert_eq!((error.event_type, error.event_id), (1, EVENT_ID_ERROR));
+        assert_eq!(error.text, format!("[ERROR] pid={} capture failed", std::process::id()));
+
+        let warning = format_event(Level::Warn, "no browser window");
+        assert_eq!((warning.event_type, warning.event_id), (2, EVENT_ID_DETECTION));
+        for level in [Level::Info, Level::Debug, Level::Trace] {
+            let record = format_event(level, "index=2");
+            assert_eq!((record.event_type, record.event_id), (4, EVENT_ID_DETECTION));
+            assert!(record.text.ends_with(" index=2"));
+        }
+    }
+
+    #[test]
+    fn favicon_blip_in_ungrouped_tabs_is_not_a_group() {
+        // A group, then ungrouped tabs: gray titles on the background, one
+        // with a 12 px favicon in the group palette's blue.
+        let img = strip(
+            500,
+            &[(40, 160, TARGET_COLORS[0]), (220, 290, 0x9A9A9A), (300, 312, TARGET_COLORS[0]), (318, 390, 0x9A9A9A)],
+        );
+        let groups = |ungrouped_blip_width| {
+            let options = ScanOptions {
+                mode: DetectionMode::Fill,
+                min_group_width: 8,
+                ungrouped_blip_width,
+                ..ScanOptions::from_env()
+            };
+            let sweep = Sweep::horizontal(img.width(), 0);
+            let targets = Palette::new(TARGET_COLORS.to_vec());
+            let background = Background::new(vec![BACKGROUND_COLOR], None);
+            scan_tab_groups(&img, &sweep, &targets, &background, &options).unwrap()
+        };
+        assert_eq!(groups(0), vec![(40, 160), (300, 312)]);
+        assert_eq!(groups(16), vec![(40, 160)]);
+    }
+
+    #[test]
+    fn gray_pixel_is_rejected_below_the_saturation_floor() {
+        let gray_entry = 0x83817E;
+        let gray_pixel = 0x888888;
+        let loose = Palette::new(TARGET_COLORS.to_vec()).with_tolerance(12);
+        assert!(loose.matches(gray_pixel));
+
+        let gated = Palette::new(TARGET_COLORS.to_vec()).with_tolerance(12).with_min_saturation(0.1, &[]);
+        assert!(!gated.matches(gray_pixel));
+        assert!(!gated.matches(gray_entry));
+        assert!(gated.matches(TARGET_COLORS[0]));
+
+        // The gray group color itself, flagged as meant to be neutral.
+        let exempt = Palette::new(TARGET_COLORS.to_vec()).with_tolerance(12).with_min_saturation(0.1, &[gray_entry]);
+        assert!(exempt.matches(gray_pixel));
+        assert!(!exempt.matches(0x404040));
+    }
+
+    #[test]
+    fn tiny_deadline_returns_a_partial_result() {
+        let scan = |x, deadline| {
+            scan_frame(offline_frame_at(fixture("dark.png"), 0, 0), POINT { x, y: 30 }, false, deadline, Path::new("test"), None).unwrap()
+        };
+        let started = Instant::now();
+        let rushed = scan(100, Deadline::after_ms(0));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(rushed.partial);
+        assert_eq!(rushed.reason, Reason::DeadlineExceeded);
+
+        // The coarse cursor check runs before the first checkpoint, so a miss
+        // is still a full answer.
+        let miss = scan(190, Deadline::after_ms(0));
+        assert_eq!((miss.partial, miss.reason), (false, Reason::NotOnGroup));
+
+        let full = scan(100, Deadline::none());
+        assert_eq!((full.partial, full.index, full.reason), (false, 1, Reason::Hit));
+    }
+
+    #[test]
+    fn accent_blip_in_the_left_margin_is_ignored() {
+        // An address-bar accent at 4..24, then the real group.
+        let img = strip(400, &[(4, 24, TARGET_COLORS[0]), (80, 200, TARGET_COLORS[1])]);
+        let no_margin = ScanOptions { left_margin: 0, min_group_width: 10, ..ScanOptions::from_env() };
+        assert_eq!(fill_groups(&img, &no_margin), vec![(4, 24), (80, 200)]);
+        let margin = ScanOptions { left_margin: 30, ..no_margin };
+        assert_eq!(fill_groups(&img, &margin), vec![(80, 200)]);
+    }
+
+    #[test]
+    fn binary_result_layout() {
+        // The native host's decode test reads these same bytes back.
+        let hit = scan_at(fixture("dark.png"), 300, false);
+        assert_eq!(hit.to_binary(), [2, 0, 0, 0, 0, 0xB7, 0x6A, 0xE0, 0]);
+        let miss = scan_at(fixture("dark.png"), 190, false);
+        assert_eq!(miss.to_binary(), [0, 0, 0, 0, Reason::NotOnGroup.code(), 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(Reason::NotOnGroup.code(), 4);
+    }
+
+    #[test]
+    fn serve_line_carries_the_reason_code() {
+        // The native host's pool parses these lines with parse_detector_output.
+        let hit = scan_at(fixture("dark.png"), 300, false);
+        assert_eq!(hit.to_serve_line(), "2 reason=0");
+        let miss = scan_at(fixture("dark.png"), 190, false);
+        assert_eq!(miss.to_serve_line(), "0 reason=4");
+        let captured = Detection { captured_at_ms: Some(1_700_000_000_123), partial: true, ..two_group_detection() };
+        assert_eq!(
+            captured.to_serve_line(),
+            format!("2 partial reason={} captured_at_ms=1700000000123", captured.reason.code())
+        );
+    }
+
+    #[test]
+    fn browser_window_without_tabs_has_no_tab_strip() {
+        let geometry = ScanGeometry::for_dpi(96, GroupStyle::Pill);
+        let title_bar = |spans: &[(u32, u32, u32)]| {
+            let mut img = RgbaImage::from_pixel(1000, 60, rgba(BACKGROUND_COLOR));
+            for &(start, end, color) in spans {
+                for x in start..end {
+                    img.put_pixel(x, geometry.scan_y, rgba(color));
+                }
+            }
+            img
+        };
+        // A settings window: app icon and caption text only.
+        let settings = title_bar(&[(10, 26, 0x3A7BD5), (40, 120, 0xE0E0E0)]);
+        // Four tabs, each with a favicon, a title and a close button.
+        let tabs: Vec<(u32, u32, u32)> = (0..4)
+            .flat_map(|tab| {
+                let left = 10 + tab * 200;
+                [(left, left + 16, 0x3A7BD5), (left + 30, left + 140, 0xE0E0E0), (left + 170, left + 180, 0xE0E0E0)]
+            })
+            .collect();
+        let tabbed = title_bar(&tabs);
+
+        assert!(lacks_tab_strip(&settings, &geometry, 6).unwrap());
+        assert!(!lacks_tab_strip(&tabbed, &geometry, 6).unwrap());
+        // Off by default: even the settings window goes on to the scan.
+        assert!(!lacks_tab_strip(&settings, &geometry, 0).unwrap());
+    }
+
+    #[test]
+    fn identical_detections_save_one_change_screenshot() {
+        let state_file = std::env::temp_dir().join(format!("hover_detector_signature_{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&state_file);
+        let groups = [(40, 160), (220, 360)];
+        let saved = [
+            detection_signature(100, &groups),
+            detection_signature(120, &groups),
+            detection_signature(300, &groups),
+            detection_signature(300, &groups[..1]),
+        ]
+        .iter()
+        .filter(|signature| signature_changed(&state_file, signature).unwrap())
+        .count();
+        let _ = std::fs::remove_file(&state_file);
+        // The second detection hovers the same group over the same layout.
+        assert_eq!(saved, 3);
+    }
+
+    #[test]
+    fn measured_band_height_maps_to_the_density() {
+        // A 120 px pill centered on the scan line, `height` pixels tall.
+        let pill = |height: u32| {
+            let mut img = RgbaImage::from_pixel(640, 60, rgba(BACKGROUND_COLOR));
+            for y in 30 - height / 2..30 + height.div_ceil(2) {
+                for x in 40..160 {
+                    img.put_pixel(x, y, rgba(0x779FF8));
+                }
+            }
+            scan_at(img, 100, true)
+        };
+        let compact = pill(16);
+        assert_eq!(compact.band, Some((22, 38)));
+        assert_eq!(compact.density, Some(Density::Compact));
+        assert_eq!(pill(22).density, Some(Density::Default));
+        assert_eq!(pill(28).density, Some(Density::Touch));
+        assert_eq!(Density::from_band_height(DENSITY_COMPACT_MAX_BAND), Density::Compact);
+        assert_eq!(Density::from_band_height(DENSITY_DEFAULT_MAX_BAND + 0.5), Density::Touch);
+    }
+
+    #[test]
+    fn open_popup_is_reported_only_under_the_flag() {
+        let cursor = POINT { x: 100, y: 30 };
+        let detection = popup_detection(cursor, true, || true).expect("popup reported");
+        assert_eq!(detection.reason, Reason::PopupActive);
+        assert_eq!(detection.index, 0);
+        assert!(popup_detection(cursor, true, || false).is_none());
+        // Off by default: the popup is resolved to its browser window instead,
+        // without even looking for it here.
+        assert!(popup_detection(cursor, false, || panic!("popup looked up")).is_none());
+    }
+
+    #[test]
+    fn queries_within_one_frame_interval_reuse_the_capture() {
+        // The serve loop: answer from the throttle or capture and store.
+        let captures_for = |throttle: &mut ScanThrottle, offsets_ms: &[u64]| {
+            let start = Instant::now();
+            let mut captures = 0;
+            for &offset in offsets_ms {
+                let now = start + Duration::from_millis(offset);
+                if throttle.cached(now).is_none() {
+                    captures += 1;
+                    throttle.store(now, format!("1 captured_at_ms={}", offset));
+                }
+            }
+            captures
+        };
+        // 10 Hz: three queries inside the first 100 ms, one after it.
+        let mut throttle = ScanThrottle::new(Some(10.0));
+        assert_eq!(captures_for(&mut throttle, &[0, 20, 99]), 1);
+        let mut throttle = ScanThrottle::new(Some(10.0));
+        assert_eq!(captures_for(&mut throttle, &[0, 20, 99, 100, 150]), 2);
+        assert_eq!(captures_for(&mut ScanThrottle::new(None), &[0, 20, 99]), 3);
+    }
+
+    #[test]
+    fn bgra_buffer_read_as_rgba_fails_the_capture_check() {
+        // A group-colored row written in BGRA byte order, as a capture API
+        // with the other channel order would hand it over.
+        let (blue, green, red) = (0xF8, 0x9F, 0x77);
+        let bytes: Vec<u8> = (0..4).flat_map(|_| [blue, green, red, 0xFF]).collect();
+        let bgra = RgbaImage::from_raw(4, 1, bytes).unwrap();
+        let captured = get_pixel_color(&bgra, 2, 0).unwrap();
+        let error = check_pixel_format(captured, 0x779FF8).unwrap_err().to_string();
+        assert!(error.contains("#F89F77") && error.contains("swapped"), "{}", error);
+
+        let rgba_row = strip(4, &[(0, 4, 0x779FF8)]);
+        assert!(check_pixel_format(get_pixel_color(&rgba_row, 2, 0).unwrap(), 0x779FF8).is_ok());
+    }
+
+    #[test]
+    fn child_control_resolves_to_its_top_level_frame() {
+        use windows::Win32::UI::WindowsAndMessaging::{WS_OVERLAPPEDWINDOW, WS_VISIBLE};
+        // A frame (1) hosting a render widget (2); the widget's root is the frame.
+        let (frame, widget) = (HWND(1), HWND(2));
+        let style = |hwnd: HWND| match hwnd.0 {
+            1 => (WS_OVERLAPPEDWINDOW | WS_VISIBLE).0,
+            _ => (WS_CHILD | WS_VISIBLE).0,
+        };
+        let root = |_: HWND| frame;
+        assert_eq!(top_level_for(widget, style, root).0, frame.0);
+        assert_eq!(top_level_for(frame, style, |_| panic!("frame walked up")).0, frame.0);
+        // A child whose root cannot be found stays as it is.
+        assert_eq!(top_level_for(widget, style, |_| HWND(0)).0, widget.0);
+        // A captionless popup counts as a child too.
+        assert!(looks_like_child(WS_VISIBLE.0));
+    }
+
+    #[test]
+    fn result_cache_hits_within_a_bucket_and_recomputes_across_it() {
+        let mut cache = ResultCache::new(Duration::from_millis(500), 8);
+        let window = offline_frame_at(RgbaImage::new(640, 60), 100, 0).window;
+        let foreground = HWND(0);
+        let at = |x: i32| cache.key(&window, POINT { x, y: 30 }, foreground);
+        let (first, same_bucket, next_bucket) = (at(100 + 40), at(100 + 47), at(100 + 48));
+        let now = Instant::now();
+        cache.insert(first.clone(), now, 1, Reason::Hit);
+
+        assert_eq!(cache.get(&same_bucket, now), Some((1, Reason::Hit)));
+        assert_eq!(cache.get(&next_bucket, now), None);
+        assert_eq!(cache.get(&first, now + Duration::from_millis(500)), None);
+        // Moving the window changes the key as well.
+        let moved = offline_frame_at(RgbaImage::new(640, 60), 120, 0).window;
+        assert_eq!(cache.get(&cache.key(&moved, POINT { x: 160, y: 30 }, foreground), now), None);
+    }
+
+    #[test]
+    fn calibrated_reference_segments_groups_over_a_gradient() {
+        // An acrylic-like titlebar brightening from left to right.
+        let gradient: Vec<u32> = (0..640).map(|x| 0x202020 + (x / 16) * 0x010101).collect();
+        let mut img = RgbaImage::new(640, 1);
+        for (x, color) in gradient.iter().enumerate() {
+            img.put_pixel(x as u32, 0, rgba(*color));
+        }
+        for (start, end, color) in [(40, 160, 0x779FF8), (220, 360, 0xE06AB7)] {
+            for x in start..end {
+                img.put_pixel(x, 0, rgba(color));
+            }
+        }
+        let sweep = Sweep::horizontal(img.width(), 0);
+        let targets = Palette::new(TARGET_COLORS.to_vec());
+        let options = ScanOptions::from_env();
+        let groups = |reference| {
+            let background = Background::new(vec![BACKGROUND_COLOR], reference);
+            scan_tab_groups(&img, &sweep, &targets, &background, &options).unwrap()
+        };
+        assert_eq!(groups(Some(gradient.clone())), vec![(40, 160), (220, 360)]);
+        // Against the single background color nothing after the first group
+        // reads as background, so everything merges into one group.
+        assert_eq!(groups(None), vec![(40, 640)]);
+    }
+
+    #[test]
+    fn cursor_in_the_top_dead_zone_is_outside_the_strip() {
+        let geometry = ScanGeometry { top_dead_zone: 2, ..ScanGeometry::for_dpi(96, GroupStyle::Pill) };
+        let window = offline_frame_at(fixture("dark.png"), 100, 200).window;
+        let in_area = |y: i32| is_cursor_in_strip(POINT { x: 200, y }, &strip_bounds(&window, &geometry), &geometry);
+        // Over the first group horizontally, in the resize border.
+        assert!(!in_area(200));
+        assert!(!in_area(201));
+        assert!(in_area(202));
+        assert!(in_area(230));
+        let no_dead_zone = ScanGeometry { top_dead_zone: 0, ..geometry };
+        assert!(is_cursor_in_strip(POINT { x: 200, y: 200 }, &strip_bounds(&window, &no_dead_zone), &no_dead_zone));
+    }
+
+    #[test]
+    fn result_file_holds_the_latest_detection_as_json() {
+        let path = std::env::temp_dir().join(format!("hover_detector_result_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let read = || serde_json::from_str::<serde_json::Value>(&std::fs::read_to_string(path).unwrap()).unwrap();
+
+        write_result_file(path, &scan_at(fixture("dark.png"), 100, false)).unwrap();
+        assert_eq!(read()["index"], 1);
+        write_result_file(path, &scan_at(fixture("dark.png"), 300, false)).unwrap();
+        assert_eq!(read()["index"], 2);
+        assert_eq!(read()["reason"], "Hit");
+        // The temp file was renamed over the result, not left behind.
+        assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn non_srgb_profile_is_flagged_and_its_tolerance_covers_the_shift() {
+        assert!(is_srgb_profile(r"C:\Windows\System32\spool\drivers\color\sRGB Color Space Profile.icm"));
+        assert!(!is_srgb_profile(r"C:\Windows\System32\spool\drivers\color\DisplayP3.icc"));
+        // Only the file name counts, not a directory that mentions sRGB.
+        assert!(!is_srgb_profile("/profiles/srgb/AdobeRGB1998.icc"));
+        assert_eq!(palette_tolerance(Some("sRGB Color Space Profile.icm")), palette_tolerance(None));
+
+        // A simulated wide-gamut profile pulling each channel 20% toward gray.
+        let through_profile = |color: u32| {
+            let channels = [(color >> 16) & 0xFF, (color >> 8) & 0xFF, color & 0xFF];
+            let mean = channels.iter().sum::<u32>() / 3;
+            channels.iter().fold(0, |out, c| (out << 8) | ((*c * 4 + mean) / 5))
+        };
+        let captured = through_profile(0x779FF8);
+        assert!(!Palette::new(TARGET_COLORS.to_vec()).matches(captured));
+        assert!(Palette::new(TARGET_COLORS.to_vec()).with_tolerance(16).matches(captured));
+    }
+
+    #[test]
+    fn empty_enumeration_is_retried_until_windows_appear() {
+        let mut calls = 0;
+        let windows = enumerate_with_retry(3, Duration::ZERO, || {
+            calls += 1;
+            Ok(if calls == 1 { vec![] } else { vec!["msedge"] })
+        })
+        .unwrap();
+        assert_eq!((windows, calls), (vec!["msedge"], 2));
+
+        let mut calls = 0;
+        let error = enumerate_with_retry::<&str>(3, Duration::ZERO, || {
+            calls += 1;
+            anyhow::bail!("enumeration {} failed", calls)
+        })
+        .unwrap_err();
+        // Bounded: the last failure is returned after the final attempt.
+        assert_eq!((error.to_string().as_str(), calls), ("enumeration 3 failed", 3));
+    }
+
+    #[test]
+    fn differing_geometry_reads_are_reported_as_motion() {
+        let rect = |left: i32| RECT { left, top: 0, right: left + 800, bottom: 600 };
+        let reads = |lefts: Vec<i32>| {
+            let mut lefts = lefts.into_iter();
+            move || lefts.next().map(rect)
+        };
+        // Skip policy: two reads only, and they differ.
+        assert!(matches!(sample_motion(reads(vec![0, 10]), Duration::ZERO, Duration::ZERO), WindowMotion::Moving));
+        // Wait policy: the drag stops after a few reads.
+        let motion = sample_motion(reads(vec![0, 10, 20, 20]), Duration::ZERO, Duration::from_secs(1));
+        assert!(matches!(motion, WindowMotion::Settled { from, to } if from == rect(0) && to == rect(20)));
+        assert!(matches!(
+            sample_motion(reads(vec![0, 0]), Duration::ZERO, Duration::ZERO),
+            WindowMotion::Settled { from, to } if from == to
+        ));
+        assert!(matches!(sample_motion(reads(vec![0]), Duration::ZERO, Duration::ZERO), WindowMotion::Unknown));
+    }
+
+    #[test]
+    fn identical_layouts_hash_equal_and_a_color_change_alters_it() {
+        let groups = [(40, 160), (220, 360)];
+        let colors = [Some(0x779FF8), Some(0xE06AB7)];
+        let hash = layout_hash(&groups, &colors);
+        assert_eq!(hash, layout_hash(&[(40, 160), (220, 360)], &[Some(0x779FF8), Some(0xE06AB7)]));
+        // A fixed value: the hash must not depend on the process or the run.
+        assert_eq!(format!("{:016x}", hash), "d8fb1d1587281875");
+        assert_ne!(hash, layout_hash(&groups, &[Some(0x779FF8), Some(0xC78BD9)]));
+        assert_ne!(hash, layout_hash(&groups, &[Some(0x779FF8), None]));
+        assert_ne!(hash, layout_hash(&[(40, 160), (220, 361)], &colors));
+
+        let json = |x| scan_at(fixture("dark.png"), x, true).to_json()["layout_hash"].clone();
+        assert_eq!(json(100), json(300));
+    }
+
+    #[test]
+    fn active_tab_overhang_stays_inside_the_group_span() {
+        // The active tab at the group's right end overhangs the pill by 6 px.
+        const ACTIVE_TAB: u32 = 0x3B3B3B;
+        let img = strip(400, &[(40, 160, TARGET_COLORS[0]), (160, 166, ACTIVE_TAB), (220, 360, TARGET_COLORS[1])]);
+        let sweep = Sweep::horizontal(img.width(), 0);
+        let targets = Palette::new(TARGET_COLORS.to_vec());
+        let background = Background::new(vec![BACKGROUND_COLOR], None);
+        let groups = |mode, active_tab_colors: &[u32]| {
+            let options = ScanOptions {
+                mode,
+                active_tab_colors: active_tab_colors.to_vec(),
+                unknown_separator_width: 3,
+                ..ScanOptions::from_env()
+            };
+            scan_tab_groups(&img, &sweep, &targets, &background, &options).unwrap()
+        };
+        for mode in [DetectionMode::Fill, DetectionMode::Gaps] {
+            assert_eq!(groups(mode, &[ACTIVE_TAB]), vec![(40, 166), (220, 360)], "{:?}", mode);
+            assert_eq!(groups(mode, &[]), vec![(40, 160), (220, 360)], "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn interrupted_screenshot_save_leaves_only_the_temp_file() {
+        let path = std::env::temp_dir().join(format!("hover_detector_screenshot_{}.png", std::process::id()));
+        let path = path.to_str().unwrap();
+        let temp_path = format!("{}.tmp", path);
+        let mut png = Cursor::new(Vec::new());
+        fixture("dark.png").write_to(&mut png, ImageFormat::Png).unwrap();
+        let png = png.into_inner();
+
+        // Killed halfway through the PNG data.
+        let interrupted = write_file_atomically_with(path, |file| {
+            file.write_all(&png[..png.len() / 2])?;
+            anyhow::bail!("killed")
+        });
+        assert!(interrupted.is_err());
+        assert!(!std::path::Path::new(path).exists());
+        assert_eq!(std::fs::metadata(&temp_path).unwrap().len(), (png.len() / 2) as u64);
+
+        // The next complete save replaces the leftover and yields a valid image.
+        write_file_atomically(path, &png).unwrap();
+        assert!(!std::path::Path::new(&temp_path).exists());
+        assert_eq!(image::open(path).unwrap().width(), 640);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn cursor_in_the_second_row_hits_that_rows_first_group() {
+        // Two wrapped rows of 16 px pills: one group in the first row, two in
+        // the second.
+        let mut img = RgbaImage::from_pixel(640, 120, rgba(BACKGROUND_COLOR));
+        for (rows, start, end, color) in [
+            (16..32, 40, 160, TARGET_COLORS[0]),
+            (68..84, 40, 200, TARGET_COLORS[1]),
+            (68..84, 260, 400, TARGET_COLORS[0]),
+        ] {
+            for y in rows {
+                for x in start..end {
+                    img.put_pixel(x, y, rgba(color));
+                }
+            }
+        }
+        let geometry = ScanGeometry { tab_rows: 2, ..ScanGeometry::for_dpi(96, GroupStyle::Pill) };
+        let background = Background::new(vec![BACKGROUND_COLOR], None);
+        let rows = strip_tab_rows(&img, &geometry, &background);
+        assert_eq!(rows, vec![(16, 32), (68, 84)]);
+
+        let cursor = (100, 76);
+        let row = rows.iter().position(|(top, bottom)| (*top..*bottom).contains(&cursor.1)).unwrap();
+        assert_eq!(row, 1);
+        let targets = Palette::new(TARGET_COLORS.to_vec());
+        let background = Background::new(vec![BACKGROUND_COLOR], None);
+        let sweep = Sweep::horizontal(img.width(), row_scan_y(rows[row], GroupStyle::Pill, &geometry));
+        let groups = scan_tab_groups(&img, &sweep, &targets, &background, &ScanOptions::from_env()).unwrap();
+        assert_eq!(groups, vec![(40, 200), (260, 400)]);
+        assert_eq!(groups.iter().position(|(start, end)| (*start..*end).contains(&cursor.0)), Some(0));
+    }
+
+    #[test]
+    fn index_map_ignores_then_offsets_raw_indices() {
+        let map = IndexMap::parse(r#"{"ignore": [1], "offset": 2}"#).unwrap();
+        assert_eq!(map.apply(1), None);
+        assert_eq!(map.apply(2), Some(4));
+        assert_eq!(map.apply(0), Some(0));
+        // A negative offset cannot push a group below index 1.
+        let map = IndexMap::parse(r#"{"offset": -1}"#).unwrap();
+        assert_eq!((map.apply(1), map.apply(3)), (None, Some(2)));
+
+        for (raw, message) in [
+            ("[1]", "expected a JSON object"),
+            (r#"{"ignore": 1}"#, "'ignore' must be an array"),
+            (r#"{"ignore": [0]}"#, "'ignore' entries must be group indices"),
+            (r#"{"offset": 1.5}"#, "'offset' must be an integer"),
+            (r#"{"shift": 1}"#, "unknown key 'shift'"),
+        ] {
+            let error = IndexMap::parse(raw).unwrap_err().to_string();
+            assert!(error.contains(message), "{}: {}", raw, error);
+        }
+    }
+
+    #[test]
+    fn collapsed_chip_suggests_expanding_and_a_wide_group_collapsing() {
+        let toggle = |x| scan_at(fixture("collapsed.png"), x, false).to_json()["toggle_action"].clone();
+        // The 12 px chip at 200..212 against the 120 px group at 40..160.
+        assert_eq!(toggle(205), "expand");
+        assert_eq!(toggle(100), "collapse");
+        assert_eq!(toggle(230), serde_json::Value::Null);
+    }
+
+    #[test]
+    fn rotated_and_offset_sweeps_find_the_same_groups() {
+        let dark = fixture("dark.png");
+        let targets = Palette::new(TARGET_COLORS.to_vec());
+        let background = Background::new(vec![BACKGROUND_COLOR], None);
+        let options = ScanOptions::from_env();
+        let groups = |img: &RgbaImage, sweep: Sweep| scan_tab_groups(img, &sweep, &targets, &background, &options).unwrap();
+        let expected = vec![(40, 160), (220, 360)];
+
+        assert_eq!(groups(&dark, Sweep::horizontal(640, 30)), expected);
+        // The scan line preset moved down onto the pills.
+        assert_eq!(groups(&dark, Sweep::horizontal(640, 10).offset_across(20).unwrap()), expected);
+        // Rotated a quarter turn clockwise, the strip runs down column 29.
+        let rotated = image::imageops::rotate90(&dark);
+        assert_eq!(groups(&rotated, Sweep::vertical(29, 640)), expected);
+        // Upside down, swept right to left from the far edge.
+        let flipped = image::imageops::rotate180(&dark);
+        let leftward = Sweep { origin: (639, 29), direction: SweepDirection::Left, length: 640, thickness: 1 };
+        assert_eq!(groups(&flipped, leftward), expected);
+
+        // A 3 px thick sweep rides out a background-colored hairline that
+        // would split the first group on a one-pixel line.
+        let mut scratched = dark.clone();
+        for x in 90..110 {
+            scratched.put_pixel(x, 30, rgba(BACKGROUND_COLOR));
+        }
+        assert_eq!(groups(&scratched, Sweep::horizontal(640, 30)).len(), 3);
+        let thick = Sweep { thickness: 3, ..Sweep::horizontal(640, 30) };
+        assert_eq!(groups(&scratched, thick), expected);
+    }
+
+    #[test]
+    fn translucent_pill_matches_only_after_unblending() {
+        // The pink group drawn at 60% opacity over a light Mica backdrop.
+        let (pill, backdrop, alpha) = (0xE06AB7, 0xF3F3F3, 0.6);
+        let blended = [16, 8, 0].iter().fold(0, |out, shift| {
+            let channel = alpha * ((pill >> shift) & 0xFF) as f64 + (1.0 - alpha) * ((backdrop >> shift) & 0xFF) as f64;
+            out | ((channel.round() as u32) << shift)
+        });
+        assert!(color_matches(unblend(blended, backdrop, alpha), pill, 2));
+
+        assert!(!Palette::new(TARGET_COLORS.to_vec()).matches(blended));
+        assert!(Palette::new(TARGET_COLORS.to_vec()).with_unblend(Some((backdrop, alpha))).matches(blended));
+        // Un-blending against the wrong backdrop does not recover the pill.
+        assert!(!Palette::new(TARGET_COLORS.to_vec()).with_unblend(Some((0x202020, alpha))).matches(blended));
+    }
+
+    #[test]
+    fn unchanged_sweep_reuses_the_memoized_groups() {
+        let mut memo = ScanMemo::default();
+        let scan = |image: RgbaImage, memo: &mut ScanMemo| {
+            let detection =
+                scan_frame(offline_frame_at(image, 0, 0), POINT { x: 100, y: 30 }, true, Deadline::none(), Path::new("test"), Some(memo)).unwrap();
+            detection.groups
+        };
+        assert_eq!(scan(fixture("dark.png"), &mut memo), vec![(40, 160), (220, 360)]);
+        let (hash, _) = memo.entries[&0].clone();
+
+        // Swap the stored groups for a marker: an identical capture returns
+        // it, so the scan did not run again.
+        memo.insert(0, hash, vec![(1, 2)]);
+        assert_eq!(scan(fixture("dark.png"), &mut memo), vec![(1, 2)]);
+
+        // Drop the second group from the row: the hash changes and it rescans.
+        let mut changed = fixture("dark.png");
+        for x in 220..360 {
+            changed.put_pixel(x, 30, rgba(BACKGROUND_COLOR));
+        }
+        assert_eq!(scan(changed, &mut memo), vec![(40, 160)]);
+        assert_ne!(memo.entries[&0].0, hash);
+    }
+
+    #[test]
+    fn toast_payload_names_the_group_and_is_throttled() {
+        let hit = scan_at(fixture("dark.png"), 300, true);
+        assert_eq!(
+            toast_payload(&hit),
+            "<toast><visual><binding template=\"ToastGeneric\"><text>Group 2 (#E06AB7)</text>\
+             <text>Hit, 2 groups found</text></binding></visual><audio silent=\"true\"/></toast>"
+        );
+        let miss = scan_at(fixture("dark.png"), 190, true);
+        assert!(toast_payload(&miss).contains("<text>No group</text>"));
+
+        let last = Some((1_000, "2 Hit Some(14707383)"));
+        assert!(toast_due(None, 1_000, "2 Hit Some(14707383)", 500, true));
+        assert!(!toast_due(last, 1_400, "1 Hit Some(7839736)", 500, false));
+        assert!(toast_due(last, 1_500, "1 Hit Some(7839736)", 500, true));
+        // In change mode an unchanged result is not shown again, however late.
+        assert!(!toast_due(last, 60_000, "2 Hit Some(14707383)", 500, true));
+        assert!(toast_due(last, 60_000, "2 Hit Some(14707383)", 500, false));
+    }
+
+    #[test]
+    fn colors_json_parses_hex_entries_case_insensitively() {
+        assert_eq!(parse_colors_json(r##"["#EE5FB7", "0xee5fb7", "0X779ff8"]"##).unwrap(), vec![0xEE5FB7, 0xEE5FB7, 0x779FF8]);
+        for raw in [
+            r##"["#EE5FB7", "#GG5FB7"]"##,
+            r##"["#EE5FB7FF"]"##,
+            r##"["0x5FB7"]"##,
+            "[]",
+            r##"{"colors": ["#EE5FB7"]}"##,
+            "[7839736]",
+        ] {
+            assert!(parse_colors_json(raw).is_err(), "{}", raw);
+        }
+
+        // Missing or malformed files leave the built-in palette in place.
+        let path = std::env::temp_dir().join(format!("hover_detector_colors_{}.json", std::process::id()));
+        assert_eq!(load_colors_from(&path), None);
+        std::fs::write(&path, r##"["#EE5FB7", "pink"]"##).unwrap();
+        assert_eq!(load_colors_from(&path), None);
+        std::fs::write(&path, r##"["#EE5FB7"]"##).unwrap();
+        assert_eq!(load_colors_from(&path), Some(vec![0xEE5FB7]));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn screen_crop_cuts_the_window_out_of_its_monitor() {
+        let window = fixture("dark.png");
+        // A second monitor right of the primary one, the window 300 px into it.
+        let mut screen = RgbaImage::from_pixel(1200, 400, rgba(0x101010));
+        image::imageops::replace(&mut screen, &window, 300, 150);
+        let crop = crop_window_from_screen(&screen, (1920, 0, 1200, 400), (2220, 150, 640, 60));
+        assert_eq!(crop, window);
+        for x in [100, 300] {
+            let (from_crop, from_window) = (scan_at(crop.clone(), x, true), scan_at(window.clone(), x, true));
+            assert_eq!((from_crop.index, &from_crop.groups), (from_window.index, &from_window.groups));
+        }
+        assert_eq!(scan_at(crop, 300, true).groups, vec![(40, 160), (220, 360)]);
+
+        // At 200% the screen capture has twice the pixels of the layout.
+        let crop = crop_window_from_screen(&screen, (1920, 0, 600, 200), (2070, 75, 320, 30));
+        assert_eq!(crop, window);
+
+        // Hanging 100 px off the left edge: that part stays transparent and
+        // the rest keeps its window-relative position.
+        let mut screen = RgbaImage::from_pixel(1200, 400, rgba(0x101010));
+        image::imageops::replace(&mut screen, &*image::imageops::crop_imm(&window, 100, 0, 540, 60), 0, 150);
+        let crop = crop_window_from_screen(&screen, (1920, 0, 1200, 400), (1820, 150, 640, 60));
+        assert_eq!(crop.dimensions(), window.dimensions());
+        for (x, y, pixel) in crop.enumerate_pixels() {
+            if x < 100 {
+                assert_eq!(*pixel, image::Rgba([0, 0, 0, 0]), "({}, {})", x, y);
+            } else {
+                assert_eq!(pixel, window.get_pixel(x, y), "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn gradient_group_reports_one_quantized_color() {
+        // The blue pill with its green channel drifting from 153 to 163.
+        let shades: Vec<(u32, u32, u32)> = (40..160).map(|x| (x, x + 1, 0x770000 | ((153 + (x - 40) / 11) << 8) | 0xF8)).collect();
+        let img = strip(200, &shades);
+        let sweep = Sweep::horizontal(img.width(), 0);
+        let targets = Palette::new(TARGET_COLORS.to_vec());
+        let distinct: HashSet<u32> = shades.iter().map(|(_, _, color)| *color).collect();
+        assert_eq!(distinct.len(), 11);
+        assert!(distinct.iter().all(|color| targets.matches(*color)));
+
+        let color = |quantize| group_color(&img, &sweep, (40, 160), &targets, quantize);
+        assert_eq!(color(Quantize::Palette), Some(0x779FF8));
+        assert_eq!(color(Quantize::Grid(16)), Some(0x70A0FF));
+        // Unquantized, whichever shade covers a few more pixels wins.
+        assert!(color(Quantize::Off).is_some_and(|color| distinct.contains(&color)));
+    }
+
+    #[test]
+    fn stray_target_pixels_do_not_start_groups() {
+        let blue = TARGET_COLORS[0];
+        let pink = TARGET_COLORS[1];
+        // Speckles and a 5 px run of group color around two real pills; the
+        // first pill has a 2 px background scratch that must not split it.
+        let img = strip(
+            480,
+            &[
+                (10, 11, pink),
+                (40, 90, blue),
+                (92, 160, blue),
+                (180, 181, blue),
+                (190, 195, pink),
+                (220, 360, pink),
+                (400, 401, blue),
+            ],
+        );
+        let fill = |min_start_run, min_group_width| {
+            fill_groups(&img, &ScanOptions { min_start_run, min_group_width, ..ScanOptions::from_env() })
+        };
+        // Even with no minimum group width, runs under 6 px are ignored.
+        assert_eq!(fill(6, 1), vec![(40, 160), (220, 360)]);
+        assert_eq!(fill(MIN_START_RUN_DEFAULT, MIN_GROUP_WIDTH_DEFAULT), vec![(40, 160), (220, 360)]);
+        assert_eq!(fill(1, 1), vec![(10, 11), (40, 160), (180, 181), (190, 195), (220, 360), (400, 401)]);
+    }
+
+    #[test]
+    fn reported_theme_color_is_the_background_reference() {
+        // A navy frame color, too tinted for the neutral line sampling.
+        const NAVY: u32 = 0x1F2A44;
+        let mut img = RgbaImage::from_pixel(640, 1, rgba(NAVY));
+        for (start, end, color) in [(40, 160, TARGET_COLORS[0]), (220, 360, TARGET_COLORS[1])] {
+            for x in start..end {
+                img.put_pixel(x, 0, rgba(color));
+            }
+        }
+        let line = Sweep::horizontal(img.width(), 0);
+        let targets = Palette::new(TARGET_COLORS.to_vec());
+        // The caption color attribute as DWM hands it over, 0x00BBGGRR.
+        let attribute = 0x00442A1F;
+        let theme_color = colorref_to_rgb(attribute);
+        assert_eq!(theme_color, Some(NAVY));
+        assert_eq!(colorref_to_rgb(DWMWA_COLOR_DEFAULT), None);
+
+        let candidates = |theme_color| resolve_background_candidates(&img, &line, &targets, theme_color, Theme::Dark, 0..0);
+        assert_eq!(candidates(theme_color), vec![NAVY]);
+        assert!(!candidates(None).contains(&NAVY));
+
+        let background = Background::new(candidates(theme_color), None);
+        let groups = scan_tab_groups(&img, &line, &targets, &background, &ScanOptions::from_env()).unwrap();
+        assert_eq!(groups, vec![(40, 160), (220, 360)]);
+    }
+
+    #[test]
+    fn scan_line_votes_pick_the_majority_then_the_exact_hit() {
+        let geometry = ScanGeometry { scan_rows: 3, scan_row_spacing: 4, ..ScanGeometry::for_dpi(96, GroupStyle::Pill) };
+        assert_eq!(geometry.scan_line_offsets(), vec![0, -4, 4]);
+
+        // Glyphs on the main line: the two other lines outvote it.
+        assert_eq!(vote_scan_lines(&[(0, false), (2, true), (2, true)]), 1);
+        assert_eq!(vote_scan_lines(&[(2, true), (0, false), (2, true)]), 0);
+        // No majority near a rounded corner: the line hitting the pill wins.
+        assert_eq!(vote_scan_lines(&[(0, false), (1, false), (2, true)]), 2);
+        assert_eq!(vote_scan_lines(&[(0, false), (1, false), (2, false)]), 0);
+        // Two lines are a tie, not a majority.
+        assert_eq!(vote_scan_lines(&[(1, false), (2, true)]), 1);
+    }
+
+    #[test]
+    fn mixed_dpi_scale_keeps_the_cursor_on_its_group() {
+        // Primary at 100%, Edge on a secondary at 150%: the capture includes
+        // the frame, 2880 px for a 1904 px wide window.
+        let groups = [(2400, 2712), (2716, 2860)];
+        let index_at = |scale: f64| hovered_index(&groups, Some(map_to_image(1800, scale)));
+        let detected = 2880.0 / 1904.0;
+        assert_eq!(index_at(detected), 2, "the raw ratio lands on the next group's chip");
+
+        let (scale, source) = resolve_cursor_scale(2880, 1904, Some((144, 96)), None);
+        assert_eq!((scale, source), (1.5, "monitor"));
+        assert_eq!(index_at(scale), 1);
+        // The other way around (window at 100% on a 150% system) snaps to 2/3.
+        assert_eq!(resolve_cursor_scale(1270, 1904, Some((96, 144)), None).0, 2.0 / 3.0);
+        // A single monitor keeps the detected ratio.
+        assert_eq!(resolve_cursor_scale(2880, 1904, Some((144, 144)), None), (detected, "detected"));
+    }
+
+    #[test]
+    fn strip_covered_by_another_window_is_reported_as_occluded() {
+        // A window with a blue-on-pink page dragged over the whole strip.
+        let mut covered = fixture("dark.png");
+        for y in 10..50 {
+            for x in 0..covered.width() {
+                let color = if (x / 3 + y) % 2 == 0 { 0xE06AB7 } else { 0x779FF8 };
+                covered.put_pixel(x, y, rgba(color));
+            }
+        }
+        let detection = scan_at(covered, 100, false);
+        assert_eq!(detection.reason, Reason::StripOccluded);
+        assert_eq!(detection.index, 0);
+        assert!(detection.groups.is_empty());
+
+        // Uncovered, everything outside the two groups is background.
+        let background = Background::new(vec![BACKGROUND_COLOR], None);
+        let share = background_share(&fixture("dark.png"), &Sweep::horizontal(640, 30), &background);
+        assert!((share - 380.0 / 640.0).abs() < 1e-9, "{}", share);
+    }
+
+    #[test]
+    fn chrome_windows_get_the_chrome_palette() {
+        assert_eq!(browser_palette("Google Chrome").name, "chrome");
+        assert_eq!(browser_palette("Microsoft Edge").name, "edge");
+        // Browsers without an entry of their own fall back to Edge's colors.
+        assert_eq!(browser_palette("Brave Browser").name, "edge");
+
+        // Chrome's blue and pink groups on its dark title bar.
+        let img = strip(400, &[(40, 160, 0x8AB4F8), (220, 360, 0xFF8BCB)]);
+        let groups = |app_name| {
+            let palette: Vec<u32> = browser_palette(app_name).dark.iter().flat_map(|set| set.iter().copied()).collect();
+            let background = Background::new(vec![BACKGROUND_COLOR], None);
+            let sweep = Sweep::horizontal(img.width(), 0);
+            scan_tab_groups(&img, &sweep, &Palette::new(palette), &background, &ScanOptions::from_env()).unwrap()
+        };
+        assert_eq!(groups("Google Chrome"), vec![(40, 160), (220, 360)]);
+        assert_eq!(groups("Microsoft Edge"), vec![]);
+    }
+
+    #[test]
+    fn chromium_forks_are_browsers_by_app_or_file_name() {
+        let defaults = parse_browser_names("");
+        assert_eq!(defaults, ["edge", "chrome", "chromium", "brave", "vivaldi", "opera"]);
+        for app_name in ["Microsoft Edge", "Google Chrome", "Brave Browser", "Vivaldi", "Opera Internet Browser", "Chromium"] {
+            assert!(is_browser(app_name, || panic!("file name read for {}", app_name), &defaults), "{}", app_name);
+        }
+        // An app name that says nothing, with the executable giving it away.
+        assert!(is_browser("Web Browser", || Some("brave.exe".to_string()), &defaults));
+        assert!(!is_browser("Notepad", || Some("notepad.exe".to_string()), &defaults));
+        assert!(!is_browser("Notepad", || None, &defaults));
+
+        let custom = parse_browser_names("Thorium, arc");
+        assert_eq!(custom, ["thorium", "arc"]);
+        assert!(is_browser("Thorium", || None, &custom));
+        assert!(!is_browser("Microsoft Edge", || Some("msedge.exe".to_string()), &custom));
+    }
+
+    #[test]
+    fn popup_resolves_to_the_window_of_its_own_process() {
+        // (id, pid, title, bounds) front to back: the popup itself, an Edge
+        // window of another profile in front, the popup's own window and a
+        // Chrome window. All three contain the popup's corner at (500, 400).
+        let windows = [
+            (1, 20, "", RECT { left: 500, top: 400, right: 700, bottom: 600 }),
+            (2, 10, "Other profile - Microsoft Edge", RECT { left: 300, top: 200, right: 1300, bottom: 900 }),
+            (3, 20, "News - Microsoft Edge", RECT { left: 0, top: 0, right: 1200, bottom: 800 }),
+            (4, 30, "Docs - Google Chrome", RECT { left: 0, top: 0, right: 1920, bottom: 1080 }),
+        ];
+        let corner = POINT { x: 500, y: 400 };
+        let pick = |pid| {
+            frontmost_process_window(
+                &windows,
+                pid,
+                |(_, window_pid, title, bounds)| (*window_pid, !title.is_empty(), is_point_in_rect(corner, bounds)),
+                |(_, _, title, _)| title.contains("Edge") || title.contains("Chrome"),
+            )
+            .map(|(id, ..)| *id)
+        };
+        assert_eq!(pick(20), Some(3));
+        assert_eq!(pick(10), Some(2));
+        // No titled window of the process under the popup: spatial fallbacks.
+        assert_eq!(pick(40), None);
+    }
+
+    #[test]
+    fn screenshot_sidecar_records_the_cursor_groups_and_window() {
+        let detection = scan_at(fixture("dark.png"), 300, true);
+        let sweep = Sweep::horizontal(640, 30);
+        let sidecar = screenshot_sidecar(&detection, &sweep, (300, 30), &detection.groups, Some(1));
+        assert_eq!(sidecar["cursor"], serde_json::json!({ "x": 300, "y": 30 }));
+        assert_eq!(sidecar["capture_cursor"], serde_json::json!({ "x": 300, "y": 30 }));
+        assert_eq!(sidecar["scan_y"], 30);
+        assert_eq!(sidecar["index"], 2);
+        let groups = sidecar["groups"].as_array().unwrap();
+        let spans: Vec<(u64, u64, u64)> = groups
+            .iter()
+            .map(|g| (g["index"].as_u64().unwrap(), g["start"].as_u64().unwrap(), g["end"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(spans, vec![(1, 40, 160), (2, 220, 360)]);
+        assert_eq!(groups[0]["color"], "#779FF8");
+        assert_eq!(groups[1]["color"], "#E06AB7");
+        assert_eq!(sidecar["window"], serde_json::json!({ "title": "", "app_name": "test" }));
+    }
+
+    #[test]
+    fn diagnostic_report_gathers_the_scan_or_the_error() {
+        let active = serde_json::json!({ "id": 7, "title": "News - Microsoft Edge" });
+        let report = diagnostic_report(Some(active.clone()), Ok(scan_at(fixture("dark.png"), 300, true)));
+        assert_eq!(report["active_window"], active);
+        assert_eq!(report["selected_window"]["app_name"], "test");
+        assert_eq!(report["selected_window"]["bounds"], serde_json::json!({ "x": 0, "y": 0, "width": 640, "height": 60 }));
+        assert_eq!(report["cursor"]["x"], 300);
+        assert_eq!(report["cursor_color_matched"], true);
+        assert_eq!(report["groups"].as_array().map(Vec::len), Some(2));
+        assert_eq!(report["index"], 2);
+        assert!(report.get("error").is_none());
+
+        let report = diagnostic_report(None, Err(anyhow::anyhow!("capture failed")));
+        assert_eq!(report["error"], "capture failed");
+        assert!(report["active_window"].is_null());
+        assert!(report.get("index").is_none());
+    }
+
+    #[test]
+    fn palette_longer_than_the_builtin_one_matches_every_entry() {
+        // Two themed colors past the nine built-in ones, each starting a group.
+        let custom = [0x2E7D32, 0xFF5722];
+        let img = strip(640, &[(40, 160, 0x779FF8), (200, 300, custom[0]), (340, 420, 0x83817E), (460, 560, custom[1])]);
+        let sweep = Sweep::horizontal(img.width(), 0);
+        let background = Background::new(vec![BACKGROUND_COLOR], None);
+        let options = ScanOptions::from_env();
+        let builtin = scan_tab_groups(&img, &sweep, &Palette::new(TARGET_COLORS.to_vec()), &background, &options).unwrap();
+        assert_eq!(builtin, vec![(40, 160), (340, 420)]);
+
+        let extended: Vec<u32> = TARGET_COLORS.iter().chain(&custom).copied().collect();
+        assert_eq!(extended.len(), 11);
+        let groups = scan_tab_groups(&img, &sweep, &Palette::new(extended), &background, &options).unwrap();
+        assert_eq!(groups, vec![(40, 160), (200, 300), (340, 420), (460, 560)]);
+        assert_eq!(hovered_index(&groups, Some(500)), 4);
+    }
+
+    #[test]
+    fn hovered_group_running_to_the_edge_reports_its_width_on_screen() {
+        // The second group of the edge fixture is closed by the end of the
+        // scan; the window sits at (100, 200) on screen.
+        let frame = offline_frame_at(fixture("edge.png"), 100, 200);
+        let detection = scan_frame(frame, POINT { x: 579, y: 230 }, true, Deadline::none(), Path::new("test"), None).unwrap();
+        assert_eq!(detection.index, 2);
+        let hovered = &detection.to_json()["hovered_group"];
+        assert_eq!(hovered["index"], 2);
+        assert_eq!(hovered["start"], 240);
+        assert_eq!(hovered["end"], 480);
+        assert_eq!(hovered["width"], 240);
+        assert_eq!(hovered["window_left"], 100);
+        let (window_box, screen_box) = (&hovered["window_box"], &hovered["screen_box"]);
+        assert_eq!(screen_box["left"], window_box["left"].as_i64().unwrap() + 100);
+        assert_eq!(screen_box["top"], window_box["top"].as_i64().unwrap() + 200);
+
+        let window = detection.window.clone().unwrap();
+        let result = DetectionResult::from(detection);
+        let group = result.hovered.expect("hovered group reported");
+        assert_eq!((group.start, group.end, group.width()), (240, 480, 240));
+        let (left, top, right, bottom) = group.window_box.unwrap();
+        assert_eq!(group.screen_box(&window), Some((left + 100, top + 200, right + 100, bottom + 200)));
+
+        // Nothing hovered, nothing to size.
+        let missed = scan_at(fixture("edge.png"), 200, true);
+        assert!(missed.to_json()["hovered_group"].is_null());
+        assert!(DetectionResult::from(missed).hovered.is_none());
+    }
+
+    /// Scan of an all-background strip and of the dark fixture, for a cursor
+    /// over the dark fixture's first group.
+    fn blank_then_populated() -> (impl Fn() -> Detection, impl Fn() -> Detection) {
+        let scan = |image: RgbaImage| {
+            scan_frame(offline_frame_at(image, 0, 0), POINT { x: 100, y: 30 }, false, Deadline::none(), Path::new("test"), None).unwrap()
+        };
+        let populated = fixture("dark.png");
+        let blank = RgbaImage::from_pixel(populated.width(), populated.height(), rgba(BACKGROUND_COLOR));
+        (move || scan(blank.clone()), move || scan(populated.clone()))
+    }
+
+    #[test]
+    fn blank_strip_is_captured_again() {
+        let (blank, populated) = blank_then_populated();
+        let first = blank();
+        assert!(first.blank_strip);
+        assert_eq!(first.index, 0);
+
+        let mut captures = 0;
+        let detection = retry_blank_strip(first, 1, Duration::ZERO, Deadline::none(), || {
+            captures += 1;
+            Ok(populated())
+        })
+        .unwrap();
+        assert_eq!(captures, 1);
+        assert_eq!((detection.index, detection.blank_strip), (1, false));
+
+        // Still blank after every retry: the last answer stands.
+        let mut captures = 0;
+        let detection = retry_blank_strip(blank(), 2, Duration::ZERO, Deadline::none(), || {
+            captures += 1;
+            Ok(blank())
+        })
+        .unwrap();
+        assert_eq!(captures, 2);
+        assert_eq!((detection.index, detection.blank_strip), (0, true));
+    }
+
+    #[test]
+    fn populated_strip_or_disabled_retries_are_not_captured_again() {
+        let (blank, populated) = blank_then_populated();
+        let mut captures = 0;
+        let mut capture = || {
+            captures += 1;
+            Ok(populated())
+        };
+
+        let detection = retry_blank_strip(populated(), 3, Duration::ZERO, Deadline::none(), &mut capture).unwrap();
+        assert_eq!(detection.index, 1);
+        let detection = retry_blank_strip(blank(), 0, Duration::ZERO, Deadline::none(), &mut capture).unwrap();
+        assert_eq!((detection.index, detection.blank_strip), (0, true));
+        let detection = retry_blank_strip(blank(), 1, Duration::ZERO, Deadline::after_ms(0), &mut capture).unwrap();
+        assert!(detection.blank_strip);
+        assert_eq!(captures, 0);
+    }
+
+    #[test]
+    fn color_matches_compares_each_channel() {
+        assert!(color_matches(0x779FF8, 0x779FF8, 0));
+        assert!(color_matches(0x7FA7FF, 0x779FF8, 8));
+        assert!(color_matches(0x6F97F0, 0x779FF8, 8));
+        // One channel off by more than the tolerance is enough to miss, even
+        // though the summed difference would be small.
+        assert!(!color_matches(0x809FF8, 0x779FF8, 8));
+        // Small differences on every channel add up past 8 but still match.
+        assert!(color_matches(0x7DA5FE, 0x779FF8, 8));
+        assert!(color_matches(0x000000, 0xFFFFFF, 255));
+    }
+
+    #[test]
+    fn pixel_format_check_uses_the_channel_tolerance() {
+        assert!(check_pixel_format(0x7DA5FE, 0x779FF8).is_ok());
+        let swapped = check_pixel_format(0xF89F77, 0x779FF8).unwrap_err();
+        assert!(swapped.to_string().contains("swapped"));
+        assert!(check_pixel_format(0x202020, 0x779FF8).is_err());
+    }
+
+    #[test]
+    fn palette_presence_counts_pixels_within_tolerance() {
+        let mut img = RgbaImage::from_pixel(10, 1, image::Rgba([0x20, 0x20, 0x20, 0xFF]));
+        for x in 0..3 {
+            img.put_pixel(x, 0, image::Rgba([0x7D, 0xA5, 0xFE, 0xFF]));
+        }
+        img.put_pixel(3, 0, image::Rgba([0x90, 0x9F, 0xF8, 0xFF]));
+        assert_eq!(palette_presence(&img, 0, &[0x779FF8, 0xE06AB7]), vec![(0x779FF8, 3), (0xE06AB7, 0)]);
+    }
+
+    #[test]
+    fn verbose_call_is_scoped_to_the_call() {
+        assert!(!is_verbose());
+        assert!(with_verbose(true, is_verbose));
+        assert!(!is_verbose());
+        assert!(!with_verbose(false, is_verbose));
+        // A nested quiet call keeps the outer call's verbosity.
+        assert!(with_verbose(true, || with_verbose(false, is_verbose)));
+        assert!(!is_verbose());
+    }
+
+    #[test]
+    fn verbose_call_saves_a_screenshot() {
+        let dir = env::temp_dir().join(format!("tabgroup-screenshots-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        // The verbose log goes to the working directory; only clean up a log this test created.
+        let had_log = Path::new(LOG_FILE).exists();
+        let screenshot = dir.join("screenshot_test");
+        let scan = |verbose| {
+            with_verbose(verbose, || {
+                scan_frame(offline_frame_at(fixture("dark.png"), 0, 0), POINT { x: 300, y: 30 }, false, Deadline::none(), &screenshot, None)
+            })
+            .unwrap()
+        };
+
+        assert_eq!(scan(true).index, 2);
+        assert!(image::open(screenshot.with_extension("png")).is_ok());
+        let sidecar: serde_json::Value = serde_json::from_slice(&std::fs::read(screenshot.with_extension("json")).unwrap()).unwrap();
+        assert_eq!(sidecar["index"], 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(scan(false).index, 2);
+        assert!(!dir.exists());
+        if !had_log {
+            let _ = std::fs::remove_file(LOG_FILE);
+        }
+    }
+
+    #[test]
+    fn clipped_first_group_is_opt_in() {
+        assert!(!ScanOptions::from_env().keep_clipped_first_group);
+        // Six pixels at the strip edge: too narrow for a group or a chip.
+        let img = strip(300, &[(0, 6, 0x779FF8), (100, 200, 0xE06AB7)]);
+        let on = ScanOptions { keep_clipped_first_group: true, ..ScanOptions::from_env() };
+        let groups = fill_groups(&img, &on);
+        assert_eq!(groups, vec![(0, 6), (100, 200)]);
+        assert_eq!(hovered_index(&groups, Some(5)), 1);
+        let off = ScanOptions { keep_clipped_first_group: false, ..ScanOptions::from_env() };
+        let groups = fill_groups(&img, &off);
+        assert_eq!(groups, vec![(100, 200)]);
+        assert_eq!(hovered_index(&groups, Some(5)), 0);
+    }
+
+    #[test]
+    fn geometry_at_150_percent_scales_everything_together() {
+        let base = ScanGeometry::for_dpi(96, GroupStyle::Pill);
+        let geometry = ScanGeometry::for_dpi(144, GroupStyle::Pill);
+        assert_eq!(geometry.scale(), 1.5);
+        assert_eq!((base.strip_height(), geometry.strip_height()), (60, 90));
+        assert_eq!((base.scan_y, geometry.scan_y), (30, 45));
+        assert_eq!((base.proximity, geometry.proximity), ((2, 2), (3, 3)));
+        // Edge at 150% on a secondary monitor, primary at 100%: the capture
+        // includes the frame and comes back 2880 px for a 1904 px window.
+        // Without snapping the cursor 1800 px in lands 23 px too far right.
+        let (scale, source) = geometry.cursor_scale(2880, 1904, Some(96));
+        assert_eq!((scale, source), (1.5, "monitor"));
+        assert_eq!(map_to_image(1800, scale), 2700);
+        // With the window on the primary monitor the ratio is used as is.
+        let (scale, source) = base.cursor_scale(1920, 1920, Some(96));
+        assert_eq!((scale, source), (1.0, "detected"));
+    }
+
+    /// Every channel value a palette entry's tolerance edges land on, plus a
+    /// coarse grid over the whole cube.
+    fn sampled_colors() -> Vec<u32> {
+        let mut colors = Vec::new();
+        for r in (0..=255u32).step_by(15) {
+            for g in (0..=255u32).step_by(15) {
+                for b in (0..=255u32).step_by(15) {
+                    colors.push(r << 16 | g << 8 | b);
+                }
+            }
+        }
+        for target in TARGET_COLORS {
+            for delta in [-9i32, -8, -7, 0, 7, 8, 9] {
+                for shift in [0, 8, 16] {
+                    let channel = (target >> shift & 0xFF) as i32;
+                    let moved = (channel + delta).clamp(0, 255) as u32;
+                    colors.push(target & !(0xFF << shift) | moved << shift);
+                }
+            }
+        }
+        colors
+    }
+
+    /// `palette`'s configuration checked the slow way: every entry, every time.
+    fn linear_match(palette: &Palette, color: u32) -> bool {
+        let intrinsic = |color: u32| {
+            let gated = palette.min_saturation > 0.0 && saturation(color) < palette.min_saturation;
+            palette.colors.iter().any(|target| {
+                (!gated || palette.low_saturation.contains(target)) && color_matches(color, *target, palette.tolerance)
+            })
+        };
+        intrinsic(color) || palette.unblend.is_some_and(|(backdrop, alpha)| intrinsic(unblend(color, backdrop, alpha)))
+    }
+
+    #[test]
+    fn palette_lookup_agrees_with_the_linear_scan() {
+        let both_sets: Vec<u32> = TARGET_COLORS.iter().chain(TARGET_COLORS_ALT).copied().collect();
+        let palettes = [
+            Palette::new(TARGET_COLORS.to_vec()),
+            Palette::new(TARGET_COLORS.to_vec()).with_tolerance(0),
+            Palette::new(TARGET_COLORS.to_vec()).with_tolerance(40),
+            // Near-duplicate entries share cells.
+            Palette::new(both_sets),
+            Palette::new(TARGET_COLORS.to_vec()).with_min_saturation(0.2, &[0x83817E]),
+            Palette::new(TARGET_COLORS.to_vec()).with_unblend(Some((0x202020, 0.8))),
+        ];
+        for palette in &palettes {
+            // Twice over, so the second pass is answered from the fuzzy cache.
+            for _ in 0..2 {
+                for color in sampled_colors() {
+                    assert_eq!(palette.matches(color), linear_match(palette, color), "{:06X}", color);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Golden-image checks: saved tab strip captures in `tests/fixtures` run
+//! through the offline entry points, asserting the hovered group index.
+//!
+//! Each fixture is a 60 px strip (96 DPI) with group pills straddling the
+//! scan line at y=30:
+//!
+//! - `dark.png`: Edge dark theme, groups at 40..160 and 220..360
+//! - `light.png`: Edge light theme, the same spans
+//! - `collapsed.png`: 40..160, a 12 px collapsed chip at 200..212, 260..380
+//! - `edge.png`: 40..160 and a group at 240..480 running to the right edge
+
+use hover_detector::{capture_source_from_args, detect, scan_image, Config, Deadline, Reason};
+
+fn fixture_path(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn fixture(name: &str) -> image::RgbaImage {
+    image::open(fixture_path(name)).expect("fixture loads").to_rgba8()
+}
+
+/// Hovered index for a cursor at `x` on the scan line of `name`.
+fn index_at(name: &str, x: i32) -> u32 {
+    scan_image(&fixture(name), (x, 30), &Config::default())
+        .expect("scan succeeds")
+        .index
+}
+
+#[test]
+fn dark_theme_groups() {
+    assert_eq!(index_at("dark.png", 5), 0);
+    assert_eq!(index_at("dark.png", 40), 1);
+    assert_eq!(index_at("dark.png", 100), 1);
+    assert_eq!(index_at("dark.png", 159), 1);
+    assert_eq!(index_at("dark.png", 190), 0);
+    assert_eq!(index_at("dark.png", 300), 2);
+    assert_eq!(index_at("dark.png", 500), 0);
+}
+
+#[test]
+fn light_theme_groups() {
+    assert_eq!(index_at("light.png", 5), 0);
+    assert_eq!(index_at("light.png", 100), 1);
+    assert_eq!(index_at("light.png", 190), 0);
+    assert_eq!(index_at("light.png", 300), 2);
+}
+
+#[test]
+fn collapsed_chip_keeps_its_place() {
+    assert_eq!(index_at("collapsed.png", 100), 1);
+    assert_eq!(index_at("collapsed.png", 205), 2);
+    assert_eq!(index_at("collapsed.png", 300), 3);
+    assert_eq!(index_at("collapsed.png", 230), 0);
+}
+
+#[test]
+fn collapsed_chip_is_flagged_among_expanded_groups() {
+    let result = scan_image(&fixture("collapsed.png"), (205, 30), &Config { enumerate: true, ..Config::default() })
+        .expect("scan succeeds");
+    let layout: Vec<(u32, u32, Option<bool>)> =
+        result.groups.iter().map(|group| (group.start, group.end, group.collapsed)).collect();
+    assert_eq!(layout, vec![(40, 160, Some(false)), (200, 212, Some(true)), (260, 380, Some(false))]);
+    assert_eq!(result.index, 2);
+}
+
+#[test]
+fn group_running_to_the_window_edge() {
+    // No background after the second group: it is closed by the tail of the
+    // scan rather than by a gap.
+    let result = scan_image(&fixture("edge.png"), (479, 30), &Config { enumerate: true, ..Config::default() })
+        .expect("scan succeeds");
+    assert_eq!(result.index, 2);
+    assert_eq!(result.reason, Reason::Hit);
+    let spans: Vec<(u32, u32)> = result.groups.iter().map(|group| (group.start, group.end)).collect();
+    assert_eq!(spans, vec![(40, 160), (240, 480)]);
+    assert_eq!(index_at("edge.png", 300), 2);
+    assert_eq!(index_at("edge.png", 200), 0);
+}
+
+#[test]
+fn input_image_args_map_the_cursor_through_the_window_origin() {
+    let args: Vec<String> = ["--input-image", &fixture_path("dark.png"), "--window-origin", "100,200", "--cursor", "400,230"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    let source = capture_source_from_args(&args).expect("args parse");
+    let detection = detect(source, false, Deadline::none()).expect("scan succeeds");
+    assert_eq!(detection.index, 2);
+
+    let args: Vec<String> = ["--input-image", &fixture_path("edge.png"), "--cursor", "150,30"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    let detection = detect(capture_source_from_args(&args).expect("args parse"), false, Deadline::none())
+        .expect("scan succeeds");
+    assert_eq!(detection.index, 1);
+}
@@ -1,12 +1,20 @@
 use std::{
     env,
-    io::{self, Read, Write},
-    process::Command,
-    fs::OpenOptions,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Output, Stdio},
+    fs::{self, File, OpenOptions},
+    collections::VecDeque,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
 };
 use anyhow::{Context, Result};
-use byteorder::{LittleEndian, WriteBytesExt};
-use log::{error, info, debug};
+use byteorder::{LittleEndian, ReadBytesExt};
+use log::{error, info, debug, warn};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -16,23 +24,318 @@ struct Message {
     data: serde_json::Value,
 }
 
-fn setup_logging() -> Result<()> {
-    // Set up file logging
-    let log_path = env::current_dir()?.join("native_host.log");
-    let log_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(log_path)?;
+const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+const DEFAULT_COOLDOWN_MS: u64 = 5000;
+const DEFAULT_POOL_SIZE: usize = 0;
+const DEFAULT_DETECTOR_TIMEOUT_MS: u64 = 2000;
+const DETECTOR_POLL_INTERVAL: Duration = Duration::from_millis(5);
+const DETECTOR_CACHE_FILE: &str = "detector_cache.json";
+const HOST_CONFIG_FILE: &str = "config.json";
+const LOG_BUFFER_LINES: usize = 200;
+const DEFAULT_LOG_MAX_BYTES: u64 = 5 * 1024 * 1024;
+const DEFAULT_LOG_KEEP: u32 = 3;
+// Chrome rejects messages from the host larger than 1MB.
+const MAX_OUTGOING_MESSAGE_BYTES: usize = 1024 * 1024;
+// Anything larger from the browser is a corrupt length prefix, not a request.
+const MAX_INCOMING_MESSAGE_BYTES: u32 = 1024 * 1024;
+// Bumped whenever a message or response shape changes incompatibly.
+const PROTOCOL_VERSION: u32 = 1;
 
-    // Configure env_logger to write to both stderr and file
-    env_logger::Builder::from_default_env()
-        .target(env_logger::Target::Pipe(Box::new(log_file)))
-        .init();
+/// A message that was read off stdin but refused. Its bytes have been consumed,
+/// so the stream is still in sync and the loop can answer and carry on.
+#[derive(Debug)]
+struct RejectedMessage(String);
 
-    Ok(())
+impl std::fmt::Display for RejectedMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for RejectedMessage {}
+
+fn parse_env<T: std::str::FromStr>(var_name: &str, default_value: T) -> T {
+    env::var(var_name)
+        .ok()
+        .and_then(|raw| raw.trim().parse::<T>().ok())
+        .unwrap_or(default_value)
+}
+
+/// Builds the `hover_result` payload from the detector's 1-based index
+/// (0 meaning no group is hovered).
+///
+/// `reason` is set when the host answered without running the detector, or
+/// when the detector reported one (binary results, or the plain exit code).
+///
+/// `index` is expressed in the configured `index_base`: with base 1 the first
+/// group is 1 and a miss is 0; with base 0 the first group is 0 and a miss is
+/// -1. `ordinal_zero_based` is always the 0-based position, or `null` on a miss.
+fn hover_result_data(raw_index: u32, index_base: u32, reason: Option<&str>) -> serde_json::Value {
+    let ordinal = raw_index.checked_sub(1);
+    let index = match (index_base, ordinal) {
+        (0, Some(ordinal)) => ordinal as i64,
+        (0, None) => -1,
+        (_, _) => raw_index as i64,
+    };
+    serde_json::json!({
+        "index": index,
+        "index_base": index_base,
+        "ordinal_zero_based": ordinal,
+        "reason": reason,
+        "partial": false,
+    })
+}
+
+/// Stops spawning the hover detector after repeated failures.
+///
+/// After `failure_threshold` consecutive failures the breaker opens and every
+/// request is answered with `detector_unavailable` until `cooldown` elapses.
+/// The first request after the cooldown is let through as a probe: success
+/// closes the breaker, failure re-opens it for another cooldown.
+struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            consecutive_failures: 0,
+            open_until: None,
+        }
+    }
+
+    fn from_env() -> Self {
+        Self::new(
+            parse_env("TABGROUP_DETECTOR_FAILURE_THRESHOLD", DEFAULT_FAILURE_THRESHOLD),
+            Duration::from_millis(parse_env("TABGROUP_DETECTOR_COOLDOWN_MS", DEFAULT_COOLDOWN_MS)),
+        )
+    }
+
+    /// Returns the remaining cooldown if the detector must not be spawned at `now`.
+    fn blocked_for(&self, now: Instant) -> Option<Duration> {
+        self.open_until
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+
+    fn record_success(&mut self) {
+        self.consecutive_failures = 0;
+        self.open_until = None;
+    }
+
+    fn record_failure(&mut self, now: Instant) {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        if self.consecutive_failures >= self.failure_threshold {
+            self.open_until = Some(now + self.cooldown);
+        }
+    }
+}
+
+/// Most recent log lines, kept in memory so diagnostics can include them.
+type LogBuffer = Arc<Mutex<VecDeque<String>>>;
+
+/// The log file, renamed to `<name>.1` (shifting older ones up to `keep`)
+/// and started afresh once it would grow past `max_bytes`; `0` never rotates.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    max_bytes: u64,
+    keep: u32,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, keep: u32) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self { path, file, len, max_bytes, keep })
+    }
+
+    fn rotated_path(&self, n: u32) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            self.file.set_len(0)?;
+        } else {
+            let _ = fs::remove_file(self.rotated_path(self.keep));
+            for n in (1..self.keep).rev() {
+                let _ = fs::rename(self.rotated_path(n), self.rotated_path(n + 1));
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+            self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        }
+        self.len = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.max_bytes > 0 && self.len > 0 && self.len + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Writes log output to the log file (stderr when it could not be opened)
+/// and mirrors complete lines into a bounded in-memory buffer.
+struct TeeWriter {
+    file: Option<RotatingFile>,
+    buffer: LogBuffer,
+    partial_line: Vec<u8>,
 }
 
-fn read_message<R: Read>(mut input: R) -> Result<Option<Message>> {
+impl Write for TeeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = match &mut self.file {
+            Some(file) => file.write(buf)?,
+            None => io::stderr().write(buf)?,
+        };
+        self.partial_line.extend_from_slice(&buf[..written]);
+        while let Some(pos) = self.partial_line.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.partial_line.drain(..=pos).collect();
+            if let Ok(mut lines) = self.buffer.lock() {
+                if lines.len() >= LOG_BUFFER_LINES {
+                    lines.pop_front();
+                }
+                lines.push_back(String::from_utf8_lossy(&line).trim_end().to_string());
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => io::stderr().flush(),
+        }
+    }
+}
+
+/// `TABGROUP_LOG_PATH`, else `native_host.log` next to the executable: the
+/// browser starts the host in a directory of its own choosing, which may not
+/// be writable.
+fn log_path() -> Result<PathBuf> {
+    resolve_log_path(env::var_os("TABGROUP_LOG_PATH"), env::current_exe)
+}
+
+fn resolve_log_path(
+    configured: Option<std::ffi::OsString>,
+    current_exe: impl FnOnce() -> io::Result<PathBuf>,
+) -> Result<PathBuf> {
+    if let Some(path) = configured.filter(|path| !path.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+    let exe_path = current_exe()?;
+    let exe_dir = exe_path.parent()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get executable directory"))?;
+    Ok(exe_dir.join("native_host.log"))
+}
+
+fn setup_logging() -> Result<LogBuffer> {
+    // Set up file logging; without a usable log file, log to stderr only
+    // instead of refusing to start.
+    let opened = log_path().and_then(|path| {
+        RotatingFile::open(
+            path.clone(),
+            parse_env("TABGROUP_LOG_MAX_BYTES", DEFAULT_LOG_MAX_BYTES),
+            parse_env("TABGROUP_LOG_KEEP", DEFAULT_LOG_KEEP),
+        )
+        .with_context(|| format!("Failed to open log file {}", path.display()))
+    });
+    let (log_file, open_error) = match opened {
+        Ok(file) => (Some(file), None),
+        Err(e) => (None, Some(e)),
+    };
+    let buffer = LogBuffer::default();
+
+    // Configure env_logger to write to both the file and the in-memory buffer
+    let mut builder = env_logger::Builder::from_default_env();
+    builder.target(env_logger::Target::Pipe(Box::new(TeeWriter {
+        file: log_file,
+        buffer: buffer.clone(),
+        partial_line: Vec::new(),
+    })));
+    if env::var("TABGROUP_LOG_JSON").is_ok_and(|raw| matches!(raw.trim(), "1" | "true")) {
+        // One object per line, for log viewers.
+        builder.format(|buf, record| writeln!(buf, "{}", json_log_line(&buf.timestamp().to_string(), record)));
+    }
+    builder.init();
+    if let Some(e) = open_error {
+        error!("{:#}; logging to stderr only", e);
+    }
+
+    Ok(buffer)
+}
+
+/// One log record as a `TABGROUP_LOG_JSON` line.
+fn json_log_line(timestamp: &str, record: &log::Record) -> serde_json::Value {
+    serde_json::json!({
+        "timestamp": timestamp,
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    })
+}
+
+/// Byte order of the 4-byte length prefix of native messages. Chrome uses the
+/// platform's native order, which is little-endian on every Windows target.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ByteOrder {
+    Native,
+    Little,
+    Big,
+}
+
+impl std::str::FromStr for ByteOrder {
+    type Err = ();
+
+    fn from_str(raw: &str) -> std::result::Result<Self, ()> {
+        match raw {
+            "native" => Ok(Self::Native),
+            "little" => Ok(Self::Little),
+            "big" => Ok(Self::Big),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ByteOrder {
+    fn encode_length(self, length: u32) -> [u8; 4] {
+        match self {
+            Self::Native => length.to_ne_bytes(),
+            Self::Little => length.to_le_bytes(),
+            Self::Big => length.to_be_bytes(),
+        }
+    }
+
+    fn decode_length(self, bytes: [u8; 4]) -> u32 {
+        match self {
+            Self::Native => u32::from_ne_bytes(bytes),
+            Self::Little => u32::from_le_bytes(bytes),
+            Self::Big => u32::from_be_bytes(bytes),
+        }
+    }
+}
+
+fn read_message<R: Read>(mut input: R, order: ByteOrder) -> Result<Option<Message>> {
     info!("Attempting to read message...");
     
     // Try to read first byte to check if stdin is closed
@@ -65,9 +368,26 @@ fn read_message<R: Read>(mut input: R) -> Result<Option<Message>> {
 
     // Combine all 4 bytes and convert to u32
     let length_buf = [first_byte[0], length_bytes[0], length_bytes[1], length_bytes[2]];
-    let length = u32::from_le_bytes(length_buf);
+    let length = order.decode_length(length_buf);
     info!("Message length: {} bytes", length);
 
+    if length > MAX_INCOMING_MESSAGE_BYTES {
+        error!("Message length {} exceeds the {} byte limit, skipping it", length, MAX_INCOMING_MESSAGE_BYTES);
+        // Drain the body without buffering it so the next prefix lines up.
+        io::copy(&mut input.by_ref().take(length as u64), &mut io::sink())?;
+        return Err(RejectedMessage(format!(
+            "Message of {} bytes exceeds the {} byte limit",
+            length, MAX_INCOMING_MESSAGE_BYTES
+        ))
+        .into());
+    }
+
+    // An empty frame carries no request; it is an error, not a keepalive.
+    if length == 0 {
+        error!("Received a zero-length message");
+        return Err(RejectedMessage("Empty message (zero length prefix)".to_string()).into());
+    }
+
     // Read the message content
     let mut buffer = vec![0; length as usize];
     match input.read_exact(&mut buffer) {
@@ -97,13 +417,15 @@ fn read_message<R: Read>(mut input: R) -> Result<Option<Message>> {
             Ok(Some(message))
         }
         Err(e) => {
+            // One bad frame should not drop the connection; the next one is
+            // already lined up.
             error!("Failed to parse message as JSON: {}", e);
-            Err(e.into())
+            Err(RejectedMessage(format!("Failed to parse message as JSON: {}", e)).into())
         }
     }
 }
 
-fn write_message<W: Write>(mut output: W, message: &Message) -> Result<()> {
+fn write_message<W: Write>(mut output: W, message: &Message, order: ByteOrder) -> Result<()> {
     debug!("Writing message: {:?}", message);
     
     // Serialize message to JSON
@@ -112,8 +434,7 @@ fn write_message<W: Write>(mut output: W, message: &Message) -> Result<()> {
     
     debug!("Message serialized, length: {}", content.len());
     
-    // Write message length (little-endian)
-    output.write_u32::<LittleEndian>(content.len() as u32)
+    output.write_all(&order.encode_length(content.len() as u32))
         .context("Failed to write message length")?;
     
     // Write message content
@@ -126,12 +447,46 @@ fn write_message<W: Write>(mut output: W, message: &Message) -> Result<()> {
     Ok(())
 }
 
-fn check_hovered_group() -> Result<u32> {
+/// Detector path set through `TABGROUP_DETECTOR_PATH`, or else the
+/// `detector_path` of a `config.json` next to the host. Relative paths in the
+/// config file are taken from the host's directory. `None` if neither is set.
+fn configured_detector_path(exe_dir: &Path) -> Result<Option<(PathBuf, &'static str)>> {
+    if let Some(path) = env::var_os("TABGROUP_DETECTOR_PATH").filter(|path| !path.is_empty()) {
+        return Ok(Some((PathBuf::from(path), "TABGROUP_DETECTOR_PATH")));
+    }
+    let config_file = exe_dir.join(HOST_CONFIG_FILE);
+    let raw = match fs::read(&config_file) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", config_file.display())),
+    };
+    let config: serde_json::Value = serde_json::from_slice(&raw)
+        .with_context(|| format!("Failed to parse {}", config_file.display()))?;
+    match &config["detector_path"] {
+        serde_json::Value::Null => Ok(None),
+        serde_json::Value::String(path) => Ok(Some((exe_dir.join(path), HOST_CONFIG_FILE))),
+        other => anyhow::bail!("detector_path in {} must be a string, got {}", config_file.display(), other),
+    }
+}
+
+/// The configured detector path, or the one in this repository's build
+/// layout, checked to exist so a missing binary is reported by name.
+fn resolve_detector_path() -> Result<PathBuf> {
     // Get path of current executable
     let exe_path = env::current_exe()?;
     let exe_dir = exe_path.parent()
         .ok_or_else(|| anyhow::anyhow!("Failed to get executable directory"))?;
-    
+    let (path, source) = match configured_detector_path(exe_dir)? {
+        Some(configured) => configured,
+        None => (default_detector_path(exe_dir)?, "default location"),
+    };
+    if !path.is_file() {
+        anyhow::bail!("Hover detector not found at {} (from {})", path.display(), source);
+    }
+    Ok(path)
+}
+
+fn default_detector_path(exe_dir: &Path) -> Result<PathBuf> {
     // Go up to project root: native-host/target/release -> native-host/target -> native-host -> root
     let project_root = exe_dir
         .parent().ok_or_else(|| anyhow::anyhow!("Failed to get parent of release dir"))?
@@ -139,51 +494,1099 @@ fn check_hovered_group() -> Result<u32> {
         .parent().ok_or_else(|| anyhow::anyhow!("Failed to get parent of native-host dir"))?;
     
     // Find hover detector relative to project root
-    let detector_path = project_root
+    Ok(project_root
         .join("hover-detector")
         .join("target")
         .join("release")
-        .join("hover-detector.exe");
-    
+        .join("hover-detector.exe"))
+}
+
+/// What a detector build supports, from `hover-detector --capabilities`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct DetectorCapabilities {
+    version: String,
+    /// Understands `--binary`.
+    binary: bool,
+    /// Understands `--serve`, so it can back the detector pool.
+    serve: bool,
+}
+
+/// Asks the detector at `path` what it supports. A detector from before
+/// `--capabilities`, or one that fails to answer, yields `None`, and the
+/// host then uses its settings as configured.
+fn probe_capabilities(path: &Path) -> Option<DetectorCapabilities> {
+    let stdout = run_detector_with_env(path, &["--capabilities"], &[])
+        .map_err(|e| info!("Detector capabilities probe failed: {}", e))
+        .ok()?;
+    serde_json::from_slice(&stdout)
+        .map_err(|e| info!("Detector did not report its capabilities: {}", e))
+        .ok()
+}
+
+/// Resolved detector path persisted between host runs, together with enough
+/// of the binary's identity to notice when it has been replaced, and what
+/// that binary reported it supports.
+#[derive(Debug, Serialize, Deserialize)]
+struct DetectorCacheEntry {
+    path: PathBuf,
+    len: u64,
+    modified_secs: u64,
+    hash: u64,
+    #[serde(default)]
+    capabilities: Option<DetectorCapabilities>,
+}
+
+enum CacheValidity {
+    Valid,
+    Missing,
+    Changed,
+}
+
+fn detector_cache_file() -> Result<PathBuf> {
+    let exe_path = env::current_exe()?;
+    let exe_dir = exe_path.parent()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get executable directory"))?;
+    Ok(exe_dir.join(DETECTOR_CACHE_FILE))
+}
+
+/// FNV-1a over the file contents; stable across builds, unlike `DefaultHasher`.
+fn hash_file(path: &Path) -> Result<u64> {
+    let bytes = fs::read(path)
+        .with_context(|| format!("Failed to read {} for hashing", path.display()))?;
+    Ok(bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    }))
+}
+
+fn file_fingerprint(path: &Path) -> Result<(u64, u64)> {
+    let metadata = fs::metadata(path)?;
+    let modified_secs = metadata
+        .modified()?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    Ok((metadata.len(), modified_secs))
+}
+
+impl DetectorCacheEntry {
+    fn build(path: PathBuf, capabilities: Option<DetectorCapabilities>) -> Result<Self> {
+        let (len, modified_secs) = file_fingerprint(&path)?;
+        let hash = hash_file(&path)?;
+        Ok(Self { path, len, modified_secs, hash, capabilities })
+    }
+
+    fn load(cache_file: &Path) -> Option<Self> {
+        let raw = fs::read(cache_file).ok()?;
+        serde_json::from_slice(&raw)
+            .map_err(|e| info!("Ignoring unreadable detector cache: {}", e))
+            .ok()
+    }
+
+    fn store(&self, cache_file: &Path) -> Result<()> {
+        fs::write(cache_file, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", cache_file.display()))
+    }
+
+    fn validate(&self) -> CacheValidity {
+        let Ok((len, modified_secs)) = file_fingerprint(&self.path) else {
+            return CacheValidity::Missing;
+        };
+        // Size and mtime unchanged is good enough to skip rehashing the binary.
+        if len == self.len && modified_secs == self.modified_secs {
+            return CacheValidity::Valid;
+        }
+        match hash_file(&self.path) {
+            Ok(hash) if hash == self.hash => CacheValidity::Valid,
+            Ok(_) => CacheValidity::Changed,
+            Err(_) => CacheValidity::Missing,
+        }
+    }
+}
+
+/// Returns the detector path and capabilities from the state file when it
+/// still points at the same binary, otherwise re-resolves the path, probes
+/// the binary's capabilities again and refreshes the state file.
+fn resolve_detector_path_cached() -> Result<(PathBuf, Option<DetectorCapabilities>)> {
+    let cache_file = detector_cache_file()?;
+    // An explicit path always wins over a remembered one.
+    if let Some(exe_dir) = cache_file.parent() {
+        if configured_detector_path(exe_dir)?.is_some() {
+            let path = resolve_detector_path()?;
+            let capabilities = probe_capabilities(&path);
+            return Ok((path, capabilities));
+        }
+    }
+    cached_detector(&cache_file, resolve_detector_path, probe_capabilities)
+}
+
+/// [`resolve_detector_path_cached`] for an unconfigured path, with the
+/// resolution and the capabilities probe passed in.
+fn cached_detector(
+    cache_file: &Path,
+    resolve: impl FnOnce() -> Result<PathBuf>,
+    probe: impl FnOnce(&Path) -> Option<DetectorCapabilities>,
+) -> Result<(PathBuf, Option<DetectorCapabilities>)> {
+    if let Some(entry) = DetectorCacheEntry::load(cache_file) {
+        match entry.validate() {
+            CacheValidity::Valid => {
+                debug!("Using cached detector path: {}", entry.path.display());
+                return Ok((entry.path, entry.capabilities));
+            }
+            CacheValidity::Missing => {
+                info!("Cached detector path no longer exists: {}", entry.path.display());
+            }
+            CacheValidity::Changed => {
+                info!(
+                    "Detector binary changed since last run, re-checking its capabilities: {}",
+                    entry.path.display()
+                );
+            }
+        }
+    }
+
+    let path = resolve()?;
+    let capabilities = probe(&path);
+    match &capabilities {
+        Some(found) => info!(
+            "Detector {} capabilities: binary={}, serve={}",
+            found.version, found.binary, found.serve
+        ),
+        None => info!("Detector capabilities unknown, using the configured settings"),
+    }
+    match DetectorCacheEntry::build(path.clone(), capabilities.clone()) {
+        Ok(entry) => {
+            if let Err(e) = entry.store(cache_file) {
+                error!("Failed to update detector cache: {}", e);
+            }
+        }
+        Err(e) => debug!("Not caching detector path: {}", e),
+    }
+    Ok((path, capabilities))
+}
+
+/// Runs the detector with extra environment variables set for that run only.
+fn run_detector_with_env(detector_path: &Path, args: &[&str], envs: &[(&str, String)]) -> Result<Vec<u8>> {
+    let output = spawn_detector(detector_path, args, envs)?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        error!("Hover detector failed: {}", error);
+        anyhow::bail!("Hover detector failed: {}", error);
+    }
+
+    Ok(output.stdout)
+}
+
+/// Runs the detector to completion and returns its output, whatever the exit
+/// status.
+///
+/// A detector still running after `TABGROUP_DETECTOR_TIMEOUT_MS` (0 waits
+/// forever) is killed and reported as an error, so a capture that hangs does
+/// not hang the host with it.
+fn spawn_detector(detector_path: &Path, args: &[&str], envs: &[(&str, String)]) -> Result<Output> {
     let detector_path = detector_path.to_str()
         .ok_or_else(|| anyhow::anyhow!("Invalid path to hover detector"))?;
     
-    info!("Running hover detector: {}", detector_path);
+    info!("Running hover detector: {} {:?} {:?}", detector_path, args, envs);
     
     // Run hover detector and capture output
-    let output = Command::new(detector_path)
-        .output()
+    let mut child = Command::new(detector_path)
+        .args(args)
+        .envs(envs.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
         .with_context(|| format!("Failed to execute hover detector at {}", detector_path))?;
+    // Both pipes are read on their own threads so a detector that writes a lot
+    // cannot stall on a full pipe while we wait for it.
+    let stdout = drain_pipe(child.stdout.take());
+    let stderr = drain_pipe(child.stderr.take());
 
-    if !output.status.success() {
-        let error = String::from_utf8_lossy(&output.stderr);
-        error!("Hover detector failed: {}", error);
-        anyhow::bail!("Hover detector failed: {}", error);
+    let timeout = Duration::from_millis(parse_env("TABGROUP_DETECTOR_TIMEOUT_MS", DEFAULT_DETECTOR_TIMEOUT_MS));
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().context("Failed to wait for hover detector")? {
+            break status;
+        }
+        if !timeout.is_zero() && started.elapsed() >= timeout {
+            // Killing closes the pipes, which lets the reader threads finish.
+            let _ = child.kill();
+            let _ = child.wait();
+            error!("Hover detector timed out after {:?}, killed it", started.elapsed());
+            anyhow::bail!("Hover detector timed out after {} ms", started.elapsed().as_millis());
+        }
+        thread::sleep(DETECTOR_POLL_INTERVAL);
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+/// Reads a child pipe to its end on a background thread.
+fn drain_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        bytes
+    })
+}
+
+/// Exit codes of a plain detector run that answer "no group" for a known
+/// reason, with the reason reported for each. 0 is a hit, index on stdout.
+const DETECTOR_EXIT_REASONS: [(i32, &str); 3] = [
+    (10, "NotBrowser"),
+    (11, "CursorOutside"),
+    (12, "NotOnGroup"),
+];
+
+/// Detector `Reason` names by `--binary` wire code, in the detector's order.
+const DETECTOR_REASONS: [&str; 13] = [
+    "Hit",
+    "NoBrowserWindow",
+    "NotBrowser",
+    "CursorOutside",
+    "NotOnGroup",
+    "NoGroupAtCursor",
+    "AccessibilityFilterActive",
+    "DeadlineExceeded",
+    "NoTabStrip",
+    "PopupActive",
+    "WindowInMotion",
+    "IndexIgnored",
+    "StripOccluded",
+];
+
+/// What the detector answered for one hover check.
+#[derive(Debug, PartialEq)]
+struct HoverAnswer {
+    index: u32,
+    /// A deadline cut the run short, so `index` is best-effort.
+    partial: bool,
+    /// Not reported by pooled workers.
+    reason: Option<&'static str>,
+    /// Only reported by the binary format.
+    color: Option<u32>,
+    /// Unix time in milliseconds of the capture the answer comes from, when
+    /// the detector reports it (not in the binary format).
+    captured_at_ms: Option<u64>,
+}
+
+/// Runs a hover check. With `binary`, the detector answers in the compact
+/// `--binary` layout, which also carries the reason and cursor color. With
+/// `verbose`, this one run writes detailed logs and screenshots. `envs` are
+/// extra detector settings, such as those from `set_config`.
+fn check_hovered_group(
+    detector_path: &Path,
+    deadline_ms: Option<u64>,
+    binary: bool,
+    verbose: bool,
+    envs: &[(&'static str, String)],
+) -> Result<HoverAnswer> {
+    let deadline_arg = deadline_ms.map(|ms| ms.to_string());
+    let mut args = Vec::new();
+    if let Some(ms) = &deadline_arg {
+        args.extend(["--deadline-ms", ms.as_str()]);
+    }
+    let mut envs = envs.to_vec();
+    if verbose {
+        envs.push(("TABGROUP_HOVER_DETECTOR_VERBOSE", "1".to_string()));
+    }
+    if binary {
+        args.push("--binary");
+        let stdout = run_detector_with_env(detector_path, &args, &envs)?;
+        return decode_binary_result(&stdout);
+    }
+    let output = spawn_detector(detector_path, &args, &envs)?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    debug!("Hover detector output: {}", stdout);
+    if output.status.success() || output.status.code().and_then(exit_code_reason).is_some() {
+        return interpret_plain_output(output.status.code(), &stdout);
     }
+    let error = String::from_utf8_lossy(&output.stderr);
+    error!("Hover detector failed: {}", error);
+    anyhow::bail!("Hover detector failed: {}", error);
+}
 
-    // Convert output to string and parse as number
-    let index_str = String::from_utf8_lossy(&output.stdout);
-    debug!("Hover detector output: {}", index_str);
-    
-    let index = index_str.trim().parse::<u32>()
+/// The reason a plain detector run's exit code stands for, if it is one of
+/// the "no group" codes.
+fn exit_code_reason(code: i32) -> Option<&'static str> {
+    DETECTOR_EXIT_REASONS
+        .iter()
+        .find(|(known, _)| *known == code)
+        .map(|&(_, reason)| reason)
+}
+
+/// Reads the answer of a plain run that exited with 0 or a "no group" code.
+/// Stdout comes first: it has the `partial` flag and the exact reason. The
+/// exit code only stands in for the reason when the output does not name
+/// one (detectors from before `reason=`).
+fn interpret_plain_output(code: Option<i32>, stdout: &str) -> Result<HoverAnswer> {
+    let exit_reason = code.and_then(exit_code_reason);
+    let mut answer = match (parse_detector_output(stdout), exit_reason) {
+        (Ok(answer), _) => answer,
+        // Nothing readable on stdout, but the exit code still answers.
+        (Err(_), Some(_)) => HoverAnswer { index: 0, partial: false, reason: None, color: None, captured_at_ms: None },
+        (Err(e), None) => return Err(e),
+    };
+    answer.reason = answer.reason.or(exit_reason);
+    if exit_reason.is_some() {
+        info!("Hover detector returned no group ({})", answer.reason.unwrap_or_default());
+    }
+    Ok(answer)
+}
+
+/// Parses the plain detector answer: the index, followed by `partial` when a
+/// deadline cut the run short and `reason=<code>` with the reason wire code.
+fn parse_detector_output(output: &str) -> Result<HoverAnswer> {
+    let mut tokens = output.split_whitespace();
+    let index = tokens.next().unwrap_or_default().parse::<u32>()
         .context("Failed to parse hover detector output as number")?;
-    
-    info!("Hover detector returned index: {}", index);
-    Ok(index)
+    let mut partial = false;
+    let mut reason = None;
+    let mut captured_at_ms = None;
+    for token in tokens {
+        if token == "partial" {
+            partial = true;
+        } else if let Some(raw) = token.strip_prefix("reason=") {
+            let code = raw.parse::<usize>()
+                .with_context(|| format!("Invalid detector reason '{}'", raw))?;
+            reason = Some(*DETECTOR_REASONS
+                .get(code)
+                .ok_or_else(|| anyhow::anyhow!("Unknown detector reason code {}", code))?);
+        } else if let Some(raw) = token.strip_prefix("captured_at_ms=") {
+            captured_at_ms = Some(raw.parse::<u64>()
+                .with_context(|| format!("Invalid detector capture time '{}'", raw))?);
+        }
+    }
+
+    info!("Hover detector returned index: {}{}", index, if partial { " (partial)" } else { "" });
+    Ok(HoverAnswer {
+        index,
+        partial,
+        reason,
+        color: None,
+        captured_at_ms,
+    })
+}
+
+/// Decodes a `--binary` answer: a u32 LE length followed by index (u32 LE),
+/// reason code (u8) and cursor color (u32 LE, `u32::MAX` for none).
+fn decode_binary_result(bytes: &[u8]) -> Result<HoverAnswer> {
+    let mut input = bytes;
+    let length = input.read_u32::<LittleEndian>()
+        .context("Binary detector result is missing its length")? as usize;
+    if length != 9 || input.len() < length {
+        anyhow::bail!("Binary detector result has length {}, expected 9 ({} bytes available)", length, input.len());
+    }
+    let index = input.read_u32::<LittleEndian>()?;
+    let code = input.read_u8()?;
+    let color = input.read_u32::<LittleEndian>()?;
+    let reason = *DETECTOR_REASONS
+        .get(code as usize)
+        .ok_or_else(|| anyhow::anyhow!("Unknown detector reason code {}", code))?;
+
+    info!("Hover detector returned index: {} ({})", index, reason);
+    Ok(HoverAnswer {
+        index,
+        partial: reason == "DeadlineExceeded",
+        reason: Some(reason),
+        color: (color != u32::MAX).then_some(color),
+        captured_at_ms: None,
+    })
+}
+
+/// A detector started with `--serve`, waiting for requests on its stdin.
+struct PoolWorker {
+    child: Child,
+    stdin: ChildStdin,
+    /// Lines of its stdout, read on a thread of their own so a request can
+    /// give up on an answer after `timeout`.
+    lines: Receiver<String>,
+    /// `TABGROUP_DETECTOR_TIMEOUT_MS`; zero waits forever.
+    timeout: Duration,
+    /// The last request outlived `timeout` and the worker was killed.
+    timed_out: bool,
+}
+
+impl PoolWorker {
+    fn spawn(detector_path: &Path, envs: &[(&'static str, String)]) -> Result<Self> {
+        let mut child = Command::new(detector_path)
+            .arg("--serve")
+            .envs(envs.iter().map(|(name, value)| (name, value)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to start pooled hover detector at {}", detector_path.display()))?;
+        let stdin = child.stdin.take().context("Pooled detector has no stdin")?;
+        let stdout = child.stdout.take().context("Pooled detector has no stdout")?;
+        debug!("Spawned pooled hover detector (pid {})", child.id());
+        Ok(Self {
+            child,
+            stdin,
+            lines: spawn_line_reader(stdout),
+            timeout: Duration::from_millis(parse_env("TABGROUP_DETECTOR_TIMEOUT_MS", DEFAULT_DETECTOR_TIMEOUT_MS)),
+            timed_out: false,
+        })
+    }
+
+    fn is_alive(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    fn request(&mut self, deadline_ms: Option<u64>) -> Result<HoverAnswer> {
+        writeln!(self.stdin, "{}", deadline_ms.map(|ms| ms.to_string()).unwrap_or_default())?;
+        self.stdin.flush()?;
+        let line = match recv_line(&self.lines, self.timeout) {
+            Ok(line) => line,
+            Err(RecvTimeoutError::Disconnected) => anyhow::bail!("Pooled hover detector exited"),
+            Err(RecvTimeoutError::Timeout) => {
+                // Its answer may still arrive and would be read as the next
+                // one; the pool replaces the worker instead.
+                let _ = self.child.kill();
+                let _ = self.child.wait();
+                self.timed_out = true;
+                error!("Pooled hover detector timed out after {:?}, killed it", self.timeout);
+                anyhow::bail!("Hover detector timed out after {} ms", self.timeout.as_millis());
+            }
+        };
+        debug!("Pooled hover detector output: {}", line.trim());
+        if let Some(message) = line.trim().strip_prefix("error ") {
+            anyhow::bail!("Hover detector failed: {}", message);
+        }
+        parse_detector_output(&line)
+    }
+}
+
+/// Forwards the lines of `pipe` over a channel from a background thread. The
+/// channel closes once the pipe does.
+fn spawn_line_reader<R: Read + Send + 'static>(pipe: R) -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(pipe);
+        loop {
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) if sender.send(line).is_err() => break,
+                Ok(_) => {}
+            }
+        }
+    });
+    receiver
+}
+
+/// The next line from `lines`, waiting at most `timeout` (zero waits forever).
+fn recv_line(lines: &Receiver<String>, timeout: Duration) -> Result<String, RecvTimeoutError> {
+    if timeout.is_zero() {
+        return lines.recv().map_err(|_| RecvTimeoutError::Disconnected);
+    }
+    lines.recv_timeout(timeout)
+}
+
+impl Drop for PoolWorker {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Pre-spawned `--serve` detectors, so a hover check does not wait for a
+/// process start. Dead workers are replaced to keep the pool at `size`.
+struct DetectorPool {
+    size: usize,
+    detector_path: Option<PathBuf>,
+    /// Settings workers are started with; changing them retires idle workers.
+    envs: Vec<(&'static str, String)>,
+    idle: VecDeque<PoolWorker>,
+}
+
+impl DetectorPool {
+    fn new(size: usize) -> Self {
+        Self {
+            size,
+            detector_path: None,
+            envs: Vec::new(),
+            idle: VecDeque::new(),
+        }
+    }
+
+    fn from_env() -> Self {
+        Self::new(parse_env("TABGROUP_DETECTOR_POOL_SIZE", DEFAULT_POOL_SIZE))
+    }
+
+    fn enabled(&self) -> bool {
+        self.size > 0
+    }
+
+    /// Workers keep the environment they were started with, so new settings
+    /// only reach the ones spawned after this.
+    fn set_envs(&mut self, envs: Vec<(&'static str, String)>) {
+        if self.envs != envs {
+            self.idle.clear();
+            self.envs = envs;
+        }
+    }
+
+    /// Drops dead workers and spawns new ones up to the pool size.
+    fn refill(&mut self, detector_path: &Path) {
+        if self.detector_path.as_deref() != Some(detector_path) {
+            self.idle.clear();
+            self.detector_path = Some(detector_path.to_path_buf());
+        }
+        self.idle.retain_mut(|worker| {
+            let alive = worker.is_alive();
+            if !alive {
+                info!("Pooled hover detector died, replacing it");
+            }
+            alive
+        });
+        while self.idle.len() < self.size {
+            match PoolWorker::spawn(detector_path, &self.envs) {
+                Ok(worker) => self.idle.push_back(worker),
+                Err(e) => {
+                    error!("Failed to refill detector pool: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Hands the check to an idle worker. A worker that turns out to be dead
+    /// is replaced and the check retried once on the fresh one.
+    fn check_hovered_group(&mut self, detector_path: &Path, deadline_ms: Option<u64>) -> Result<HoverAnswer> {
+        self.refill(detector_path);
+        let mut worker = match self.idle.pop_front() {
+            Some(worker) => worker,
+            None => PoolWorker::spawn(detector_path, &self.envs)?,
+        };
+        let mut result = worker.request(deadline_ms);
+        // A worker killed for taking too long is not retried: another would
+        // most likely hang on the same capture. Refilling replaces it.
+        if result.is_err() && !worker.timed_out && !worker.is_alive() {
+            info!("Pooled hover detector died mid-request, retrying on a new one");
+            worker = PoolWorker::spawn(detector_path, &self.envs)?;
+            result = worker.request(deadline_ms);
+        }
+        if worker.is_alive() {
+            self.idle.push_back(worker);
+        }
+        self.refill(detector_path);
+        result
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+fn error_response(message: String) -> Message {
+    Message {
+        message_type: "error".to_string(),
+        data: serde_json::json!({ 
+            "message": message
+        }),
+    }
+}
+
+/// Detection settings pushed by the extension with `set_config`, clamped to
+/// ranges the detector can use. Each one is handed to the detector as the
+/// `TABGROUP_HOVER_*` variable it already reads; unset ones leave the
+/// detector's own configuration alone.
+#[derive(Debug, Default)]
+struct DetectorTuning {
+    color_tolerance: Option<u8>,
+    vertical_threshold: Option<u32>,
+    proximity_radius: Option<u32>,
+    theme: Option<&'static str>,
+}
+
+impl DetectorTuning {
+    /// Takes the known fields of a `set_config` payload. Unknown fields and
+    /// values of the wrong type are ignored.
+    fn update(&mut self, data: &serde_json::Value) {
+        if let Some(tolerance) = data["tolerance"].as_u64() {
+            // Per-channel difference, so 255 already matches anything.
+            self.color_tolerance = Some(tolerance.min(255) as u8);
+        }
+        if let Some(threshold) = data["vertical_threshold"].as_u64() {
+            self.vertical_threshold = Some(threshold.clamp(16, 200) as u32);
+        }
+        if let Some(radius) = data["proximity_radius"].as_u64() {
+            self.proximity_radius = Some(radius.clamp(1, 16) as u32);
+        }
+        match data["theme"].as_str().map(str::trim) {
+            Some("auto") => self.theme = Some("auto"),
+            Some("dark") => self.theme = Some("dark"),
+            Some("light") => self.theme = Some("light"),
+            _ => {}
+        }
+    }
+
+    fn envs(&self) -> Vec<(&'static str, String)> {
+        let mut envs = Vec::new();
+        if let Some(tolerance) = self.color_tolerance {
+            envs.push(("TABGROUP_HOVER_COLOR_TOLERANCE", tolerance.to_string()));
+        }
+        if let Some(threshold) = self.vertical_threshold {
+            envs.push(("TABGROUP_HOVER_VERTICAL_THRESHOLD", threshold.to_string()));
+        }
+        if let Some(radius) = self.proximity_radius {
+            envs.push(("TABGROUP_HOVER_PROXIMITY_RADIUS", radius.to_string()));
+        }
+        if let Some(theme) = self.theme {
+            envs.push(("TABGROUP_HOVER_THEME", theme.to_string()));
+        }
+        envs
+    }
+
+    /// Values in effect, `null` for those left to the detector's configuration.
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "tolerance": self.color_tolerance,
+            "vertical_threshold": self.vertical_threshold,
+            "proximity_radius": self.proximity_radius,
+            "theme": self.theme,
+        })
+    }
+}
+
+/// Liveness answer to `ping`, with what the extension needs to tell which host
+/// build it is talking to.
+fn pong() -> Message {
+    Message {
+        message_type: "pong".to_string(),
+        data: serde_json::json!({
+            "pid": std::process::id(),
+            "version": env!("CARGO_PKG_VERSION"),
+        }),
+    }
+}
+
+/// Answer to `get_version`, so the extension can tell an outdated host apart
+/// from a broken one.
+fn version_info() -> Message {
+    Message {
+        message_type: "version".to_string(),
+        data: serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "protocol": PROTOCOL_VERSION,
+        }),
+    }
+}
+
+/// Warns when a message says it was written for another protocol version;
+/// messages without a `protocol` field are taken as current.
+fn check_protocol(message: &Message) {
+    if let Some(warning) = protocol_mismatch(message) {
+        warn!("{}", warning);
+    }
+}
+
+fn protocol_mismatch(message: &Message) -> Option<String> {
+    let protocol = message.data.get("protocol")?;
+    (protocol.as_u64() != Some(PROTOCOL_VERSION as u64)).then(|| {
+        format!(
+            "'{}' message uses protocol {}, this host speaks {}; the extension and host may be out of sync",
+            message.message_type, protocol, PROTOCOL_VERSION
+        )
+    })
+}
+
+/// State carried across messages for the lifetime of the host process.
+struct HostState {
+    index_base: u32,
+    breaker: CircuitBreaker,
+    detector_path: Option<PathBuf>,
+    log_buffer: LogBuffer,
+    /// Set by `pause`; `check_hover` then answers without capturing.
+    paused: bool,
+    pool: DetectorPool,
+    /// Ask the detector for the compact `--binary` result instead of text.
+    binary_results: bool,
+    /// Reported by the resolved detector, see [`probe_capabilities`].
+    capabilities: Option<DetectorCapabilities>,
+    /// Run hover checks through the linked detector library rather than a
+    /// detector process; off outside Windows or with `TABGROUP_USE_SUBPROCESS`.
+    in_process: bool,
+    /// Settings from `set_config`, applied to every detector run.
+    tuning: DetectorTuning,
+    /// The detector's caches between in-process checks.
+    #[cfg(windows)]
+    detector_session: hover_detector::Session,
+    /// Sequence number of the last response, so callers can drop results
+    /// that arrive out of order or predate a newer one.
+    seq: u64,
+}
+
+impl HostState {
+    fn new(log_buffer: LogBuffer) -> Self {
+        Self {
+            index_base: parse_env("TABGROUP_INDEX_BASE", 1u32).min(1),
+            breaker: CircuitBreaker::from_env(),
+            detector_path: None,
+            log_buffer,
+            paused: false,
+            pool: DetectorPool::from_env(),
+            binary_results: parse_env("TABGROUP_DETECTOR_BINARY", false),
+            capabilities: None,
+            in_process: cfg!(windows)
+                && !env::var("TABGROUP_USE_SUBPROCESS").is_ok_and(|raw| matches!(raw.trim(), "1" | "true")),
+            tuning: DetectorTuning::default(),
+            #[cfg(windows)]
+            detector_session: hover_detector::Session::new(),
+            seq: 0,
+        }
+    }
+
+    fn detector_path(&mut self) -> Result<PathBuf> {
+        let path = match self.detector_path.take().filter(|path| path.exists()) {
+            Some(path) => path,
+            None => {
+                let (path, capabilities) = resolve_detector_path_cached()?;
+                self.capabilities = capabilities;
+                path
+            }
+        };
+        self.detector_path = Some(path.clone());
+        Ok(path)
+    }
+
+    /// Whether the resolved detector has a capability; one that did not
+    /// report its capabilities is taken to have them all.
+    fn detector_supports(&self, capability: impl Fn(&DetectorCapabilities) -> bool) -> bool {
+        self.capabilities.as_ref().is_none_or(capability)
+    }
+
+    fn config_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "index_base": self.index_base,
+            "failure_threshold": self.breaker.failure_threshold,
+            "cooldown_ms": self.breaker.cooldown.as_millis() as u64,
+            "detector_path": self.detector_path,
+            "detector_capabilities": self.capabilities,
+            "paused": self.paused,
+            "pool_size": self.pool.size,
+            "binary_results": self.binary_results,
+            "in_process": self.in_process,
+            "detector_timeout_ms": parse_env("TABGROUP_DETECTOR_TIMEOUT_MS", DEFAULT_DETECTOR_TIMEOUT_MS),
+            "tuning": self.tuning.to_json(),
+        })
+    }
+
+    fn handle_message(&mut self, message: &Message) -> Message {
+        check_protocol(message);
+        self.dispatch(message)
+    }
+
+    /// Stamps an outgoing response, whatever produced it, with the next `seq`
+    /// and `captured_at_ms`: the capture time a hover result already carries,
+    /// otherwise `started`, when handling began (Unix milliseconds).
+    fn stamp(&mut self, mut response: Message, started: SystemTime) -> Message {
+        self.seq += 1;
+        if let Some(data) = response.data.as_object_mut() {
+            data.insert("seq".to_string(), serde_json::json!(self.seq));
+            data.entry("captured_at_ms")
+                .or_insert_with(|| serde_json::json!(unix_millis(started)));
+        }
+        response
+    }
+
+    fn dispatch(&mut self, message: &Message) -> Message {
+        match message.message_type.as_str() {
+            "check_hover" => self.handle_check_hover(&message.data),
+            "collect_diagnostics" => self.handle_collect_diagnostics(),
+            "get_version" => version_info(),
+            "layout_hash" => self.handle_layout_hash(),
+            "list_groups" => self.handle_list_groups(),
+            "pause" => self.set_paused(true),
+            "ping" => pong(),
+            "resume" => self.set_paused(false),
+            "set_config" => self.handle_set_config(&message.data),
+            _ => {
+                error!("Unknown message type: {}", message.message_type);
+                error_response(format!("Unknown message type: {}", message.message_type))
+            }
+        }
+    }
+
+    fn set_paused(&mut self, paused: bool) -> Message {
+        info!("Detection {}", if paused { "paused" } else { "resumed" });
+        self.paused = paused;
+        Message {
+            message_type: "pause_state".to_string(),
+            data: serde_json::json!({ "paused": paused }),
+        }
+    }
+
+    /// Answers `set_config` with `config_ack`, echoing the settings now in
+    /// effect after clamping.
+    fn handle_set_config(&mut self, data: &serde_json::Value) -> Message {
+        info!("Processing set_config request: {}", data);
+        self.tuning.update(data);
+        let envs = self.tuning.envs();
+        if self.in_process {
+            // The linked detector reads the host's own environment.
+            for (name, value) in &envs {
+                env::set_var(name, value);
+            }
+        }
+        // Cached scans were made with the old settings.
+        #[cfg(windows)]
+        {
+            self.detector_session = hover_detector::Session::new();
+        }
+        self.pool.set_envs(envs);
+        Message {
+            message_type: "config_ack".to_string(),
+            data: self.tuning.to_json(),
+        }
+    }
+
+    /// Answers `check_hover`. An optional `deadline_ms` in `data` bounds the
+    /// detector's run; it then may answer with `partial: true`. `verbose: true`
+    /// turns on detector logging and screenshots for this request only.
+    fn handle_check_hover(&mut self, data: &serde_json::Value) -> Message {
+        info!("Processing check_hover request");
+        if self.paused {
+            debug!("Detection paused, skipping hover check");
+            return Message {
+                message_type: "hover_result".to_string(),
+                data: hover_result_data(0, self.index_base, Some("Paused")),
+            };
+        }
+        if let Some(remaining) = self.breaker.blocked_for(Instant::now()) {
+            info!("Detector circuit open, skipping spawn for {:?}", remaining);
+            return Message {
+                message_type: "error".to_string(),
+                data: serde_json::json!({
+                    "code": "detector_unavailable",
+                    "message": "Hover detector failed repeatedly, waiting before retrying",
+                    "retry_after_ms": remaining.as_millis() as u64
+                }),
+            };
+        }
+        let deadline_ms = data["deadline_ms"].as_u64();
+        let verbose = data["verbose"].as_bool().unwrap_or(false);
+        let result = self.hover_route(verbose).and_then(|route| match route {
+            HoverRoute::InProcess => self.check_hovered_group_in_process(deadline_ms, verbose),
+            HoverRoute::Pool(path) => self.pool.check_hovered_group(&path, deadline_ms),
+            HoverRoute::Spawn(path) => {
+                let binary = self.binary_results && self.detector_supports(|found| found.binary);
+                check_hovered_group(&path, deadline_ms, binary, verbose, &self.tuning.envs())
+            }
+        });
+        match result {
+            Ok(answer) => {
+                info!("Hover check successful, index: {}", answer.index);
+                self.breaker.record_success();
+                let mut data = hover_result_data(answer.index, self.index_base, answer.reason);
+                data["partial"] = serde_json::json!(answer.partial);
+                if let Some(color) = answer.color {
+                    data["color"] = serde_json::json!(format!("#{:06X}", color));
+                }
+                if let Some(captured_at_ms) = answer.captured_at_ms {
+                    data["captured_at_ms"] = serde_json::json!(captured_at_ms);
+                }
+                Message {
+                    message_type: "hover_result".to_string(),
+                    data,
+                }
+            }
+            Err(e) => {
+                error!("Error checking hover: {}", e);
+                self.breaker.record_failure(Instant::now());
+                error_response(format!("Failed to check hover: {}", e))
+            }
+        }
+    }
+
+    /// Where a hover check runs. In process, a verbose request turns verbose
+    /// logging on for that one call. Pooled workers keep the environment they
+    /// started with, so there a verbose request gets a fresh process. Out of
+    /// process the detector path is resolved first, since that tells the
+    /// route what the detector supports.
+    fn hover_route(&mut self, verbose: bool) -> Result<HoverRoute> {
+        if self.in_process {
+            return Ok(HoverRoute::InProcess);
+        }
+        let path = self.detector_path()?;
+        Ok(if self.pool.enabled() && !verbose && self.detector_supports(|found| found.serve) {
+            HoverRoute::Pool(path)
+        } else {
+            HoverRoute::Spawn(path)
+        })
+    }
+
+    /// Runs a hover check inside the host through the detector library, which
+    /// saves the process start of every spawned check.
+    #[cfg(windows)]
+    fn check_hovered_group_in_process(&mut self, deadline_ms: Option<u64>, verbose: bool) -> Result<HoverAnswer> {
+        let result = self.detector_session.detect_hovered_group(&hover_detector::Config {
+            deadline_ms,
+            enumerate: false,
+            verbose,
+        })?;
+        let reason = DETECTOR_REASONS.get(result.reason.code() as usize).copied();
+        info!("In-process detection returned index: {} ({:?})", result.index, reason);
+        Ok(HoverAnswer {
+            index: result.index,
+            partial: result.partial,
+            reason,
+            color: result.cursor_color,
+            captured_at_ms: result.captured_at_ms,
+        })
+    }
+
+    #[cfg(not(windows))]
+    fn check_hovered_group_in_process(&mut self, _deadline_ms: Option<u64>, _verbose: bool) -> Result<HoverAnswer> {
+        anyhow::bail!("In-process detection is only available on Windows")
+    }
+
+    /// Answers `layout_hash` with just the hash of the current group layout,
+    /// so callers can tell whether groups changed without the full payload.
+    fn handle_layout_hash(&mut self) -> Message {
+        info!("Processing layout_hash request");
+        let detection = self
+            .detector_path()
+            .and_then(|path| run_detector_with_env(&path, &["--json"], &self.tuning.envs()))
+            .and_then(|stdout| {
+                serde_json::from_slice::<serde_json::Value>(&stdout)
+                    .context("Failed to parse detector JSON output")
+            });
+        match detection {
+            Ok(detection) => Message {
+                message_type: "layout_hash".to_string(),
+                data: serde_json::json!({
+                    "layout_hash": detection["layout_hash"],
+                    "group_count": detection["groups"].as_array().map_or(0, |groups| groups.len()),
+                }),
+            },
+            Err(e) => {
+                error!("Error computing layout hash: {}", e);
+                error_response(format!("Failed to compute layout hash: {}", e))
+            }
+        }
+    }
+
+    /// Answers `list_groups` with every group on the tab strip, hovered or
+    /// not, including positions and colors.
+    fn handle_list_groups(&mut self) -> Message {
+        info!("Processing list_groups request");
+        let detection = self
+            .detector_path()
+            .and_then(|path| run_detector_with_env(&path, &["--format", "json"], &self.tuning.envs()))
+            .and_then(|stdout| {
+                serde_json::from_slice::<serde_json::Value>(&stdout)
+                    .context("Failed to parse detector JSON output")
+            })
+            .and_then(|detection| match detection["status"].as_str() {
+                Some("error") => Err(anyhow::anyhow!(
+                    "Detector error: {}",
+                    detection["message"].as_str().unwrap_or("unknown")
+                )),
+                _ => Ok(detection),
+            });
+        match detection {
+            Ok(detection) => Message {
+                message_type: "groups_result".to_string(),
+                data: serde_json::json!({
+                    "status": detection["status"],
+                    "groups": detection["groups"],
+                    "window": detection["window"],
+                }),
+            },
+            Err(e) => {
+                error!("Error listing groups: {}", e);
+                error_response(format!("Failed to list groups: {}", e))
+            }
+        }
+    }
+
+    /// Bundles everything a bug report needs into one response: versions,
+    /// host and detector configuration, recent log lines, the detected groups
+    /// and an annotated capture of the tab strip.
+    fn handle_collect_diagnostics(&mut self) -> Message {
+        info!("Processing collect_diagnostics request");
+        let detector = self
+            .detector_path()
+            .and_then(|path| run_detector_with_env(&path, &["--diagnostics"], &self.tuning.envs()))
+            .and_then(|stdout| {
+                serde_json::from_slice::<serde_json::Value>(&stdout)
+                    .context("Failed to parse detector diagnostics")
+            });
+        let (detector, detector_error) = match detector {
+            Ok(value) => (value, None),
+            Err(e) => {
+                error!("Detector diagnostics failed: {}", e);
+                (serde_json::Value::Null, Some(e.to_string()))
+            }
+        };
+        let recent_logs: Vec<String> = self
+            .log_buffer
+            .lock()
+            .map(|lines| lines.iter().cloned().collect())
+            .unwrap_or_default();
+
+        let mut bundle = serde_json::json!({
+            "version": {
+                "host": env!("CARGO_PKG_VERSION"),
+                "detector": detector["version"],
+            },
+            "config": {
+                "host": self.config_json(),
+                "detector": detector["config"],
+            },
+            "logs": recent_logs,
+            "groups": detector["detection"]["groups"],
+            "detection": detector["detection"],
+            "capture_png_base64": detector["capture_png_base64"],
+            "detector_error": detector_error,
+        });
+
+        // Stay under the native messaging limit; the capture is the only bulky part.
+        let size = serde_json::to_vec(&bundle).map(|v| v.len()).unwrap_or(0);
+        if size > MAX_OUTGOING_MESSAGE_BYTES {
+            info!("Diagnostics bundle is {} bytes, dropping capture", size);
+            bundle["capture_png_base64"] = serde_json::Value::Null;
+            bundle["capture_omitted"] = serde_json::json!("exceeds native messaging size limit");
+        }
+
+        Message {
+            message_type: "diagnostics_result".to_string(),
+            data: bundle,
+        }
+    }
+}
+
+/// Whether the startup dump includes the variable `key`: everything with
+/// `TABGROUP_LOG_ENV`, otherwise only the variables that affect the host.
+fn env_var_logged(key: &str, log_all_env: bool) -> bool {
+    log_all_env || key.starts_with("TABGROUP_") || key == "RUST_LOG" || key.eq_ignore_ascii_case("PATH")
 }
 
 fn main() -> Result<()> {
     // Set up logging before anything else
-    setup_logging()?;
+    let log_buffer = setup_logging()?;
     
     info!("Native messaging host started");
     info!("Process ID: {}", std::process::id());
     info!("Current directory: {:?}", env::current_dir()?);
     
-    // Log all environment variables for debugging
-    info!("Environment variables:");
+    // The full environment may hold secrets, so it is only dumped on request;
+    // by default just the variables that affect the host are logged.
+    let log_all_env = env::var("TABGROUP_LOG_ENV").is_ok_and(|raw| matches!(raw.trim(), "1" | "true"));
+    info!("Environment variables{}:", if log_all_env { "" } else { " (TABGROUP_*, RUST_LOG, PATH)" });
     for (key, value) in env::vars() {
-        info!("{}: {}", key, value);
+        if env_var_logged(&key, log_all_env) {
+            info!("{}: {}", key, value);
+        }
     }
 
     let stdin = io::stdin();
@@ -191,51 +1594,623 @@ fn main() -> Result<()> {
     let mut reader = stdin.lock();
     let mut writer = stdout.lock();
 
+    let mut state = HostState::new(log_buffer);
+    info!("Reporting hover indices with base {}", state.index_base);
+    info!(
+        "Detector circuit breaker: threshold={}, cooldown={:?}",
+        state.breaker.failure_threshold, state.breaker.cooldown
+    );
+    #[cfg(windows)]
+    if state.in_process {
+        // The detector library expects the same DPI awareness as its executable.
+        if let Err(e) = hover_detector::enable_dpi_awareness() {
+            error!("{}", e);
+        }
+    }
+    info!("Hover checks run {}", if state.in_process { "in process" } else { "in a detector process" });
+
+    let order = parse_env("TABGROUP_FRAME_BYTE_ORDER", ByteOrder::Native);
+    info!("Message length prefixes use {:?} byte order", order);
+
     info!("Starting message processing loop");
+    serve_messages(&mut reader, &mut writer, &mut state, order)?;
 
-    // Process messages from the extension
-    while let Some(message) = read_message(&mut reader)? {
-        info!("Processing message: {:?}", message);
+    info!("Native messaging host shutting down");
+    Ok(())
+}
 
-        match message.message_type.as_str() {
-            "check_hover" => {
-                info!("Processing check_hover request");
-                match check_hovered_group() {
-                    Ok(index) => {
-                        info!("Hover check successful, index: {}", index);
-                        let response = Message {
-                            message_type: "hover_result".to_string(),
-                            data: serde_json::json!({ 
-                                "index": index 
-                            }),
-                        };
-                        write_message(&mut writer, &response)?;
-                    }
-                    Err(e) => {
-                        error!("Error checking hover: {}", e);
-                        let response = Message {
-                            message_type: "error".to_string(),
-                            data: serde_json::json!({ 
-                                "message": format!("Failed to check hover: {}", e)
-                            }),
-                        };
-                        write_message(&mut writer, &response)?;
-                    }
-                }
+/// How one hover check is answered, see [`HostState::hover_route`].
+#[derive(Debug, PartialEq)]
+enum HoverRoute {
+    InProcess,
+    Pool(PathBuf),
+    Spawn(PathBuf),
+}
+
+/// Answers messages from `reader` on `writer` until the input ends or gets
+/// out of sync. Every response goes through [`HostState::stamp`].
+fn serve_messages<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    state: &mut HostState,
+    order: ByteOrder,
+) -> Result<()> {
+    loop {
+        let read = read_message(&mut reader, order);
+        let started = SystemTime::now();
+        let response = match read {
+            Ok(Some(message)) => {
+                info!("Processing message: {:?}", message);
+                state.handle_message(&message)
             }
-            _ => {
-                error!("Unknown message type: {}", message.message_type);
-                let response = Message {
-                    message_type: "error".to_string(),
-                    data: serde_json::json!({ 
-                        "message": format!("Unknown message type: {}", message.message_type)
-                    }),
-                };
-                write_message(&mut writer, &response)?;
+            Ok(None) => return Ok(()),
+            Err(e) => match e.downcast_ref::<RejectedMessage>() {
+                Some(rejected) => error_response(rejected.to_string()),
+                None => return Err(e),
+            },
+        };
+        write_message(&mut writer, &state.stamp(response, started), order)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `json` framed with a little-endian length prefix.
+    fn frame(json: &str) -> Vec<u8> {
+        let mut bytes = (json.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(json.as_bytes());
+        bytes
+    }
+
+    /// Runs the message loop over `input` and returns the responses.
+    fn serve(input: Vec<u8>) -> Vec<Message> {
+        let mut state = HostState::new(LogBuffer::default());
+        let mut output = Vec::new();
+        serve_messages(io::Cursor::new(input), &mut output, &mut state, ByteOrder::Little).unwrap();
+        let mut responses = Vec::new();
+        let mut output = io::Cursor::new(output);
+        while let Some(response) = read_message(&mut output, ByteOrder::Little).unwrap() {
+            responses.push(response);
+        }
+        responses
+    }
+
+    #[test]
+    fn breaker_opens_at_the_threshold_and_probes_after_the_cooldown() {
+        let cooldown = Duration::from_secs(10);
+        let mut breaker = CircuitBreaker::new(3, cooldown);
+        let start = Instant::now();
+
+        breaker.record_failure(start);
+        breaker.record_failure(start);
+        assert_eq!(breaker.blocked_for(start), None);
+        breaker.record_failure(start);
+        assert_eq!(breaker.blocked_for(start), Some(cooldown));
+        assert_eq!(breaker.blocked_for(start + Duration::from_secs(4)), Some(Duration::from_secs(6)));
+
+        // Half-open: the cooldown has passed and one probe may run...
+        let later = start + cooldown;
+        assert_eq!(breaker.blocked_for(later), None);
+        // ...and a failed probe opens the breaker again straight away.
+        breaker.record_failure(later);
+        assert_eq!(breaker.blocked_for(later), Some(cooldown));
+
+        // A successful probe closes it and resets the failure count.
+        let after = later + cooldown;
+        breaker.record_success();
+        assert_eq!(breaker.blocked_for(after), None);
+        breaker.record_failure(after);
+        breaker.record_failure(after);
+        assert_eq!(breaker.blocked_for(after), None);
+    }
+
+    #[test]
+    fn breaker_threshold_is_at_least_one() {
+        let mut breaker = CircuitBreaker::new(0, Duration::from_secs(1));
+        let now = Instant::now();
+        breaker.record_failure(now);
+        assert!(breaker.blocked_for(now).is_some());
+    }
+
+    #[test]
+    fn hit_in_the_second_group_reports_both_index_bases() {
+        let one_based = hover_result_data(2, 1, None);
+        assert_eq!((one_based["index"].as_i64(), one_based["ordinal_zero_based"].as_u64()), (Some(2), Some(1)));
+        let zero_based = hover_result_data(2, 0, None);
+        assert_eq!((zero_based["index"].as_i64(), zero_based["ordinal_zero_based"].as_u64()), (Some(1), Some(1)));
+        assert_eq!(zero_based["index_base"], 0);
+
+        assert_eq!(hover_result_data(0, 1, None)["index"], 0);
+        assert_eq!(hover_result_data(0, 0, None)["index"], -1);
+        assert!(hover_result_data(0, 0, None)["ordinal_zero_based"].is_null());
+    }
+
+    /// An executable shell script standing in for the detector, in a fresh
+    /// temp directory.
+    #[cfg(unix)]
+    fn fake_detector(name: &str, script: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = env::temp_dir().join(format!("tabgroup-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hover-detector");
+        fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn diagnostics_bundle_has_every_section() {
+        let report = r#"{"version":"0.1.0","config":{"mode":"fill"},"detection":{"index":2,"groups":[{"start":40,"end":160},{"start":220,"end":360}]},"capture_png_base64":"iVBORw0KGgo="}"#;
+        let detector = fake_detector(
+            "diagnostics",
+            &format!("[ \"$1\" = --diagnostics ] || exit 1\nprintf '%s' '{}'", report),
+        );
+        let log_buffer = LogBuffer::default();
+        log_buffer.lock().unwrap().push_back("Processing check_hover request".to_string());
+        let mut state = HostState::new(log_buffer);
+        state.detector_path = Some(detector.clone());
+
+        let reply = state.handle_collect_diagnostics();
+        assert_eq!(reply.message_type, "diagnostics_result");
+        let bundle = &reply.data;
+        for section in ["version", "config", "logs", "groups", "detection", "capture_png_base64"] {
+            assert!(!bundle[section].is_null(), "missing {}: {}", section, bundle);
+        }
+        assert_eq!(bundle["version"]["detector"], "0.1.0");
+        assert_eq!(bundle["version"]["host"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(bundle["config"]["host"]["detector_path"], detector.to_str().unwrap());
+        assert_eq!(bundle["config"]["detector"]["mode"], "fill");
+        assert_eq!(bundle["logs"], serde_json::json!(["Processing check_hover request"]));
+        assert_eq!(bundle["groups"].as_array().map(Vec::len), Some(2));
+        assert_eq!(bundle["detection"]["index"], 2);
+        assert_eq!(bundle["capture_png_base64"], "iVBORw0KGgo=");
+        assert!(bundle["detector_error"].is_null());
+    }
+
+    fn message(message_type: &str) -> Message {
+        Message { message_type: message_type.to_string(), data: serde_json::json!({}) }
+    }
+
+    #[test]
+    fn paused_checks_skip_detection_until_resumed() {
+        let mut state = HostState::new(LogBuffer::default());
+        state.in_process = false;
+        #[cfg(unix)]
+        let marker = {
+            let detector = fake_detector("paused", "touch \"$(dirname \"$0\")/ran\"\nprintf '2\\nreason=0\\n'");
+            let marker = detector.with_file_name("ran");
+            let _ = fs::remove_file(&marker);
+            state.detector_path = Some(detector);
+            marker
+        };
+
+        assert_eq!(state.handle_message(&message("pause")).data["paused"], true);
+        let paused = state.handle_message(&message("check_hover"));
+        assert_eq!(paused.message_type, "hover_result");
+        assert_eq!((paused.data["index"].as_i64(), paused.data["reason"].as_str()), (Some(0), Some("Paused")));
+        #[cfg(unix)]
+        assert!(!marker.exists(), "no detector run while paused");
+
+        assert_eq!(state.handle_message(&message("resume")).data["paused"], false);
+        #[cfg(unix)]
+        {
+            let resumed = state.handle_message(&message("check_hover"));
+            assert_eq!(resumed.message_type, "hover_result");
+            assert_eq!((resumed.data["index"].as_i64(), resumed.data["reason"].as_str()), (Some(2), Some("Hit")));
+            assert!(marker.exists());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pool_serves_from_idle_workers_and_replaces_dead_ones() {
+        let detector = fake_detector(
+            "pool",
+            "echo $$ >> \"$(dirname \"$0\")/spawned\"\nwhile read deadline; do echo '2 reason=0'; done",
+        );
+        let spawned_file = detector.with_file_name("spawned");
+        let _ = fs::remove_file(&spawned_file);
+        let spawned = || fs::read_to_string(&spawned_file).map_or(0, |pids| pids.lines().count());
+
+        let mut pool = DetectorPool::new(1);
+        for _ in 0..3 {
+            let answer = pool.check_hovered_group(&detector, None).unwrap();
+            assert_eq!((answer.index, answer.reason), (2, Some("Hit")));
+        }
+        assert_eq!(spawned(), 1, "every check went to the one pooled worker");
+        assert_eq!(pool.idle.len(), 1);
+
+        let worker = &mut pool.idle[0];
+        worker.child.kill().unwrap();
+        worker.child.wait().unwrap();
+        let answer = pool.check_hovered_group(&detector, Some(50)).unwrap();
+        assert_eq!(answer.index, 2);
+        assert_eq!(spawned(), 2, "the dead worker was replaced");
+        assert!(pool.idle[0].is_alive());
+    }
+
+    #[test]
+    fn binary_result_round_trips_the_hover_fields() {
+        // The detector's `binary_result_layout` test encodes these payloads.
+        let framed = |payload: [u8; 9]| [&9u32.to_le_bytes()[..], &payload].concat();
+        let hit = decode_binary_result(&framed([2, 0, 0, 0, 0, 0xB7, 0x6A, 0xE0, 0])).unwrap();
+        assert_eq!((hit.index, hit.reason, hit.color, hit.partial), (2, Some("Hit"), Some(0xE06AB7), false));
+        let miss = decode_binary_result(&framed([0, 0, 0, 0, 4, 0xFF, 0xFF, 0xFF, 0xFF])).unwrap();
+        assert_eq!((miss.index, miss.reason, miss.color), (0, Some("NotOnGroup"), None));
+        let rushed = decode_binary_result(&framed([1, 0, 0, 0, 7, 0xFF, 0xFF, 0xFF, 0xFF])).unwrap();
+        assert_eq!((rushed.reason, rushed.partial), (Some("DeadlineExceeded"), true));
+
+        assert!(decode_binary_result(&framed([0, 0, 0, 0, 200, 0, 0, 0, 0])).is_err());
+        assert!(decode_binary_result(&[9, 0, 0, 0, 2, 0]).is_err());
+        assert!(decode_binary_result(&[8, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn get_version_reports_the_versions_and_mismatches_are_flagged() {
+        let version = HostState::new(LogBuffer::default()).handle_message(&message("get_version"));
+        assert_eq!(version.message_type, "version");
+        assert_eq!(version.data["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(version.data["protocol"], PROTOCOL_VERSION);
+
+        let with_protocol = |protocol: serde_json::Value| Message {
+            message_type: "check_hover".to_string(),
+            data: serde_json::json!({ "protocol": protocol }),
+        };
+        assert_eq!(protocol_mismatch(&with_protocol(PROTOCOL_VERSION.into())), None);
+        assert_eq!(protocol_mismatch(&message("check_hover")), None);
+        let warning = protocol_mismatch(&with_protocol((PROTOCOL_VERSION + 1).into())).unwrap();
+        let expected = format!("'check_hover' message uses protocol {}, this host speaks {}", PROTOCOL_VERSION + 1, PROTOCOL_VERSION);
+        assert!(warning.starts_with(&expected), "{}", warning);
+    }
+
+    #[test]
+    fn messages_round_trip_in_both_byte_orders() {
+        let message = Message {
+            message_type: "hoverResult".to_string(),
+            data: serde_json::json!({ "index": 2, "reason": "hit" }),
+        };
+        let length = serde_json::to_vec(&message).unwrap().len() as u32;
+        for (order, prefix) in [
+            (ByteOrder::Little, length.to_le_bytes()),
+            (ByteOrder::Big, length.to_be_bytes()),
+            (ByteOrder::Native, length.to_ne_bytes()),
+        ] {
+            let mut stream = io::Cursor::new(Vec::new());
+            write_message(&mut stream, &message, order).unwrap();
+            assert_eq!(stream.get_ref()[..4], prefix, "{:?} prefix", order);
+
+            stream.set_position(0);
+            let read = read_message(&mut stream, order).unwrap().expect("a message");
+            assert_eq!(read.message_type, message.message_type);
+            assert_eq!(read.data, message.data);
+            assert!(read_message(&mut stream, order).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn big_endian_prefix_is_misread_as_little_endian() {
+        // A 25-byte frame in big-endian order reads as 25 << 24 bytes
+        // little-endian, past the size limit, so the mismatch is caught.
+        let ping = Message { message_type: "ping".to_string(), data: serde_json::json!({}) };
+        let mut stream = io::Cursor::new(Vec::new());
+        write_message(&mut stream, &ping, ByteOrder::Big).unwrap();
+        assert_eq!(stream.get_ref()[..4], [0, 0, 0, 25]);
+        stream.set_position(0);
+        let error = read_message(&mut stream, ByteOrder::Little).unwrap_err();
+        assert!(error.downcast_ref::<RejectedMessage>().is_some());
+    }
+
+    #[test]
+    fn zero_length_frame_is_an_error_and_the_next_frame_is_read() {
+        let ping = Message { message_type: "ping".to_string(), data: serde_json::json!({}) };
+        let mut stream = io::Cursor::new(vec![0, 0, 0, 0]);
+        stream.set_position(4);
+        write_message(&mut stream, &ping, ByteOrder::Little).unwrap();
+        stream.set_position(0);
+        let error = read_message(&mut stream, ByteOrder::Little).unwrap_err();
+        let rejected = error.downcast_ref::<RejectedMessage>().expect("a rejected message");
+        assert_eq!(rejected.to_string(), "Empty message (zero length prefix)");
+        let next = read_message(&mut stream, ByteOrder::Little).unwrap().expect("a message");
+        assert_eq!(next.message_type, "ping");
+    }
+
+    #[test]
+    fn log_past_its_size_limit_rotates_keeping_the_newest_files() {
+        let dir = env::temp_dir().join(format!("tabgroup-log-rotation-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("native_host.log");
+        let read = |name: &str| fs::read_to_string(dir.join(name)).ok();
+
+        let mut log = RotatingFile::open(path.clone(), 10, 2).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            log.write_all(line.as_bytes()).unwrap();
+        }
+        log.flush().unwrap();
+        // Each line pushes the file past 10 bytes, so every write rotates and
+        // the oldest line falls off after two rotated files.
+        assert_eq!(read("native_host.log").as_deref(), Some("fourth\n"));
+        assert_eq!(read("native_host.log.1").as_deref(), Some("third\n"));
+        assert_eq!(read("native_host.log.2").as_deref(), Some("second\n"));
+        assert_eq!(read("native_host.log.3"), None);
+
+        // Reopening picks up the existing size instead of starting from zero.
+        let mut log = RotatingFile::open(path.clone(), 10, 2).unwrap();
+        log.write_all(b"fifth\n").unwrap();
+        log.flush().unwrap();
+        assert_eq!(read("native_host.log").as_deref(), Some("fifth\n"));
+        assert_eq!(read("native_host.log.1").as_deref(), Some("fourth\n"));
+
+        // A limit of 0 never rotates.
+        let mut log = RotatingFile::open(path, 0, 2).unwrap();
+        log.write_all(b"sixth line, longer than the old limit\n").unwrap();
+        log.flush().unwrap();
+        assert_eq!(read("native_host.log").as_deref(), Some("fifth\nsixth line, longer than the old limit\n"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn startup_env_dump_is_limited_to_the_hosts_variables() {
+        for key in ["TABGROUP_LOG_KEEP", "RUST_LOG", "PATH", "Path"] {
+            assert!(env_var_logged(key, false), "{}", key);
+        }
+        for key in ["GITHUB_TOKEN", "AWS_SECRET_ACCESS_KEY", "USERPROFILE", "tabgroup_lowercase"] {
+            assert!(!env_var_logged(key, false), "{}", key);
+            assert!(env_var_logged(key, true), "{}", key);
+        }
+    }
+
+    #[test]
+    fn log_path_defaults_next_to_the_executable_and_falls_back_to_stderr() {
+        let exe = || Ok(PathBuf::from("/opt/tabgroup/native-host"));
+        assert_eq!(resolve_log_path(None, exe).unwrap(), PathBuf::from("/opt/tabgroup/native_host.log"));
+        assert_eq!(resolve_log_path(Some("".into()), exe).unwrap(), PathBuf::from("/opt/tabgroup/native_host.log"));
+        assert_eq!(
+            resolve_log_path(Some("/var/log/tabgroup.log".into()), || panic!("executable looked up")).unwrap(),
+            PathBuf::from("/var/log/tabgroup.log")
+        );
+
+        // A directory cannot be opened as the log; the writer then still
+        // mirrors lines into the buffer.
+        assert!(RotatingFile::open(env::temp_dir(), DEFAULT_LOG_MAX_BYTES, DEFAULT_LOG_KEEP).is_err());
+        let buffer = LogBuffer::default();
+        let mut writer = TeeWriter { file: None, buffer: buffer.clone(), partial_line: Vec::new() };
+        writer.write_all(b"logged without a file\n").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(buffer.lock().unwrap().back().map(String::as_str), Some("logged without a file"));
+    }
+
+    #[test]
+    fn json_log_line_holds_the_timestamp_level_and_message() {
+        let line = json_log_line(
+            "2026-10-14T15:37:00Z",
+            &log::Record::builder()
+                .level(log::Level::Warn)
+                .target("native_host")
+                .args(format_args!("Received message: {}", "check_hover"))
+                .build(),
+        );
+        assert_eq!(
+            line,
+            serde_json::json!({
+                "timestamp": "2026-10-14T15:37:00Z",
+                "level": "WARN",
+                "target": "native_host",
+                "message": "Received message: check_hover",
+            })
+        );
+        // A message with quotes and a newline stays one valid line.
+        let line = json_log_line("t", &log::Record::builder().args(format_args!("say \"hi\"\nthere")).build()).to_string();
+        assert!(!line.contains('\n'), "{}", line);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&line).unwrap()["message"], "say \"hi\"\nthere");
+    }
+
+    #[test]
+    fn oversized_message_is_skipped_and_the_next_frame_is_handled() {
+        let length = MAX_INCOMING_MESSAGE_BYTES + 1;
+        let mut input = length.to_le_bytes().to_vec();
+        input.resize(4 + length as usize, b' ');
+        input.extend(frame(r#"{"type":"ping","data":{}}"#));
+        let responses = serve(input);
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].message_type, "error");
+        let expected = format!("Message of {} bytes exceeds the {} byte limit", length, MAX_INCOMING_MESSAGE_BYTES);
+        assert_eq!(responses[0].data["message"], expected);
+        assert_eq!(responses[1].message_type, "pong");
+    }
+
+    #[test]
+    fn plain_output_reason_wins_over_the_exit_code() {
+        let answer = interpret_plain_output(Some(12), "0\npartial\nreason=12").unwrap();
+        assert_eq!(
+            answer,
+            HoverAnswer {
+                index: 0,
+                partial: true,
+                reason: Some("StripOccluded"),
+                color: None,
+                captured_at_ms: None,
             }
+        );
+        let answer = interpret_plain_output(Some(12), "0\nreason=10").unwrap();
+        assert_eq!(answer.reason, Some("WindowInMotion"));
+        let answer = interpret_plain_output(Some(0), "3\nreason=0").unwrap();
+        assert_eq!((answer.index, answer.reason), (3, Some("Hit")));
+    }
+
+    #[test]
+    fn plain_output_without_a_reason_falls_back_to_the_exit_code() {
+        let answer = interpret_plain_output(Some(11), "0").unwrap();
+        assert_eq!(answer.reason, Some("CursorOutside"));
+        let answer = interpret_plain_output(Some(10), "").unwrap();
+        assert_eq!((answer.index, answer.reason), (0, Some("NotBrowser")));
+        assert_eq!(interpret_plain_output(Some(0), "2").unwrap().reason, None);
+        assert!(interpret_plain_output(Some(0), "").is_err());
+        assert!(interpret_plain_output(Some(0), "1\nreason=99").is_err());
+    }
+
+    /// A pipe that never produces a byte, like a detector stuck in a capture.
+    struct StalledPipe(Mutex<Receiver<()>>);
+
+    impl Read for StalledPipe {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            let _ = self.0.lock().unwrap().recv();
+            Ok(0)
         }
     }
 
-    info!("Native messaging host shutting down");
-    Ok(())
+    #[test]
+    fn pooled_answer_wait_times_out() {
+        let (_keep_open, never) = mpsc::channel();
+        let lines = spawn_line_reader(StalledPipe(Mutex::new(never)));
+        let started = Instant::now();
+        assert_eq!(recv_line(&lines, Duration::from_millis(50)), Err(RecvTimeoutError::Timeout));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn pooled_answer_lines_arrive_until_the_pipe_closes() {
+        let lines = spawn_line_reader(io::Cursor::new(b"3\n0 partial\n".to_vec()));
+        assert_eq!(recv_line(&lines, Duration::from_secs(5)).as_deref(), Ok("3\n"));
+        assert_eq!(recv_line(&lines, Duration::ZERO).as_deref(), Ok("0 partial\n"));
+        assert_eq!(recv_line(&lines, Duration::from_secs(5)), Err(RecvTimeoutError::Disconnected));
+    }
+
+    #[test]
+    fn tuning_clamps_the_tolerance_to_a_channel() {
+        let mut tuning = DetectorTuning::default();
+        tuning.update(&serde_json::json!({ "tolerance": 1000 }));
+        assert_eq!(tuning.color_tolerance, Some(255));
+        tuning.update(&serde_json::json!({ "tolerance": 8 }));
+        assert_eq!(tuning.envs(), vec![("TABGROUP_HOVER_COLOR_TOLERANCE", "8".to_string())]);
+    }
+
+    #[test]
+    fn every_response_gets_a_strictly_increasing_seq() {
+        let mut input = frame(r#"{"type":"ping","data":{}}"#);
+        input.extend(0u32.to_le_bytes());
+        input.extend(frame("not json"));
+        input.extend(frame(r#"{"type":"no_such_message","data":{}}"#));
+        input.extend(frame(r#"{"type":"ping","data":{}}"#));
+        let responses = serve(input);
+        let types: Vec<&str> = responses.iter().map(|r| r.message_type.as_str()).collect();
+        assert_eq!(types, ["pong", "error", "error", "error", "pong"]);
+        let seqs: Vec<u64> = responses.iter().map(|r| r.data["seq"].as_u64().unwrap()).collect();
+        assert!(seqs.windows(2).all(|pair| pair[1] > pair[0]), "{:?}", seqs);
+        assert!(responses.iter().all(|r| r.data["captured_at_ms"].is_u64()));
+    }
+
+    #[test]
+    fn stamp_keeps_the_capture_time_of_a_hover_result() {
+        let mut state = HostState::new(LogBuffer::default());
+        let started = SystemTime::UNIX_EPOCH + Duration::from_millis(2_000);
+        let hover = Message {
+            message_type: "hover_result".to_string(),
+            data: serde_json::json!({ "index": 1, "captured_at_ms": 1_500 }),
+        };
+        let stamped = state.stamp(hover, started);
+        assert_eq!(stamped.data["captured_at_ms"], 1_500);
+        assert_eq!(stamped.data["seq"], 1);
+        let stamped = state.stamp(pong(), started);
+        assert_eq!(stamped.data["captured_at_ms"], 2_000);
+        assert_eq!(stamped.data["seq"], 2);
+    }
+
+    #[test]
+    fn plain_output_carries_the_capture_time() {
+        let answer = parse_detector_output("2\nreason=0\ncaptured_at_ms=1700000000123").unwrap();
+        assert_eq!(answer.captured_at_ms, Some(1_700_000_000_123));
+        assert_eq!(parse_detector_output("2 partial").unwrap().captured_at_ms, None);
+    }
+
+    #[test]
+    fn serve_line_carries_the_reason() {
+        // The line the detector's `serve_line_carries_the_reason_code` test pins.
+        let answer = parse_detector_output("0 reason=4").unwrap();
+        assert_eq!((answer.index, answer.reason), (0, Some("NotOnGroup")));
+        let answer = parse_detector_output("2 partial reason=7 captured_at_ms=1700000000123").unwrap();
+        assert_eq!((answer.index, answer.partial), (2, true));
+        assert_eq!(answer.reason, Some("DeadlineExceeded"));
+        assert_eq!(answer.captured_at_ms, Some(1_700_000_000_123));
+    }
+
+    #[test]
+    fn verbose_checks_stay_in_process() {
+        let mut state = HostState::new(LogBuffer::default());
+        state.in_process = true;
+        assert_eq!(state.hover_route(false).unwrap(), HoverRoute::InProcess);
+        assert_eq!(state.hover_route(true).unwrap(), HoverRoute::InProcess);
+
+        // Any existing file stands in for the detector, so nothing is probed.
+        let detector = env::current_exe().unwrap();
+        state.detector_path = Some(detector.clone());
+        state.in_process = false;
+        state.pool = DetectorPool::new(2);
+        assert_eq!(state.hover_route(false).unwrap(), HoverRoute::Pool(detector.clone()));
+        assert_eq!(state.hover_route(true).unwrap(), HoverRoute::Spawn(detector.clone()));
+        state.pool = DetectorPool::new(0);
+        assert_eq!(state.hover_route(false).unwrap(), HoverRoute::Spawn(detector));
+    }
+
+    #[test]
+    fn changed_detector_hash_invalidates_the_cache_and_probes_again() {
+        let dir = env::temp_dir().join(format!("tabgroup-detector-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let detector = dir.join("hover-detector.exe");
+        let cache_file = dir.join(DETECTOR_CACHE_FILE);
+        let _ = fs::remove_file(&cache_file);
+        fs::write(&detector, b"detector v1").unwrap();
+
+        let capabilities = |version: &str| DetectorCapabilities {
+            version: version.to_string(),
+            binary: true,
+            serve: false,
+        };
+        let probes = std::cell::Cell::new(0);
+        let lookup = |version: &str| {
+            cached_detector(
+                &cache_file,
+                || Ok(detector.clone()),
+                |_| {
+                    probes.set(probes.get() + 1);
+                    Some(capabilities(version))
+                },
+            )
+            .unwrap()
+        };
+
+        assert_eq!(lookup("1.0"), (detector.clone(), Some(capabilities("1.0"))));
+        assert_eq!(probes.get(), 1);
+        // Same binary: path and capabilities come from the cache.
+        assert_eq!(lookup("unused"), (detector.clone(), Some(capabilities("1.0"))));
+        assert_eq!(probes.get(), 1);
+
+        fs::write(&detector, b"detector v2, rebuilt").unwrap();
+        assert_eq!(lookup("2.0"), (detector.clone(), Some(capabilities("2.0"))));
+        assert_eq!(probes.get(), 2);
+        let entry = DetectorCacheEntry::load(&cache_file).unwrap();
+        assert_eq!(entry.hash, hash_file(&detector).unwrap());
+        assert_eq!(lookup("unused").1, Some(capabilities("2.0")));
+        assert_eq!(probes.get(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn routes_follow_the_detector_capabilities() {
+        let mut state = HostState::new(LogBuffer::default());
+        let detector = env::current_exe().unwrap();
+        state.detector_path = Some(detector.clone());
+        state.in_process = false;
+        state.pool = DetectorPool::new(2);
+        state.capabilities = Some(DetectorCapabilities { version: "0.1.0".to_string(), binary: false, serve: false });
+        assert_eq!(state.hover_route(false).unwrap(), HoverRoute::Spawn(detector.clone()));
+        assert!(!state.detector_supports(|found| found.binary));
+        state.capabilities = None;
+        assert_eq!(state.hover_route(false).unwrap(), HoverRoute::Pool(detector));
+        assert!(state.detector_supports(|found| found.binary));
+    }
 }